@@ -0,0 +1,7 @@
+#!/usr/bin/env bash
+# Build the JavaScript bindings into wasm-demo/pkg, ready to be served with wasm-demo/index.html
+# Needs wasm-pack (https://rustwasm.github.io/wasm-pack/) and the wasm32-unknown-unknown target
+set -euo pipefail
+
+cd "$(dirname "$0")"
+wasm-pack build --target web --release --out-dir wasm-demo/pkg --out-name ast_calc -- --features wasm
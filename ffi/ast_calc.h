@@ -0,0 +1,39 @@
+/* C interface to the ast_calc library, built as libast_calc by `cargo build --release` */
+#ifndef AST_CALC_H
+#define AST_CALC_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Status codes returned by the functions below */
+#define AST_CALC_OK 0
+#define AST_CALC_INVALID_INPUT 1 /* A required pointer was null or a string was not UTF-8 */
+#define AST_CALC_PARSE_ERROR 2
+#define AST_CALC_EVAL_ERROR 3
+#define AST_CALC_INVALID_VARS 4  /* vars_json was not a flat JSON object of numbers */
+
+/* A parsed expression, owned by the library */
+typedef struct AstCalcAst AstCalcAst;
+
+/* Parse and evaluate expr, writing its value to result */
+int ast_calc_eval(const char *expr, double *result);
+
+/* Like ast_calc_eval, with variables taken from a JSON object such as {"x": 1, "y": 2.5}
+ * vars_json may be NULL when the expression has no variables */
+int ast_calc_eval_with_vars(const char *expr, const char *vars_json, double *result);
+
+/* Parse expr once into a handle that can be evaluated many times */
+int ast_calc_parse(const char *expr, AstCalcAst **out_handle);
+
+/* Evaluate a handle from ast_calc_parse, where vars_json may be NULL */
+int ast_calc_eval_handle(const AstCalcAst *handle, const char *vars_json, double *result);
+
+/* Release a handle from ast_calc_parse, which does nothing for NULL */
+void ast_calc_free_handle(AstCalcAst *handle);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
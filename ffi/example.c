@@ -0,0 +1,30 @@
+/* Build the library with `cargo build --release`, then from the repo root:
+ *   cc ffi/example.c -Iffi -Ltarget/release -last_calc -o example
+ *   LD_LIBRARY_PATH=target/release ./example
+ */
+#include <stdio.h>
+#include "ast_calc.h"
+
+int main(void) {
+    double result;
+    if (ast_calc_eval("sin(4) + exp(3 - 1)^3", &result) == AST_CALC_OK) {
+        printf("sin(4) + exp(3 - 1)^3 = %f\n", result);
+    }
+
+    if (ast_calc_eval("1 +", &result) == AST_CALC_PARSE_ERROR) {
+        printf("1 + does not parse\n");
+    }
+
+    AstCalcAst *square;
+    if (ast_calc_parse("x^2 + 1", &square) != AST_CALC_OK) {
+        return 1;
+    }
+    const char *points[] = {"{\"x\": 0}", "{\"x\": 1.5}", "{\"x\": 3}"};
+    for (int i = 0; i < 3; i++) {
+        if (ast_calc_eval_handle(square, points[i], &result) == AST_CALC_OK) {
+            printf("x^2 + 1 at %s = %f\n", points[i], result);
+        }
+    }
+    ast_calc_free_handle(square);
+    return 0;
+}
@@ -0,0 +1,37 @@
+use ast_calc::ast::Ast;
+use proc_macro::TokenStream;
+use proc_macro2::Literal;
+use quote::quote;
+use syn::{parse_macro_input, LitStr};
+
+/// Evaluate an expression at compile time, expanding to an `f64` literal usable in constants
+///
+/// ```
+/// use ast_calc_macros::const_eval;
+///
+/// const KIB: f64 = const_eval!("2^10");
+/// assert_eq!(KIB, 1024.0);
+/// ```
+///
+/// Expressions that cannot be evaluated, such as ones with variables, fail to compile
+///
+/// ```compile_fail
+/// use ast_calc_macros::const_eval;
+///
+/// const Y: f64 = const_eval!("x + 1");
+/// ```
+#[proc_macro]
+pub fn const_eval(input: TokenStream) -> TokenStream {
+    let s = parse_macro_input!(input as LitStr);
+    let result = Ast::try_from_str(&s.value())
+        .map_err(|e| format!("Could not parse {:?}: {}", s.value(), e))
+        .and_then(|ast| ast.try_eval().map_err(|e| format!("Could not evaluate {:?}: {}", s.value(), e)))
+        .and_then(|val| if val.is_finite() { Ok(val) } else { Err(format!("{:?} evaluates to {}, which has no literal", s.value(), val)) });
+    match result {
+        Ok(val) => {
+            let lit = Literal::f64_suffixed(val);
+            quote!(#lit).into()
+        },
+        Err(e) => syn::Error::new(s.span(), e).to_compile_error().into()
+    }
+}
@@ -0,0 +1,13 @@
+use ast_calc_macros::const_eval;
+
+const KIB: f64 = const_eval!("2^10");
+const HALF: f64 = const_eval!("sin(pi / 6)");
+const NEGATIVE: f64 = const_eval!("1 - 3!");
+
+#[test]
+fn evaluated_at_compile_time() {
+    assert_eq!(KIB, 1024.0);
+    assert!((HALF - 0.5).abs() < 1e-15);
+    assert_eq!(NEGATIVE, -5.0);
+    assert_eq!(const_eval!("(1 + 2) * 4 / 8"), 1.5);
+}
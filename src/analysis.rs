@@ -0,0 +1,450 @@
+use std::collections::HashMap;
+use crate::ast::Ast;
+use crate::error::DomainError;
+use crate::parse::{BinOp, Expr, UnOp, MAX_FACTORIAL};
+
+// Multiplier of the rolling hash behind expression fingerprints, a large odd prime
+const FINGERPRINT_BASE: u64 = 0x100000001b3;
+
+// Distance in multiples of pi from an odd multiple of pi / 2 within which tan is undefined
+const TAN_POLE_TOLERANCE: f64 = 1e-12;
+
+// Cost of a transcendental function call compared with a single arithmetic operation
+const TRANSCENDENTAL_STEPS: usize = 10;
+
+impl Ast {
+    /// Count how many times each operator or function appears in the expression
+    /// Keys are the printed form of the operator, so negation and subtraction share `-`
+    pub fn operations_histogram(&self) -> HashMap<String, usize> {
+        let mut counts = HashMap::new();
+        self.expr.count_operations(&mut counts);
+        counts
+    }
+
+    /// Score how complicated the expression is as the number of nodes in its tree
+    pub fn complexity(&self) -> usize {
+        self.expr.complexity()
+    }
+
+    /// Estimate how many primitive operations evaluating the expression takes
+    /// Arithmetic operators, negation, square roots and percentages count as 1, sin, cos, tan,
+    /// exp and ln as 10, and a factorial as one step per multiplication, assuming the largest
+    /// computable argument when it depends on a variable
+    pub fn steps_to_evaluate(&self) -> usize {
+        self.expr.steps_to_evaluate()
+    }
+
+    /// Describe likely mistakes that can be spotted without evaluating, such as
+    /// factorials of literals too large to compute exactly
+    pub fn lint(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        self.expr.lint(&mut warnings);
+        warnings
+    }
+
+    /// Check whether any division has a denominator other than a nonzero number literal
+    /// This overapproximates, so 1 / (x - 1) is reported even though only x = 1 divides by zero,
+    /// and so is 1 / (2 - 1) since constants are not folded first
+    pub fn has_division_by_zero_risk(&self) -> bool {
+        self.expr.has_division_by_zero_risk()
+    }
+
+    /// The largest number literal in the tree, or None if there are none
+    /// Negation is an operator, so the literal of -5 is 5, and named constants like pi count as literals
+    pub fn max_value_literal(&self) -> Option<f64> {
+        self.expr.literals().into_iter().reduce(f64::max)
+    }
+
+    /// The smallest number literal in the tree, or None if there are none
+    pub fn min_value_literal(&self) -> Option<f64> {
+        self.expr.literals().into_iter().reduce(f64::min)
+    }
+
+    /// Check every subexpression without variables for function arguments outside their domain,
+    /// such as ln(-3), sqrt(-1) or tan(pi / 2), reporting all of them rather than only the first
+    /// Subexpressions above a domain error or other evaluation failure are not checked
+    pub fn validate_domain(&self) -> Result<(), Vec<DomainError>> {
+        let mut errors = Vec::new();
+        self.expr.check_domain(&mut errors);
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    /// Hash the structure of the expression so that structurally equal trees share a fingerprint
+    /// This is a polynomial rolling hash over a preorder walk, so distinct trees only rarely collide
+    pub fn expression_fingerprint(&self) -> u64 {
+        let mut hash = 0;
+        self.expr.fingerprint(&mut hash);
+        hash
+    }
+
+    /// The distinct variable names in the order they first appear reading left to right,
+    /// for when a fixed order of inputs is needed
+    pub fn variables_ordered(&self) -> Vec<String> {
+        let mut vars = Vec::new();
+        self.expr.collect_variables_ordered(&mut vars);
+        vars
+    }
+
+    /// The number of independent inputs the expression takes, which is its count of distinct
+    /// variables, or None for an empty expression which takes no value at all
+    /// Single variable tools like `is_monotone_on`, `period`, `convolve` and `zip_eval` need an
+    /// arity of at most 1 and fail with `EvalError::WrongArity` otherwise
+    pub fn arity(&self) -> Option<usize> {
+        match self.expr {
+            Expr::Eof => None,
+            _ => Some(self.expr.free_variables().len())
+        }
+    }
+}
+
+// Fold one more word into the rolling hash
+fn roll(hash: &mut u64, word: u64) {
+    *hash = hash.wrapping_mul(FINGERPRINT_BASE).wrapping_add(word);
+}
+
+// Bit pattern of a number, with -0 mapped to 0 since the two compare equal
+fn number_bits(n: f64) -> u64 {
+    if n == 0.0 { 0 } else { n.to_bits() }
+}
+
+impl Expr {
+    fn count_operations(&self, counts: &mut HashMap<String, usize>) {
+        match self {
+            Expr::BinaryOp(op, e1, e2) => {
+                *counts.entry(op.to_string()).or_insert(0) += 1;
+                e1.count_operations(counts);
+                e2.count_operations(counts);
+            },
+            Expr::UnaryOp(op, e) => {
+                *counts.entry(op.to_string()).or_insert(0) += 1;
+                e.count_operations(counts);
+            },
+            Expr::Let(_, value, body) => {
+                value.count_operations(counts);
+                body.count_operations(counts);
+            },
+            Expr::Number(_) | Expr::Complex(_, _) | Expr::Variable(_) | Expr::Eof => {}
+        }
+    }
+
+    // Each node starts with a tag for its kind, and names end with a marker, so no two
+    // different preorder walks produce the same sequence of words
+    fn fingerprint(&self, hash: &mut u64) {
+        match self {
+            Expr::BinaryOp(op, e1, e2) => {
+                roll(hash, 1);
+                op.to_string().bytes().for_each(|b| roll(hash, b as u64));
+                e1.fingerprint(hash);
+                e2.fingerprint(hash);
+            },
+            Expr::UnaryOp(op, e) => {
+                roll(hash, 2);
+                op.to_string().bytes().for_each(|b| roll(hash, b as u64));
+                e.fingerprint(hash);
+            },
+            Expr::Number(n) => {
+                roll(hash, 3);
+                roll(hash, number_bits(*n));
+            },
+            Expr::Complex(re, im) => {
+                roll(hash, 4);
+                roll(hash, number_bits(*re));
+                roll(hash, number_bits(*im));
+            },
+            Expr::Variable(name) => {
+                roll(hash, 5);
+                name.bytes().for_each(|b| roll(hash, b as u64));
+                roll(hash, 0x100);
+            },
+            Expr::Let(name, value, body) => {
+                roll(hash, 7);
+                name.bytes().for_each(|b| roll(hash, b as u64));
+                roll(hash, 0x100);
+                value.fingerprint(hash);
+                body.fingerprint(hash);
+            },
+            Expr::Eof => roll(hash, 6)
+        }
+    }
+
+    fn collect_variables_ordered(&self, vars: &mut Vec<String>) {
+        match self {
+            Expr::BinaryOp(_, e1, e2) => {
+                e1.collect_variables_ordered(vars);
+                e2.collect_variables_ordered(vars);
+            },
+            Expr::UnaryOp(_, e) => e.collect_variables_ordered(vars),
+            Expr::Let(..) => self.inline_lets().collect_variables_ordered(vars),
+            Expr::Variable(name) => {
+                if !vars.contains(name) {
+                    vars.push(name.clone());
+                }
+            },
+            Expr::Number(_) | Expr::Complex(_, _) | Expr::Eof => {}
+        }
+    }
+
+    pub(crate) fn complexity(&self) -> usize {
+        match self {
+            Expr::BinaryOp(_, e1, e2) => 1 + e1.complexity() + e2.complexity(),
+            Expr::UnaryOp(_, e) => 1 + e.complexity(),
+            Expr::Let(_, value, body) => 1 + value.complexity() + body.complexity(),
+            Expr::Number(_) | Expr::Complex(_, _) | Expr::Variable(_) => 1,
+            Expr::Eof => 0
+        }
+    }
+
+    fn steps_to_evaluate(&self) -> usize {
+        match self {
+            Expr::BinaryOp(_, e1, e2) => 1 + e1.steps_to_evaluate() + e2.steps_to_evaluate(),
+            Expr::UnaryOp(op, e) => {
+                let steps = match op {
+                    UnOp::Sin | UnOp::Cos | UnOp::Tan | UnOp::Exp | UnOp::Log
+                    | UnOp::Asinh | UnOp::Acosh | UnOp::Atanh => TRANSCENDENTAL_STEPS,
+                    UnOp::Factorial => match e.eval() {
+                        Ok(n) if n >= 1.0 => (n as usize).min(MAX_FACTORIAL as usize),
+                        Ok(_) => 1,
+                        Err(_) => MAX_FACTORIAL as usize
+                    },
+                    UnOp::Negative | UnOp::Sqrt | UnOp::Percent => 1
+                };
+                steps + e.steps_to_evaluate()
+            },
+            // A bound value is computed once however often its name is used
+            Expr::Let(_, value, body) => value.steps_to_evaluate() + body.steps_to_evaluate(),
+            Expr::Number(_) | Expr::Complex(_, _) | Expr::Variable(_) | Expr::Eof => 0
+        }
+    }
+
+    fn literals(&self) -> Vec<f64> {
+        match self {
+            Expr::BinaryOp(_, e1, e2) | Expr::Let(_, e1, e2) => {
+                let mut literals = e1.literals();
+                literals.extend(e2.literals());
+                literals
+            },
+            Expr::UnaryOp(_, e) => e.literals(),
+            Expr::Number(n) => vec![*n],
+            Expr::Complex(_, _) | Expr::Variable(_) | Expr::Eof => vec![]
+        }
+    }
+
+    // The value of the subexpression, if it has no variables and evaluates within every domain
+    fn check_domain(&self, errors: &mut Vec<DomainError>) -> Option<f64> {
+        match self {
+            Expr::BinaryOp(op, e1, e2) => {
+                let (v1, v2) = (e1.check_domain(errors), e2.check_domain(errors));
+                let (v1, v2) = (v1?, v2?);
+                if *op == BinOp::LogBase {
+                    let before = errors.len();
+                    for arg in [v1, v2] {
+                        if arg <= 0.0 {
+                            errors.push(DomainError::LogOfNonPositive { arg });
+                        }
+                    }
+                    if errors.len() > before {
+                        return None
+                    }
+                }
+                op.apply(v1, v2).ok()
+            },
+            Expr::UnaryOp(op, e) => {
+                let arg = e.check_domain(errors)?;
+                let error = match op {
+                    UnOp::Log if arg <= 0.0 => Some(DomainError::LogOfNonPositive { arg }),
+                    UnOp::Sqrt if arg < 0.0 => Some(DomainError::SqrtOfNegative { arg }),
+                    UnOp::Acosh if arg < 1.0 => Some(DomainError::AcoshBelowOne { arg }),
+                    UnOp::Atanh if arg.abs() >= 1.0 => Some(DomainError::AtanhOutsideUnit { arg }),
+                    UnOp::Tan => {
+                        let half_turns = arg / std::f64::consts::PI - 0.5;
+                        ((half_turns - half_turns.round()).abs() < TAN_POLE_TOLERANCE).then_some(DomainError::TanOfPiOver2)
+                    },
+                    _ => None
+                };
+                match error {
+                    Some(error) => {
+                        errors.push(error);
+                        None
+                    },
+                    None => op.apply(arg).ok()
+                }
+            },
+            Expr::Number(n) => Some(*n),
+            Expr::Let(..) => self.inline_lets().check_domain(errors),
+            Expr::Complex(_, _) | Expr::Variable(_) | Expr::Eof => None
+        }
+    }
+
+    fn has_division_by_zero_risk(&self) -> bool {
+        match self {
+            Expr::BinaryOp(BinOp::Divide, _, e2) if !matches!(e2.as_ref(), Expr::Number(n) if *n != 0.0 && !n.is_nan()) => true,
+            Expr::BinaryOp(_, e1, e2) => e1.has_division_by_zero_risk() || e2.has_division_by_zero_risk(),
+            Expr::UnaryOp(_, e) => e.has_division_by_zero_risk(),
+            Expr::Let(..) => self.inline_lets().has_division_by_zero_risk(),
+            Expr::Number(_) | Expr::Complex(_, _) | Expr::Variable(_) | Expr::Eof => false
+        }
+    }
+
+    fn lint(&self, warnings: &mut Vec<String>) {
+        match self {
+            Expr::BinaryOp(_, e1, e2) => {
+                e1.lint(warnings);
+                e2.lint(warnings);
+            },
+            Expr::Let(_, value, body) => {
+                value.lint(warnings);
+                body.lint(warnings);
+            },
+            Expr::UnaryOp(op, e) => {
+                if let (UnOp::Factorial, Expr::Number(n)) = (op, e.as_ref()) {
+                    if *n > MAX_FACTORIAL as f64 {
+                        warnings.push(format!("{}! overflows, factorials above {}! cannot be computed", n, MAX_FACTORIAL));
+                    }
+                }
+                e.lint(warnings);
+            },
+            Expr::Number(_) | Expr::Complex(_, _) | Expr::Variable(_) | Expr::Eof => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_counts() {
+        let hist = Ast::string_to_ast("sin(x) + cos(x) + sin(y)").operations_histogram();
+        let expected = HashMap::from([("sin".to_owned(), 2), ("cos".to_owned(), 1), ("+".to_owned(), 2)]);
+        assert_eq!(hist, expected);
+    }
+
+    #[test]
+    fn histogram_empty_for_leaves() {
+        assert!(Ast::string_to_ast("42").operations_histogram().is_empty());
+        assert!(Ast::string_to_ast("").operations_histogram().is_empty());
+    }
+
+    #[test]
+    fn complexity_counts_nodes() {
+        assert_eq!(Ast::string_to_ast("1 + 2 * x").complexity(), 5);
+        assert_eq!(Ast::string_to_ast("-sin(x)!").complexity(), 4);
+        assert_eq!(Ast::string_to_ast("").complexity(), 0);
+    }
+
+    #[test]
+    fn steps_weigh_operations() {
+        let steps = |s| Ast::string_to_ast(s).steps_to_evaluate();
+        assert_eq!(steps("1 + 2 * x"), 2);
+        assert_eq!(steps("-sqrt(x) + sin(x)"), 13);
+        assert_eq!(steps("5! + x!"), 26);
+        assert_eq!(steps("x"), 0);
+        assert_eq!(steps(""), 0);
+    }
+
+    #[test]
+    fn folding_reduces_steps() {
+        for (s, before, after) in [("x * (2 + 3) + sin(1)", 13, 2), ("exp(ln(4)) * 6! - x", 28, 1), ("x + y", 1, 1)] {
+            let ast = Ast::string_to_ast(s);
+            assert_eq!(ast.steps_to_evaluate(), before, "{}", s);
+            assert_eq!(Ast::new(ast.expr.fold_constants()).steps_to_evaluate(), after, "{}", s);
+        }
+    }
+
+    #[test]
+    fn variables_in_first_occurrence_order() {
+        assert_eq!(Ast::string_to_ast("y + x * z + y").variables_ordered(), vec!["y", "x", "z"]);
+        assert_eq!(Ast::string_to_ast("logb(b, sin(a) - b)").variables_ordered(), vec!["b", "a"]);
+        assert!(Ast::string_to_ast("2 * pi").variables_ordered().is_empty());
+    }
+
+    #[test]
+    fn arity_counts_distinct_variables() {
+        assert_eq!(Ast::string_to_ast("3! + pi").arity(), Some(0));
+        assert_eq!(Ast::string_to_ast("x^2 - x").arity(), Some(1));
+        assert_eq!(Ast::string_to_ast("y + x * z + y").arity(), Some(3));
+        assert_eq!(Ast::string_to_ast("").arity(), None);
+    }
+
+    #[test]
+    fn lint_large_factorials() {
+        assert!(Ast::string_to_ast("20! + x!").lint().is_empty());
+        assert_eq!(Ast::string_to_ast("sin(21!)").lint(), vec!["21! overflows, factorials above 20! cannot be computed"]);
+    }
+
+    #[test]
+    fn division_by_zero_risks() {
+        for (s, risk) in [
+            ("1 / 0", true),
+            ("1 / 2", false),
+            ("x / (y - 1)", true),
+            ("x / 0.5 + 3 * 4", false),
+            ("sin(1 / sin(x))", true),
+            ("1 / (2 - 1)", true),
+            ("x^2 - 1", false),
+        ] {
+            assert_eq!(Ast::string_to_ast(s).has_division_by_zero_risk(), risk, "{}", s);
+        }
+    }
+
+    #[test]
+    fn extreme_literals() {
+        let ast = Ast::string_to_ast("sin(3.0) + ln(2.0)");
+        assert_eq!((ast.max_value_literal(), ast.min_value_literal()), (Some(3.0), Some(2.0)));
+        let ast = Ast::string_to_ast("-5 * x + 25! / 0.5^y");
+        assert_eq!((ast.max_value_literal(), ast.min_value_literal()), (Some(25.0), Some(0.5)));
+        let ast = Ast::string_to_ast("x * i");
+        assert_eq!((ast.max_value_literal(), ast.min_value_literal()), (None, None));
+        assert_eq!(Ast::string_to_ast("").max_value_literal(), None);
+    }
+
+    #[test]
+    fn domain_errors() {
+        let validate = |s| Ast::string_to_ast(s).validate_domain();
+        assert_eq!(validate("ln(-3) + sqrt(-1)"), Err(vec![
+            DomainError::LogOfNonPositive { arg: -3.0 },
+            DomainError::SqrtOfNegative { arg: -1.0 },
+        ]));
+        assert_eq!(validate("ln(1 - 1)"), Err(vec![DomainError::LogOfNonPositive { arg: 0.0 }]));
+        assert_eq!(validate("logb(-2, 8)"), Err(vec![DomainError::LogOfNonPositive { arg: -2.0 }]));
+        assert_eq!(validate("2 * tan(pi / 2)"), Err(vec![DomainError::TanOfPiOver2]));
+        assert_eq!(validate("tan(-3 * pi / 2)"), Err(vec![DomainError::TanOfPiOver2]));
+        // Constant parts are checked even when others have variables
+        assert_eq!(validate("x + sqrt(1 - 5)"), Err(vec![DomainError::SqrtOfNegative { arg: -4.0 }]));
+        // Only the innermost error is reported
+        assert_eq!(validate("sqrt(ln(-1))"), Err(vec![DomainError::LogOfNonPositive { arg: -1.0 }]));
+        assert_eq!(validate("acosh(0.5) + atanh(1) + atanh(-2)"), Err(vec![
+            DomainError::AcoshBelowOne { arg: 0.5 },
+            DomainError::AtanhOutsideUnit { arg: 1.0 },
+            DomainError::AtanhOutsideUnit { arg: -2.0 },
+        ]));
+
+        assert_eq!(validate("ln(2) + sqrt(0) + tan(pi) + logb(2, 8)"), Ok(()));
+        assert_eq!(validate("acosh(1) + atanh(0.5) + atanh(-0.5)"), Ok(()));
+        assert_eq!(validate("ln(x) + sqrt(y)"), Ok(()));
+        assert_eq!(validate(""), Ok(()));
+    }
+
+    #[test]
+    fn fingerprints_distinguish_trees() {
+        let fingerprints: Vec<u64> = [
+            "sin(4) + exp(3 - 1)^3",
+            "-2 + 4 * -(5^3 + 7 * 3!)",
+            "sin(3.14159) + cos(3.14159) + exp(0)^2 - ln(1)/2",
+            "tan(-4--4) / ln(4)",
+            "ln(exp(-4/5))",
+        ].iter().map(|s| Ast::string_to_ast(s).expression_fingerprint()).collect();
+        for (i, a) in fingerprints.iter().enumerate() {
+            assert!(fingerprints[i + 1..].iter().all(|b| a != b));
+        }
+    }
+
+    #[test]
+    fn fingerprints_match_equal_trees() {
+        let fingerprint = |s| Ast::string_to_ast(s).expression_fingerprint();
+        assert_eq!(fingerprint("x * (y + 1)"), fingerprint("x*(y+1)"));
+        assert_ne!(fingerprint("x - y"), fingerprint("y - x"));
+        assert_ne!(fingerprint("-x"), fingerprint("0 - x"));
+        assert_ne!(fingerprint("ab"), fingerprint("a * b"));
+    }
+}
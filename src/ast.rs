@@ -1,28 +1,182 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::fmt::{Display, Formatter};
-use logos::Logos;
-use crate::lex::Token;
-use crate::parse::{Expr, expr_prec};
+use std::io::{BufRead, Read};
+use std::str::FromStr;
+use crate::error::{CalcError, EvalError, ParseError};
+use crate::lex::TokenStream;
+use crate::parse::{BinOp, Expr, UnOp, parse_expr};
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct Ast {
-    expr: Expr
+    pub(crate) expr: Expr
+}
+
+/// An AST for the zero constant
+impl Default for Ast {
+    fn default() -> Self {
+        Ast::new(Expr::default())
+    }
+}
+
+/// Parse an AST with `"1 + 2".parse::<Ast>()`
+impl FromStr for Ast {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::try_from_str(s)
+    }
 }
 
 impl Ast {
-    fn new(expr: Expr) -> Self {
+    pub(crate) fn new(expr: Expr) -> Self {
         Self {
             expr
         }
     }
 
+    /// Evaluate the expression, panicking if it cannot be evaluated
     pub fn eval(&self) -> f64 {
+        self.try_eval().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_eval(&self) -> Result<f64, EvalError> {
         self.expr.eval()
     }
 
+    /// Evaluate the expression on tokio's blocking thread pool, so that a large expression
+    /// does not hold up other tasks on the async executor
+    #[cfg(feature = "async")]
+    pub async fn eval_async(&self) -> Result<f64, EvalError> {
+        let ast = self.clone();
+        tokio::task::spawn_blocking(move || ast.try_eval())
+            .await
+            .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))
+    }
+
+    /// Evaluate the expression with `f32` arithmetic at every step, not just a final cast
+    pub fn eval_f32(&self) -> Result<f32, EvalError> {
+        self.expr.eval_f32()
+    }
+
+    /// Evaluate the expression, requiring the result to be a whole number that fits in an i64
+    pub fn eval_integer(&self) -> Result<i64, EvalError> {
+        let val = self.try_eval()?;
+        // i64::MAX is not exactly representable, so compare against 2^63 itself
+        if val.fract() != 0.0 || val < -(2f64.powi(63)) || val >= 2f64.powi(63) {
+            return Err(EvalError::NotInteger(val))
+        }
+        Ok(val as i64)
+    }
+
+    /// Evaluate the expression, looking up any variables in `vars`
+    pub fn eval_multivar(&self, vars: &HashMap<String, f64>) -> Result<f64, EvalError> {
+        self.expr.eval_with(vars)
+    }
+
+    /// Evaluate the expression with all of the given variables bound at once
+    /// If a name is repeated the last value for it wins
+    pub fn substitute_many(&self, vars: &[(&str, f64)]) -> Result<f64, EvalError> {
+        let vars = vars.iter().map(|(name, val)| (name.to_string(), *val)).collect();
+        self.eval_multivar(&vars)
+    }
+
+    // Bind the variables, in the order they first appear, to the values, which must match them in number
+    fn eval_in_order(&self, values: &[f64]) -> Result<f64, EvalError> {
+        let vars = self.variables_ordered();
+        if vars.len() != values.len() {
+            return Err(EvalError::WrongArity { expected: values.len(), found: vars.len() })
+        }
+        let bindings: Vec<(&str, f64)> = vars.iter().map(|v| v.as_str()).zip(values.iter().copied()).collect();
+        self.substitute_many(&bindings)
+    }
+
+    /// Evaluate an expression of exactly one variable with that variable set to x
+    pub fn eval_at(&self, x: f64) -> Result<f64, EvalError> {
+        if self.expr.free_variables().is_empty() {
+            return Err(EvalError::WrongArity { expected: 1, found: 0 })
+        }
+        self.eval_point(x)
+    }
+
+    /// Evaluate an expression of exactly two variables, setting the one that appears first to x
+    /// and the other to y
+    pub fn eval_at2(&self, x: f64, y: f64) -> Result<f64, EvalError> {
+        self.eval_in_order(&[x, y])
+    }
+
+    /// Parse the string into an AST, panicking if it is not a valid expression
     pub fn string_to_ast(s: &str) -> Self {
-        let lex = Token::lexer(s);
-        let expr = expr_prec(&mut lex.peekable(), 0);
-        Ast::new(expr)
+        Self::try_from_str(s).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_from_str(s: &str) -> Result<Self, ParseError> {
+        let expr = parse_expr(&mut TokenStream::new(s))?;
+        Ok(Ast::new(expr))
+    }
+
+    /// Read a single line from the reader and parse it
+    /// Bytes are read one at a time so nothing past the newline is consumed
+    /// A carriage return before the newline is dropped, as `BufRead::lines` does
+    #[allow(clippy::unbuffered_bytes)]
+    pub fn from_reader(r: impl Read) -> Result<Self, ParseError> {
+        let mut bytes = Vec::new();
+        for byte in r.bytes() {
+            match byte {
+                Ok(b'\n') => break,
+                Ok(b) => bytes.push(b),
+                Err(e) => return Err(ParseError::Io(e.to_string()))
+            }
+        }
+        if bytes.last() == Some(&b'\r') {
+            bytes.pop();
+        }
+        let line = String::from_utf8(bytes).map_err(|e| ParseError::Io(e.to_string()))?;
+        Self::try_from_str(&line)
+    }
+
+    /// Parse and evaluate each non-blank line of the reader, pairing results with their input
+    pub fn eval_reader(r: impl BufRead) -> impl Iterator<Item = Result<(String, f64), CalcError>> {
+        r.lines()
+            .filter(|line| !matches!(line, Ok(l) if l.trim().is_empty()))
+            .map(|line| {
+                let line = line.map_err(|e| ParseError::Io(e.to_string()))?;
+                let val = Self::try_from_str(&line)?.try_eval()?;
+                Ok((line, val))
+            })
+    }
+
+    /// Parse and evaluate each input on its own, one after another, pairing results with their input
+    /// A failure in one input has no effect on the others
+    pub fn eval_batch_sequential(inputs: &[&str]) -> Vec<Result<(String, f64), CalcError>> {
+        inputs.iter()
+            .map(|input| {
+                let val = Self::try_from_str(input)?.try_eval()?;
+                Ok((input.to_string(), val))
+            })
+            .collect()
+    }
+
+    /// Combine two expressions as the operands of a binary operator
+    pub fn merge(self, other: Ast, op: BinOp) -> Ast {
+        self.apply_binop(other, op)
+    }
+
+    /// Use this expression as the left operand and `other` as the right operand of `op`
+    pub fn apply_binop(self, other: Ast, op: BinOp) -> Ast {
+        Ast::new(Expr::BinaryOp(op, Box::new(self.expr), Box::new(other.expr)))
+    }
+
+    /// Use this expression as the argument of `op`
+    pub fn apply_unop(self, op: UnOp) -> Ast {
+        Ast::new(Expr::UnaryOp(op, Box::new(self.expr)))
+    }
+
+    pub fn negate(self) -> Ast {
+        self.apply_unop(UnOp::Negative)
+    }
+
+    pub fn reciprocal(self) -> Ast {
+        Ast::new(Expr::BinaryOp(BinOp::Divide, Box::new(Expr::Number(1f64)), Box::new(self.expr)))
     }
 }
 
@@ -55,12 +209,12 @@ fn pad_center(s: String, total_width: usize, align: Align) -> String {
         s
     } else {
         let diff = total_width - s.len();
-        if diff % 2 == 0 {
+        if diff.is_multiple_of(2) {
             let padding = " ".repeat(diff/2);
             format!("{}{}{}", padding, s, padding)
         } else {
             let short_padding = " ".repeat(diff/2);
-            let long_padding = " ".repeat((diff+1)/2);
+            let long_padding = " ".repeat(diff.div_ceil(2));
             match align {
                 Align::Left => format!("{}{}{}", short_padding, s, long_padding),
                 Align::Right => format!("{}{}{}", long_padding, s, short_padding)
@@ -71,7 +225,26 @@ fn pad_center(s: String, total_width: usize, align: Align) -> String {
 
 impl Ast {
     pub fn print_hierarchy(&self) {
-        self.expr.print_hierarchy("", false);
+        print!("{}", self.hierarchy_string());
+    }
+
+    fn hierarchy_string(&self) -> String {
+        let mut out = String::new();
+        self.expr.write_hierarchy(&mut out, "", false, true);
+        out
+    }
+
+    /// Print the hierarchy with each node followed by the value of its subtree, as in `+ [5.0]`,
+    /// given values for the variables
+    /// Nodes that cannot be evaluated, such as those over an unbound variable, are marked `[?]`
+    pub fn print_with_values(&self, vars: &HashMap<String, f64>) {
+        print!("{}", self.hierarchy_with_values(vars));
+    }
+
+    fn hierarchy_with_values(&self, vars: &HashMap<String, f64>) -> String {
+        let mut out = String::new();
+        let _ = self.expr.write_annotated_hierarchy(&mut out, "", false, true, Some(vars));
+        out
     }
 }
 
@@ -81,25 +254,27 @@ impl Display for Ast {
     /// Generate edges below them in cells of same length
     /// Setup next line for printing using the Exprs inside the given Expr
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let mut width = self.expr.get_width();
+        // Let bindings have no place in the picture, so their values are drawn where they are used
+        let expr = self.expr.inline_lets();
+        let mut width = expr.get_width();
 
         // Ensure width is odd so root can start at middle
-        width = if width % 2 == 0 {
+        width = if width.is_multiple_of(2) {
             width + 1
         } else {
             width
         };
 
-        let mut cell_length = self.expr.get_max_len();
+        let mut cell_length = expr.get_max_len();
         // Ensure odd length so can have | in middle
-        cell_length = if cell_length % 2 == 0 {
+        cell_length = if cell_length.is_multiple_of(2) {
             cell_length + 1
         } else {
             cell_length
         };
         let cell_minus_2 = cell_length - 2;
 
-        let mut current_row: VecDeque<PositionedExpr> = VecDeque::from([PositionedExpr::new(&self.expr, (width+1)/2, Align::Left)]);
+        let mut current_row: VecDeque<PositionedExpr> = VecDeque::from([PositionedExpr::new(&expr, width.div_ceil(2), Align::Left)]);
         let mut next_row: VecDeque<PositionedExpr> = VecDeque::new();
         loop {
             let mut edges_vec = vec![" ".repeat(cell_length); width];
@@ -129,6 +304,13 @@ impl Display for Ast {
                     Expr::Number(n) => {
                         nodes_vec[next.pos] = pad_center(n.to_string(), cell_length, next.align);
                     },
+                    Expr::Complex(_, _) => {
+                        nodes_vec[next.pos] = pad_center(next.expr.to_string(), cell_length, next.align);
+                    },
+                    Expr::Variable(name) => {
+                        nodes_vec[next.pos] = pad_center(name.clone(), cell_length, next.align);
+                    },
+                    Expr::Let(..) => unreachable!("Bindings are inlined before drawing"),
                     Expr::Eof => {}
                 }
             }
@@ -188,4 +370,174 @@ mod tests {
         let ast = Ast::string_to_ast("ln(exp(-4/5))");
         assert_eq!(ast.eval(), -0.8);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!("sin(4) + exp(3 - 1)^3".parse::<Ast>().unwrap().eval(), 402.67199099742726);
+        assert_eq!("-2 + 4 * -(5^3 + 7 * 3!)".parse::<Ast>().unwrap().eval(), -670f64);
+        assert_eq!("sin(3.14159) + cos(3.14159) + exp(0)^2 - ln(1)/2".parse::<Ast>().unwrap().eval(), 0.0000026535933140836576);
+        assert_eq!("tan(-4--4) / ln(4)".parse::<Ast>().unwrap().eval(), 0f64);
+        assert_eq!("ln(exp(-4/5))".parse::<Ast>().unwrap().eval(), -0.8);
+        assert_eq!("(1 + 2".parse::<Ast>(), Err(ParseError::MissingClosingParen));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_eval_async() {
+        assert_eq!(Ast::string_to_ast("sin(4) + exp(3 - 1)^3").eval_async().await, Ok(402.67199099742726));
+        assert_eq!(Ast::string_to_ast("-2 + 4 * -(5^3 + 7 * 3!)").eval_async().await, Ok(-670f64));
+        assert_eq!(Ast::string_to_ast("x").eval_async().await, Err(EvalError::UnboundVariable("x".to_owned())));
+    }
+
+    #[test]
+    fn test_merge() {
+        let ast = Ast::string_to_ast("sin(x)").merge(Ast::string_to_ast("cos(x)"), BinOp::Plus);
+        assert_eq!(ast.eval_point(std::f64::consts::FRAC_PI_2), Ok(1f64));
+        assert_eq!(ast, Ast::string_to_ast("sin(x) + cos(x)"));
+    }
+
+    #[test]
+    fn test_negate_reciprocal() {
+        assert_eq!(Ast::string_to_ast("2 + 3").negate().eval(), -5f64);
+        assert_eq!(Ast::string_to_ast("2 * 2").reciprocal().eval(), 0.25);
+        assert_eq!(Ast::string_to_ast("x").reciprocal(), Ast::string_to_ast("1 / x"));
+    }
+
+    #[test]
+    fn test_eval_integer() {
+        assert_eq!(Ast::string_to_ast("4!").eval_integer(), Ok(24));
+        assert_eq!(Ast::string_to_ast("2^53").eval_integer(), Ok(9007199254740992));
+        assert_eq!(Ast::string_to_ast("2.5 + 1.5").eval_integer(), Ok(4));
+        assert!(matches!(Ast::string_to_ast("0.5 + 0.5 + 0.1").eval_integer(), Err(EvalError::NotInteger(_))));
+        assert_eq!(Ast::string_to_ast("2^63").eval_integer(), Err(EvalError::NotInteger(2f64.powi(63))));
+        assert_eq!(Ast::string_to_ast("-2^63").eval_integer(), Ok(i64::MIN));
+        assert!(matches!(Ast::string_to_ast("1 / 0").eval_integer(), Err(EvalError::NotInteger(_))));
+    }
+
+    #[test]
+    fn test_hierarchy_root() {
+        // The root used to be drawn as a right child, as "└── +" with its children indented under it
+        assert_eq!(Ast::string_to_ast("1 + 2").hierarchy_string(), "+\n├── 1\n└── 2\n");
+        assert_eq!(Ast::string_to_ast("-(x * 3)").hierarchy_string(), "-\n└── *\n    ├── x\n    └── 3\n");
+        assert_eq!(Ast::string_to_ast("4").hierarchy_string(), "4\n");
+        assert_eq!(Ast::string_to_ast("").hierarchy_string(), "");
+    }
+
+    #[test]
+    fn test_hierarchy_with_values() {
+        let vars = HashMap::from([("x".to_owned(), 2.0)]);
+        assert_eq!(
+            Ast::string_to_ast("x * 3 - 1").hierarchy_with_values(&vars),
+            "- [5.0]\n├── * [6.0]\n│   ├── x [2.0]\n│   └── 3 [3.0]\n└── 1 [1.0]\n"
+        );
+        // Only the nodes above the unbound variable or failing factorial go without a value
+        assert_eq!(
+            Ast::string_to_ast("(x + y) / (1 - 2)!").hierarchy_with_values(&vars),
+            "/ [?]\n├── + [?]\n│   ├── x [2.0]\n│   └── y [?]\n└── ! [?]\n    └── - [-1.0]\n        ├── 1 [1.0]\n        └── 2 [2.0]\n"
+        );
+        assert_eq!(Ast::string_to_ast("").hierarchy_with_values(&vars), "");
+    }
+
+    #[test]
+    fn test_default() {
+        assert_eq!(Ast::default().try_eval(), Ok(0.0));
+        assert_eq!(Ast::default(), Ast::string_to_ast("0"));
+    }
+
+    #[test]
+    fn test_apply_ops() {
+        let expected = ["-3", "sin(3)", "cos(3)", "tan(3)", "exp(3)", "ln(3)", "sqrt(3)", "asinh(3)", "acosh(3)", "atanh(3)", "3!", "3%"];
+        for (op, s) in UnOp::ALL.into_iter().zip(expected) {
+            assert_eq!(Ast::string_to_ast("3").apply_unop(op), Ast::string_to_ast(s));
+        }
+
+        let expected = ["x + 2", "x - 2", "x * 2", "x / 2", "x^2"];
+        for (op, s) in BinOp::ALL.into_iter().zip(expected) {
+            assert_eq!(Ast::string_to_ast("x").apply_binop(Ast::string_to_ast("2"), op), Ast::string_to_ast(s));
+        }
+    }
+
+    #[test]
+    fn test_from_reader() {
+        let mut cursor = std::io::Cursor::new("1 + 2\n3 * 4");
+        assert_eq!(Ast::from_reader(&mut cursor).unwrap().eval(), 3f64);
+        assert_eq!(Ast::from_reader(&mut cursor).unwrap().eval(), 12f64);
+        let mut cursor = std::io::Cursor::new("1 + 2\r\n3 * 4\r\n");
+        assert_eq!(Ast::from_reader(&mut cursor).unwrap().eval(), 3f64);
+        assert_eq!(Ast::from_reader(&mut cursor).unwrap().eval(), 12f64);
+        assert_eq!(Ast::from_reader(std::io::Cursor::new("(1 + 2")), Err(ParseError::MissingClosingParen));
+    }
+
+    #[test]
+    fn test_eval_reader() {
+        let cursor = std::io::Cursor::new("1 + 2\n\n2 *\nx\nexp(0)\n");
+        let results: Vec<_> = Ast::eval_reader(cursor).collect();
+        assert_eq!(results, vec![
+            Ok(("1 + 2".to_owned(), 3f64)),
+            Err(CalcError::Parse(ParseError::UnexpectedEof)),
+            Err(CalcError::Eval(EvalError::UnboundVariable("x".to_owned()))),
+            Ok(("exp(0)".to_owned(), 1f64)),
+        ]);
+    }
+
+    #[test]
+    fn test_eval_batch_sequential() {
+        let inputs: Vec<String> = (0..100).map(|i| match i % 10 {
+            3 => format!("{} +", i),
+            7 => format!("{} * y", i),
+            _ => format!("{} * 2", i)
+        }).collect();
+        let inputs: Vec<&str> = inputs.iter().map(String::as_str).collect();
+        let results = Ast::eval_batch_sequential(&inputs);
+        assert_eq!(results.len(), 100);
+        for (i, result) in results.into_iter().enumerate() {
+            match i % 10 {
+                3 => assert_eq!(result, Err(CalcError::Parse(ParseError::UnexpectedEof))),
+                7 => assert_eq!(result, Err(CalcError::Eval(EvalError::UnboundVariable("y".to_owned())))),
+                _ => assert_eq!(result, Ok((inputs[i].to_owned(), 2.0 * i as f64)))
+            }
+        }
+    }
+
+    #[test]
+    fn test_eval_f32() {
+        assert_eq!(Ast::string_to_ast("sin(pi/6)").eval_f32(), Ok(0.5f32));
+        assert_eq!(Ast::string_to_ast("4! / 3").eval_f32(), Ok(8f32));
+        assert_eq!(Ast::string_to_ast("1 + 1e-10").eval_f32(), Ok(1f32));
+        assert_ne!(Ast::string_to_ast("1 + 1e-10").eval(), 1f64);
+        assert_eq!(Ast::string_to_ast("x").eval_f32(), Err(EvalError::UnboundVariable("x".to_owned())));
+    }
+
+    #[test]
+    fn test_substitute_many() {
+        let ast = Ast::string_to_ast("x + y");
+        assert_eq!(ast.substitute_many(&[("x", 3.0), ("y", 4.0)]), Ok(7.0));
+        assert_eq!(ast.substitute_many(&[("x", 3.0)]), Err(EvalError::UnboundVariable("y".to_owned())));
+
+        let vars = HashMap::from([("x".to_owned(), 2.0), ("y".to_owned(), 0.5)]);
+        assert_eq!(Ast::string_to_ast("x^y * y").eval_multivar(&vars), Ok(2f64.sqrt() / 2.0));
+    }
+
+    #[test]
+    fn test_eval_at() {
+        assert_eq!(Ast::string_to_ast("sin(x)").eval_at(std::f64::consts::FRAC_PI_2), Ok(1.0));
+        assert_eq!(Ast::string_to_ast("t^2 - t").eval_at(3.0), Ok(6.0));
+        assert_eq!(Ast::string_to_ast("2 + 2").eval_at(1.0), Err(EvalError::WrongArity { expected: 1, found: 0 }));
+        assert_eq!(Ast::string_to_ast("x * y").eval_at(1.0), Err(EvalError::WrongArity { expected: 1, found: 2 }));
+        // Apart from rejecting constants, eval_at agrees with the memoized and numeric evaluations
+        for s in ["sin(x)", "(x - 1)!", "x * y"] {
+            let ast = Ast::string_to_ast(s);
+            assert_eq!(ast.eval_at(-2.0), ast.eval_point(-2.0), "{}", s);
+            assert_eq!(ast.eval_at(-2.0), ast.memoize_eval().eval_at(-2.0), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_eval_at2() {
+        assert_eq!(Ast::string_to_ast("x^2 + y^2").eval_at2(3.0, 4.0), Ok(25.0));
+        // Variables are bound in the order they first appear, not alphabetically
+        assert_eq!(Ast::string_to_ast("b - a").eval_at2(5.0, 2.0), Ok(3.0));
+        assert_eq!(Ast::string_to_ast("x * x").eval_at2(1.0, 2.0), Err(EvalError::WrongArity { expected: 2, found: 1 }));
+        assert_eq!(Ast::string_to_ast("x + y + z").eval_at2(1.0, 2.0), Err(EvalError::WrongArity { expected: 2, found: 3 }));
+    }
+}
@@ -0,0 +1,314 @@
+use crate::ast::Ast;
+use crate::parse::{BinOp, Expr, UnOp};
+
+// Generated source code for a subexpression
+// Atoms can be used as operands or method receivers without parentheses
+struct Code {
+    src: String,
+    is_atom: bool
+}
+
+impl Code {
+    fn atom(src: String) -> Self {
+        Self {
+            src,
+            is_atom: true
+        }
+    }
+
+    fn compound(src: String) -> Self {
+        Self {
+            src,
+            is_atom: false
+        }
+    }
+
+    fn wrapped(&self) -> String {
+        if self.is_atom {
+            self.src.clone()
+        } else {
+            format!("({})", self.src)
+        }
+    }
+}
+
+impl Ast {
+    // Free variables in a stable order for use as function parameters
+    fn parameters(&self) -> Vec<String> {
+        let mut vars: Vec<String> = self.expr.free_variables().into_iter().collect();
+        vars.sort();
+        vars
+    }
+
+    /// Generate Rust source for a closure computing the expression
+    /// Free variables become `f64` parameters in alphabetical order
+    ///
+    /// ```
+    /// use ast_calc::ast::Ast;
+    ///
+    /// let ast = Ast::string_to_ast("sin(x) + cos(x)");
+    /// assert_eq!(ast.to_rust_closure(), "|x: f64| -> f64 { x.sin() + x.cos() }");
+    ///
+    /// let f = |x: f64| -> f64 { x.sin() + x.cos() };
+    /// assert_eq!(f(0.0), 1.0);
+    /// ```
+    pub fn to_rust_closure(&self) -> String {
+        let params: Vec<String> = self.parameters().iter().map(|v| format!("{}: f64", v)).collect();
+        format!("|{}| -> f64 {{ {} }}", params.join(", "), self.expr.rust_code().src)
+    }
+
+    /// Generate a C function named `expr` computing the expression
+    /// The output needs `math.h` and free variables become `double` parameters in alphabetical order
+    pub fn to_c_code(&self) -> String {
+        self.to_c_function("expr")
+    }
+
+    /// Generate a C function with the given name computing the expression
+    pub fn to_c_function(&self, name: &str) -> String {
+        let params: Vec<String> = self.parameters().iter().map(|v| format!("double {}", v)).collect();
+        let params = if params.is_empty() {
+            "void".to_owned()
+        } else {
+            params.join(", ")
+        };
+        format!("double {}({}) {{ return {}; }}", name, params, self.expr.c_code().src)
+    }
+}
+
+impl Expr {
+    fn rust_code(&self) -> Code {
+        match self {
+            Expr::BinaryOp(op, e1, e2) => {
+                let (c1, c2) = (e1.rust_code(), e2.rust_code());
+                match op {
+                    BinOp::Power => Code::atom(format!("{}.powf({})", c1.wrapped(), c2.src)),
+                    BinOp::LogBase => Code::atom(format!("{}.log({})", c2.wrapped(), c1.src)),
+                    _ => Code::compound(format!("{} {} {}", c1.wrapped(), op, c2.wrapped()))
+                }
+            },
+            Expr::UnaryOp(op, e) => {
+                let c = e.rust_code();
+                match op {
+                    UnOp::Negative => Code::compound(format!("-{}", c.wrapped())),
+                    UnOp::Sin => Code::atom(format!("{}.sin()", c.wrapped())),
+                    UnOp::Cos => Code::atom(format!("{}.cos()", c.wrapped())),
+                    UnOp::Tan => Code::atom(format!("{}.tan()", c.wrapped())),
+                    UnOp::Exp => Code::atom(format!("{}.exp()", c.wrapped())),
+                    UnOp::Log => Code::atom(format!("{}.ln()", c.wrapped())),
+                    UnOp::Sqrt => Code::atom(format!("{}.sqrt()", c.wrapped())),
+                    UnOp::Asinh => Code::atom(format!("{}.asinh()", c.wrapped())),
+                    UnOp::Acosh => Code::atom(format!("{}.acosh()", c.wrapped())),
+                    UnOp::Atanh => Code::atom(format!("{}.atanh()", c.wrapped())),
+                    // Arguments without an exact u64 factorial give NaN, like other unrepresentable values
+                    UnOp::Factorial => Code::compound(format!(
+                        "{{ let n = {}; if (0.0..=20.0).contains(&n) && n.fract() == 0.0 {{ (1..=(n as u64)).product::<u64>() as f64 }} else {{ f64::NAN }} }}",
+                        c.src
+                    )),
+                    UnOp::Percent => Code::compound(format!("{} / 100.0", c.wrapped()))
+                }
+            },
+            Expr::Number(n) => {
+                if n.is_nan() {
+                    Code::atom("f64::NAN".to_owned())
+                } else if n.is_infinite() {
+                    Code::compound(format!("{}f64::INFINITY", if *n < 0.0 { "-" } else { "" }))
+                } else if n.is_sign_negative() {
+                    Code::compound(format!("{:?}_f64", n))
+                } else {
+                    // Untyped float literals cannot be method receivers
+                    Code::atom(format!("{:?}_f64", n))
+                }
+            },
+            // Generated code only works with real numbers
+            Expr::Complex(re, im) if *im == 0.0 => Expr::Number(*re).rust_code(),
+            Expr::Complex(_, _) => Code::atom("f64::NAN".to_owned()),
+            Expr::Variable(name) => Code::atom(name.clone()),
+            Expr::Let(name, value, body) => Code::compound(format!("{{ let {} = {}; {} }}", name, value.rust_code().src, body.rust_code().src)),
+            Expr::Eof => Code::atom("f64::NAN".to_owned())
+        }
+    }
+
+    fn c_code(&self) -> Code {
+        match self {
+            Expr::BinaryOp(op, e1, e2) => {
+                let (c1, c2) = (e1.c_code(), e2.c_code());
+                match op {
+                    BinOp::Power => Code::atom(format!("pow({}, {})", c1.src, c2.src)),
+                    BinOp::LogBase => Code::compound(format!("log({}) / log({})", c2.src, c1.src)),
+                    _ => Code::compound(format!("{} {} {}", c1.wrapped(), op, c2.wrapped()))
+                }
+            },
+            Expr::UnaryOp(op, e) => {
+                let c = e.c_code();
+                match op {
+                    UnOp::Negative => Code::compound(format!("-{}", c.wrapped())),
+                    UnOp::Sin => Code::atom(format!("sin({})", c.src)),
+                    UnOp::Cos => Code::atom(format!("cos({})", c.src)),
+                    UnOp::Tan => Code::atom(format!("tan({})", c.src)),
+                    UnOp::Exp => Code::atom(format!("exp({})", c.src)),
+                    UnOp::Log => Code::atom(format!("log({})", c.src)),
+                    UnOp::Sqrt => Code::atom(format!("sqrt({})", c.src)),
+                    UnOp::Asinh => Code::atom(format!("asinh({})", c.src)),
+                    UnOp::Acosh => Code::atom(format!("acosh({})", c.src)),
+                    UnOp::Atanh => Code::atom(format!("atanh({})", c.src)),
+                    // n! is gamma(n + 1)
+                    UnOp::Factorial => Code::atom(format!("tgamma({} + 1)", c.wrapped())),
+                    UnOp::Percent => Code::compound(format!("{} / 100.0", c.wrapped()))
+                }
+            },
+            Expr::Number(n) => {
+                if n.is_nan() {
+                    Code::atom("NAN".to_owned())
+                } else if n.is_infinite() {
+                    Code::compound(format!("{}INFINITY", if *n < 0.0 { "-" } else { "" }))
+                } else if n.is_sign_negative() {
+                    Code::compound(format!("{:?}", n))
+                } else {
+                    Code::atom(format!("{:?}", n))
+                }
+            },
+            Expr::Complex(re, im) if *im == 0.0 => Expr::Number(*re).c_code(),
+            Expr::Complex(_, _) => Code::atom("NAN".to_owned()),
+            Expr::Variable(name) => Code::atom(name.clone()),
+            // C has no expressions that bind names
+            Expr::Let(..) => self.inline_lets().c_code(),
+            Expr::Eof => Code::atom("NAN".to_owned())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::process::Command;
+    use super::*;
+
+    #[test]
+    fn rust_closure_single_variable() {
+        let ast = Ast::string_to_ast("sin(x) + cos(x)");
+        assert_eq!(ast.to_rust_closure(), "|x: f64| -> f64 { x.sin() + x.cos() }");
+
+        let f = |x: f64| -> f64 { x.sin() + x.cos() };
+        for x in [0.0, 1.0, -2.5] {
+            assert_eq!(f(x), ast.eval_point(x).unwrap());
+        }
+    }
+
+    #[test]
+    fn rust_closure_multiple_variables() {
+        let ast = Ast::string_to_ast("-y^2 * ln(x + 1) / 3!");
+        assert_eq!(
+            ast.to_rust_closure(),
+            "|x: f64, y: f64| -> f64 { ((-y).powf(2.0_f64) * (x + 1.0_f64).ln()) / ({ let n = 3.0_f64; \
+             if (0.0..=20.0).contains(&n) && n.fract() == 0.0 { (1..=(n as u64)).product::<u64>() as f64 } else { f64::NAN } }) }"
+        );
+
+        let f = |x: f64, y: f64| -> f64 { ((-y).powf(2.0_f64) * (x + 1.0_f64).ln()) / ({ let n = 3.0_f64;
+             if (0.0..=20.0).contains(&n) && n.fract() == 0.0 { (1..=(n as u64)).product::<u64>() as f64 } else { f64::NAN } }) };
+        for (x, y) in [(0.5, 2.0), (3.0, -1.0)] {
+            let vars = HashMap::from([("x".to_owned(), x), ("y".to_owned(), y)]);
+            assert_eq!(f(x, y), ast.expr.eval_with(&vars).unwrap());
+        }
+    }
+
+    #[test]
+    fn rust_closure_literal_receivers() {
+        assert_eq!(Ast::string_to_ast("2^x").to_rust_closure(), "|x: f64| -> f64 { 2.0_f64.powf(x) }");
+        assert_eq!(Ast::string_to_ast("sin(1) + (-2)^2").to_rust_closure(), "|| -> f64 { 1.0_f64.sin() + (-2.0_f64).powf(2.0_f64) }");
+
+        let f = |x: f64| -> f64 { 2.0_f64.powf(x) };
+        assert_eq!(f(3.0), 8.0);
+        let g = || -> f64 { 1.0_f64.sin() + (-2.0_f64).powf(2.0_f64) };
+        assert_eq!(g(), Ast::string_to_ast("sin(1) + (-2)^2").eval());
+    }
+
+    // Compile the closure into a program printing its value at the arguments, and run it
+    fn run_rust_closure(ast: &Ast, args: &str) -> Option<f64> {
+        if Command::new("rustc").arg("--version").output().is_err() {
+            return None
+        }
+
+        let dir = std::env::temp_dir().join(format!("ast_calc_rust_codegen_{}_{}", std::process::id(), args.len()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (src, bin) = (dir.join("main.rs"), dir.join("main.out"));
+        let program = format!("fn main() {{ let f = {}; print!(\"{{:?}}\", f({})); }}\n", ast.to_rust_closure(), args);
+        std::fs::write(&src, program).unwrap();
+
+        let status = Command::new("rustc").arg(&src).arg("-o").arg(&bin).status().unwrap();
+        assert!(status.success());
+        let output = Command::new(&bin).output().unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+        Some(String::from_utf8(output.stdout).unwrap().parse().unwrap())
+    }
+
+    // Compile the function with a main printing its value at the arguments, and run it
+    fn run_c_function(ast: &Ast, args: &str) -> Option<f64> {
+        if Command::new("cc").arg("--version").output().is_err() {
+            return None
+        }
+
+        let dir = std::env::temp_dir().join(format!("ast_calc_codegen_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (src, bin) = (dir.join(format!("{}.c", args.len())), dir.join(format!("{}.out", args.len())));
+        let program = format!(
+            "#include <math.h>\n#include <stdio.h>\n{}\nint main(void) {{ printf(\"%.17g\", expr({})); return 0; }}\n",
+            ast.to_c_code(), args
+        );
+        std::fs::write(&src, program).unwrap();
+
+        let status = Command::new("cc").arg(&src).arg("-o").arg(&bin).arg("-lm").status().unwrap();
+        assert!(status.success());
+        let output = Command::new(&bin).output().unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+        Some(String::from_utf8(output.stdout).unwrap().parse().unwrap())
+    }
+
+    #[test]
+    fn c_code_output() {
+        let ast = Ast::string_to_ast("sin(x) + cos(x)");
+        assert_eq!(ast.to_c_code(), "double expr(double x) { return sin(x) + cos(x); }");
+        assert_eq!(ast.to_c_function("f"), "double f(double x) { return sin(x) + cos(x); }");
+        assert_eq!(Ast::string_to_ast("ln(2)^3!").to_c_code(), "double expr(void) { return pow(log(2.0), tgamma(3.0 + 1)); }");
+        assert_eq!(Ast::string_to_ast("2 * logb(2, x)").to_c_code(), "double expr(double x) { return 2.0 * (log(x) / log(2.0)); }");
+        assert_eq!(Ast::string_to_ast("logb(2, x)").to_rust_closure(), "|x: f64| -> f64 { x.log(2.0_f64) }");
+    }
+
+    #[test]
+    fn c_code_compiles() {
+        let ast = Ast::string_to_ast("sin(x) + cos(x)");
+        if let Some(val) = run_c_function(&ast, "1.5") {
+            assert!((val - ast.eval_point(1.5).unwrap()).abs() < 1e-12);
+        }
+
+        let ast = Ast::string_to_ast("-y^2 * ln(x + 1) / 3! - 2^0.5");
+        if let Some(val) = run_c_function(&ast, "0.5, 2.0") {
+            let vars = HashMap::from([("x".to_owned(), 0.5), ("y".to_owned(), 2.0)]);
+            assert!((val - ast.expr.eval_with(&vars).unwrap()).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn rust_closure_compiles() {
+        let ast = Ast::string_to_ast("2^sin(1) + logb(2, 8) * sqrt(2 * 3)");
+        if let Some(val) = run_rust_closure(&ast, "") {
+            assert_eq!(val, ast.eval());
+        }
+
+        let ast = Ast::string_to_ast("x! + 2^y");
+        if let Some(val) = run_rust_closure(&ast, "5.0, 0.5") {
+            let vars = HashMap::from([("x".to_owned(), 5.0), ("y".to_owned(), 0.5)]);
+            assert_eq!(val, ast.expr.eval_with(&vars).unwrap());
+        }
+        // Factorials without an exact value are NaN rather than a panic or a wrong answer
+        for args in ["21.0, 0.0", "-3.0, 0.0", "1.5, 0.0"] {
+            if let Some(val) = run_rust_closure(&ast, args) {
+                assert!(val.is_nan());
+            }
+        }
+    }
+
+    #[test]
+    fn rust_closure_constant() {
+        assert_eq!(Ast::string_to_ast("2 - -1").to_rust_closure(), "|| -> f64 { 2.0_f64 - (-1.0_f64) }");
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+use crate::ast::Ast;
+use crate::error::EvalError;
+use crate::parse::{factorial, BinOp, Expr, UnOp};
+
+/// A complex number re + im * i
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Complex {
+    pub(crate) re: f64,
+    pub(crate) im: f64
+}
+
+impl Complex {
+    pub(crate) fn new(re: f64, im: f64) -> Self {
+        Self {
+            re,
+            im
+        }
+    }
+
+    fn real(re: f64) -> Self {
+        Self::new(re, 0.0)
+    }
+
+    fn abs(self) -> f64 {
+        self.re.hypot(self.im)
+    }
+
+    fn arg(self) -> f64 {
+        self.im.atan2(self.re)
+    }
+
+    fn exp(self) -> Self {
+        let r = self.re.exp();
+        Self::new(r * self.im.cos(), r * self.im.sin())
+    }
+
+    // Principal branch of the logarithm
+    fn ln(self) -> Self {
+        Self::new(self.abs().ln(), self.arg())
+    }
+
+    fn sqrt(self) -> Self {
+        let r = self.abs().sqrt();
+        let theta = self.arg() / 2.0;
+        Self::new(r * theta.cos(), r * theta.sin())
+    }
+
+    fn sin(self) -> Self {
+        Self::new(self.re.sin() * self.im.cosh(), self.re.cos() * self.im.sinh())
+    }
+
+    fn cos(self) -> Self {
+        Self::new(self.re.cos() * self.im.cosh(), -self.re.sin() * self.im.sinh())
+    }
+
+    fn asinh(self) -> Self {
+        (self + (self * self + Self::real(1.0)).sqrt()).ln()
+    }
+
+    // Taking the square roots separately puts the branch cut along the real axis below 1
+    fn acosh(self) -> Self {
+        (self + (self + Self::real(1.0)).sqrt() * (self - Self::real(1.0)).sqrt()).ln()
+    }
+
+    fn atanh(self) -> Self {
+        ((Self::real(1.0) + self).ln() - (Self::real(1.0) - self).ln()) / Self::real(2.0)
+    }
+
+    fn powc(self, w: Self) -> Self {
+        if self == Self::real(0.0) {
+            // Match the real convention that 0^0 == 1
+            return if w == Self::real(0.0) { Self::real(1.0) } else { self }
+        }
+        (w * self.ln()).exp()
+    }
+}
+
+impl Add for Complex {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Self::new(self.re * rhs.re - self.im * rhs.im, self.re * rhs.im + self.im * rhs.re)
+    }
+}
+
+impl Div for Complex {
+    type Output = Self;
+
+    fn div(self, rhs: Self) -> Self {
+        let denom = rhs.re * rhs.re + rhs.im * rhs.im;
+        Self::new(
+            (self.re * rhs.re + self.im * rhs.im) / denom,
+            (self.im * rhs.re - self.re * rhs.im) / denom
+        )
+    }
+}
+
+impl Neg for Complex {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        // Avoid producing -0.0 as the imaginary part of negated reals, which would put
+        // them on the other side of the branch cut for ln and sqrt
+        Self::new(-self.re, 0.0 - self.im)
+    }
+}
+
+impl Expr {
+    pub(crate) fn eval_complex(&self, vars: &HashMap<String, f64>) -> Result<Complex, EvalError> {
+        let val = match self {
+            Expr::BinaryOp(op, e1, e2) => {
+                let (v1, v2) = (e1.eval_complex(vars)?, e2.eval_complex(vars)?);
+                match op {
+                    BinOp::Plus => v1 + v2,
+                    BinOp::Minus => v1 - v2,
+                    BinOp::Multiply => v1 * v2,
+                    BinOp::Divide => v1 / v2,
+                    BinOp::Power => v1.powc(v2),
+                    BinOp::LogBase => {
+                        if v1 == Complex::real(1.0) {
+                            return Err(EvalError::InvalidArgument("logb is undefined for base 1".to_owned()))
+                        }
+                        v2.ln() / v1.ln()
+                    }
+                }
+            },
+            Expr::UnaryOp(op, e) => {
+                let val = e.eval_complex(vars)?;
+                match op {
+                    UnOp::Negative => -val,
+                    UnOp::Sin => val.sin(),
+                    UnOp::Cos => val.cos(),
+                    UnOp::Tan => val.sin() / val.cos(),
+                    UnOp::Exp => val.exp(),
+                    UnOp::Log => val.ln(),
+                    UnOp::Sqrt => val.sqrt(),
+                    UnOp::Asinh => val.asinh(),
+                    UnOp::Acosh => val.acosh(),
+                    UnOp::Atanh => val.atanh(),
+                    UnOp::Factorial => {
+                        if val.im != 0.0 {
+                            return Err(EvalError::NotReal)
+                        }
+                        Complex::real(factorial(val.re)?)
+                    },
+                    UnOp::Percent => val / Complex::real(100.0)
+                }
+            },
+            Expr::Complex(re, im) => Complex::new(*re, *im),
+            Expr::Let(..) => self.inline_lets().eval_complex(vars)?,
+            e => Complex::real(e.eval_with(vars)?)
+        };
+
+        Ok(val)
+    }
+}
+
+impl Ast {
+    /// Evaluate the expression over the complex numbers, returning (re, im)
+    pub fn eval_complex(&self) -> Result<(f64, f64), EvalError> {
+        let val = self.expr.eval_complex(&HashMap::new())?;
+        Ok((val.re, val.im))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: (f64, f64), expected: (f64, f64)) {
+        assert!((actual.0 - expected.0).abs() < 1e-12, "{:?} != {:?}", actual, expected);
+        assert!((actual.1 - expected.1).abs() < 1e-12, "{:?} != {:?}", actual, expected);
+    }
+
+    #[test]
+    fn complex_sqrt() {
+        assert_close(Ast::string_to_ast("sqrt(-1)").eval_complex().unwrap(), (0.0, 1.0));
+        assert_close(Ast::string_to_ast("sqrt(-4) * i").eval_complex().unwrap(), (-2.0, 0.0));
+    }
+
+    #[test]
+    fn complex_euler_identity() {
+        assert_close(Ast::string_to_ast("exp(i * pi) + 1").eval_complex().unwrap(), (0.0, 0.0));
+        assert_close(Ast::string_to_ast("i^2").eval_complex().unwrap(), (-1.0, 0.0));
+        assert_close(Ast::string_to_ast("ln(-1)").eval_complex().unwrap(), (0.0, std::f64::consts::PI));
+    }
+
+    #[test]
+    fn complex_arithmetic() {
+        assert_close(Ast::string_to_ast("(1 + 2*i) * (3 - i)").eval_complex().unwrap(), (5.0, 5.0));
+        assert_close(Ast::string_to_ast("(1 + i) / (1 - i)").eval_complex().unwrap(), (0.0, 1.0));
+        assert_close(Ast::string_to_ast("sin(i)^2 + cos(i)^2").eval_complex().unwrap(), (1.0, 0.0));
+        assert_close(Ast::string_to_ast("3! + 0^0").eval_complex().unwrap(), (7.0, 0.0));
+        assert_eq!(Ast::string_to_ast("i!").eval_complex(), Err(EvalError::NotReal));
+    }
+
+    #[test]
+    fn complex_inverse_hyperbolic() {
+        // Outside the real domains these land on the imaginary axis
+        assert_close(Ast::string_to_ast("acosh(0.5)").eval_complex().unwrap(), (0.0, std::f64::consts::FRAC_PI_3));
+        assert_close(Ast::string_to_ast("asinh(i)").eval_complex().unwrap(), (0.0, std::f64::consts::FRAC_PI_2));
+        assert_close(Ast::string_to_ast("atanh(i)").eval_complex().unwrap(), (0.0, std::f64::consts::FRAC_PI_4));
+        assert_close(Ast::string_to_ast("acosh(2) + atanh(0.5)").eval_complex().unwrap(), (2f64.acosh() + 0.5f64.atanh(), 0.0));
+    }
+
+    #[test]
+    fn real_eval_rejects_imaginary() {
+        assert_eq!(Ast::string_to_ast("1 + i").try_eval(), Err(EvalError::NotReal));
+    }
+}
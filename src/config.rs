@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use crate::ast::Ast;
+use crate::context::EvalContext;
+use crate::error::{EvalError, ParseError};
+use crate::lex::TokenStream;
+use crate::parse::{factorial, parse_expr_with_options, Expr, ParseOptions, UnOp, MAX_FACTORIAL};
+
+/// Options for parsing and evaluating expressions, built up like
+/// `AstConfig::default().degrees_mode(true).max_depth(256)`
+#[derive(Debug, Clone, PartialEq)]
+pub struct AstConfig {
+    /// Deepest nesting of operators allowed in a parsed tree, checked once parsing finishes
+    pub max_depth: usize,
+    /// Largest n whose factorial may be computed, exactly up to 20 and in floating point above
+    pub max_factorial: u64,
+    /// Take the arguments of sin, cos and tan in degrees rather than radians
+    pub degrees_mode: bool,
+    /// Fail with `EvalError::NotANumber` as soon as any step produces NaN
+    pub strict_nan: bool,
+    /// Read an operand directly following another, as in 2x or 3(x + 1), as a product
+    pub implicit_multiply: bool,
+}
+
+impl Default for AstConfig {
+    fn default() -> Self {
+        Self {
+            max_depth: 512,
+            max_factorial: MAX_FACTORIAL,
+            degrees_mode: false,
+            strict_nan: false,
+            implicit_multiply: false,
+        }
+    }
+}
+
+impl AstConfig {
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn max_factorial(mut self, max_factorial: u64) -> Self {
+        self.max_factorial = max_factorial;
+        self
+    }
+
+    pub fn degrees_mode(mut self, degrees_mode: bool) -> Self {
+        self.degrees_mode = degrees_mode;
+        self
+    }
+
+    pub fn strict_nan(mut self, strict_nan: bool) -> Self {
+        self.strict_nan = strict_nan;
+        self
+    }
+
+    pub fn implicit_multiply(mut self, implicit_multiply: bool) -> Self {
+        self.implicit_multiply = implicit_multiply;
+        self
+    }
+
+    fn factorial(&self, val: f64) -> Result<f64, EvalError> {
+        if val.fract() == 0.0 && val > self.max_factorial as f64 {
+            return Err(EvalError::FactorialOverflow(val as u64))
+        }
+        if val.fract() == 0.0 && val > MAX_FACTORIAL as f64 {
+            let product = (1..=val as u64).fold(1.0, |acc, k| acc * k as f64);
+            return if product.is_finite() { Ok(product) } else { Err(EvalError::FactorialOverflow(val as u64)) }
+        }
+        factorial(val)
+    }
+}
+
+impl Ast {
+    /// Parse the string into an AST using the parsing options of `config`
+    pub fn from_str_with_config(s: &str, config: &AstConfig) -> Result<Ast, ParseError> {
+        let functions = HashMap::new();
+        let options = ParseOptions { functions: &functions, implicit_multiply: config.implicit_multiply };
+        let expr = parse_expr_with_options(&mut TokenStream::new(s), &options)?;
+        if expr.depth() > config.max_depth {
+            return Err(ParseError::TooDeep { max_depth: config.max_depth })
+        }
+        Ok(Ast::new(expr))
+    }
+
+    /// Evaluate the expression using the evaluation options of `config`,
+    /// looking up its variables in the context
+    pub fn eval_with_config(&self, ctx: &EvalContext, config: &AstConfig) -> Result<f64, EvalError> {
+        self.expr.eval_with_config(&ctx.variables, config)
+    }
+}
+
+impl Expr {
+    // Levels of operators above the deepest leaf, so a lone number has depth 0
+    fn depth(&self) -> usize {
+        match self {
+            Expr::BinaryOp(_, e1, e2) => 1 + e1.depth().max(e2.depth()),
+            Expr::UnaryOp(_, e) => 1 + e.depth(),
+            Expr::Let(_, value, body) => value.depth().max(body.depth()),
+            Expr::Number(_) | Expr::Complex(_, _) | Expr::Variable(_) | Expr::Eof => 0
+        }
+    }
+
+    fn eval_with_config(&self, vars: &HashMap<String, f64>, config: &AstConfig) -> Result<f64, EvalError> {
+        let val = match self {
+            Expr::BinaryOp(op, e1, e2) => op.apply(e1.eval_with_config(vars, config)?, e2.eval_with_config(vars, config)?)?,
+            Expr::UnaryOp(op, e) => {
+                let val = e.eval_with_config(vars, config)?;
+                match op {
+                    UnOp::Sin | UnOp::Cos | UnOp::Tan if config.degrees_mode => op.apply(val.to_radians())?,
+                    UnOp::Factorial => config.factorial(val)?,
+                    op => op.apply(val)?
+                }
+            },
+            Expr::Let(name, value, body) => {
+                let mut vars = vars.clone();
+                vars.insert(name.clone(), value.eval_with_config(&vars, config)?);
+                body.eval_with_config(&vars, config)?
+            },
+            e => e.eval_with(vars)?
+        };
+        if config.strict_nan && val.is_nan() {
+            return Err(EvalError::NotANumber)
+        }
+        Ok(val)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(s: &str, config: &AstConfig) -> Result<f64, EvalError> {
+        Ast::from_str_with_config(s, config).unwrap().eval_with_config(&EvalContext::new(), config)
+    }
+
+    #[test]
+    fn builder_sets_fields() {
+        let config = AstConfig::default().degrees_mode(true).max_depth(256);
+        assert!(config.degrees_mode);
+        assert_eq!(config.max_depth, 256);
+        assert_eq!(config, AstConfig { degrees_mode: true, max_depth: 256, ..AstConfig::default() });
+    }
+
+    #[test]
+    fn max_depth() {
+        let config = AstConfig::default().max_depth(3);
+        assert!(Ast::from_str_with_config("sin(1 + 2 * x)", &config).is_ok());
+        assert_eq!(Ast::from_str_with_config("sin(1 + 2 * x^2)", &config), Err(ParseError::TooDeep { max_depth: 3 }));
+        let config = AstConfig::default().max_depth(50);
+        // Parentheses alone do not add depth
+        let nested = format!("{}1{}", "(".repeat(60), ")".repeat(60));
+        assert!(Ast::from_str_with_config(&nested, &config).is_ok());
+        let negated = format!("{}1", "-".repeat(60));
+        assert_eq!(Ast::from_str_with_config(&negated, &config), Err(ParseError::TooDeep { max_depth: 50 }));
+    }
+
+    #[test]
+    fn max_factorial() {
+        assert_eq!(eval("21!", &AstConfig::default()), Err(EvalError::FactorialOverflow(21)));
+        assert_eq!(eval("5!", &AstConfig::default().max_factorial(4)), Err(EvalError::FactorialOverflow(5)));
+        let config = AstConfig::default().max_factorial(170);
+        assert_eq!(eval("21! / 20!", &config), Ok(21.0));
+        assert!(eval("170!", &config).unwrap().is_finite());
+        assert_eq!(eval("2.5!", &config), Err(EvalError::NonIntegerFactorial(2.5)));
+        assert_eq!(eval("171!", &AstConfig::default().max_factorial(200)), Err(EvalError::FactorialOverflow(171)));
+    }
+
+    #[test]
+    fn degrees_mode() {
+        let config = AstConfig::default().degrees_mode(true);
+        assert!((eval("sin(30)", &config).unwrap() - 0.5).abs() < 1e-12);
+        assert!((eval("cos(60) + tan(45)", &config).unwrap() - 1.5).abs() < 1e-12);
+        assert!((eval("sin(30)", &AstConfig::default()).unwrap() - 30f64.sin()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn strict_nan() {
+        assert!(eval("sqrt(0 - 1) * 0", &AstConfig::default()).unwrap().is_nan());
+        assert_eq!(eval("sqrt(0 - 1) * 0", &AstConfig::default().strict_nan(true)), Err(EvalError::NotANumber));
+        assert_eq!(eval("inf - inf", &AstConfig::default().strict_nan(true)), Err(EvalError::NotANumber));
+        assert_eq!(eval("1 / 0", &AstConfig::default().strict_nan(true)), Ok(f64::INFINITY));
+    }
+
+    #[test]
+    fn implicit_multiply() {
+        let config = AstConfig::default().implicit_multiply(true);
+        let parse = |s| Ast::from_str_with_config(s, &config);
+        assert_eq!(parse("2x"), Ok(Ast::string_to_ast("2 * x")));
+        assert_eq!(parse("3(x + 1)^2"), Ok(Ast::string_to_ast("3 * (x + 1)^2")));
+        assert_eq!(parse("2 pi r"), Ok(Ast::string_to_ast("2 * pi * r")));
+        assert_eq!(parse("x sin(y) - 1"), Ok(Ast::string_to_ast("x * sin(y) - 1")));
+        assert_eq!(parse("(a)(b) / c"), Ok(Ast::string_to_ast("a * b / c")));
+        assert!(Ast::from_str_with_config("2x", &AstConfig::default()).is_err());
+
+        let mut ctx = EvalContext::new();
+        ctx.set_variable("x", 4.0);
+        assert_eq!(parse("2x + 1").unwrap().eval_with_config(&ctx, &config), Ok(9.0));
+    }
+}
@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use crate::ast::Ast;
+use crate::error::{EvalError, ParseError};
+use crate::lex::TokenStream;
+use crate::parse::{parse_expr_with, Expr};
+
+/// A user defined function such as f(x, y) = x^2 + y
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    params: Vec<String>,
+    body: Expr
+}
+
+impl Function {
+    pub fn params(&self) -> &[String] {
+        &self.params
+    }
+
+    pub fn body(&self) -> &Expr {
+        &self.body
+    }
+
+    // Expand a call by substituting the arguments for the parameters in the body
+    pub(crate) fn inline(&self, name: &str, args: Vec<Expr>) -> Result<Expr, ParseError> {
+        if args.len() != self.params.len() {
+            return Err(ParseError::WrongArity { name: name.to_owned(), expected: self.params.len(), found: args.len() })
+        }
+        let bindings = self.params.iter().cloned().zip(args).collect();
+        Ok(self.body.substitute(&bindings))
+    }
+}
+
+/// Variables and functions defined over the course of a session
+/// Calls to functions are inlined when an expression is parsed with `EvalContext::parse`,
+/// while variables are looked up when it is evaluated
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EvalContext {
+    pub(crate) variables: HashMap<String, f64>,
+    functions: HashMap<String, Function>
+}
+
+impl EvalContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_variable(&mut self, name: &str, val: f64) {
+        self.variables.insert(name.to_owned(), val);
+    }
+
+    pub fn variable(&self, name: &str) -> Option<f64> {
+        self.variables.get(name).copied()
+    }
+
+    /// Define `name(params) = body`, where the body may call previously defined functions
+    pub fn define_function(&mut self, name: &str, params: Vec<String>, body: &str) -> Result<(), ParseError> {
+        let body = self.parse(body)?.expr;
+        self.functions.insert(name.to_owned(), Function { params, body });
+        Ok(())
+    }
+
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        self.functions.get(name)
+    }
+
+    /// All variables with their values, sorted by name
+    pub fn variables(&self) -> Vec<(&str, f64)> {
+        let mut vars: Vec<(&str, f64)> = self.variables.iter().map(|(name, val)| (name.as_str(), *val)).collect();
+        vars.sort_by(|a, b| a.0.cmp(b.0));
+        vars
+    }
+
+    /// All functions with their definitions, sorted by name
+    pub fn functions(&self) -> Vec<(&str, &Function)> {
+        let mut fns: Vec<(&str, &Function)> = self.functions.iter().map(|(name, f)| (name.as_str(), f)).collect();
+        fns.sort_by(|a, b| a.0.cmp(b.0));
+        fns
+    }
+
+    /// Remove the variable or function with this name, returning whether anything was removed
+    pub fn remove(&mut self, name: &str) -> bool {
+        let removed_var = self.variables.remove(name).is_some();
+        let removed_fn = self.functions.remove(name).is_some();
+        removed_var || removed_fn
+    }
+
+    pub fn clear(&mut self) {
+        self.variables.clear();
+        self.functions.clear();
+    }
+
+    /// Parse the string into an AST, inlining calls to the functions defined so far
+    pub fn parse(&self, s: &str) -> Result<Ast, ParseError> {
+        let expr = parse_expr_with(&mut TokenStream::new(s), &self.functions)?;
+        Ok(Ast::new(expr))
+    }
+}
+
+impl Ast {
+    /// Evaluate the expression, looking up its variables in the context
+    pub fn eval_with_context(&self, ctx: &EvalContext) -> Result<f64, EvalError> {
+        self.expr.eval_with(&ctx.variables)
+    }
+
+    /// Replace every variable that has a value in the context with that value
+    pub fn bind_variables(&self, ctx: &EvalContext) -> Ast {
+        let bindings = ctx.variables.iter().map(|(name, val)| (name.clone(), Expr::Number(*val))).collect();
+        Ast::new(self.expr.substitute(&bindings))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn context_variables() {
+        let mut ctx = EvalContext::new();
+        ctx.set_variable("y", 2.0);
+        ctx.set_variable("x", 3.0);
+        assert_eq!(ctx.variables(), vec![("x", 3.0), ("y", 2.0)]);
+
+        let ast = ctx.parse("x * y + z").unwrap();
+        assert_eq!(ast.eval_with_context(&ctx), Err(EvalError::UnboundVariable("z".to_owned())));
+        ctx.set_variable("z", 1.0);
+        assert_eq!(ast.eval_with_context(&ctx), Ok(7.0));
+        assert_eq!(ast.bind_variables(&ctx).try_eval(), Ok(7.0));
+    }
+
+    #[test]
+    fn context_functions() {
+        let mut ctx = EvalContext::new();
+        ctx.define_function("sq", vec!["x".to_owned()], "x^2").unwrap();
+        ctx.define_function("hyp", vec!["a".to_owned(), "b".to_owned()], "sqrt(sq(a) + sq(b))").unwrap();
+        assert_eq!(ctx.parse("hyp(3, 2 * 2)").unwrap().try_eval(), Ok(5.0));
+        assert_eq!(ctx.parse("sq(1 + 1)!").unwrap().try_eval(), Ok(24.0));
+        assert_eq!(ctx.parse("sq(1, 2)"), Err(ParseError::WrongArity { name: "sq".to_owned(), expected: 1, found: 2 }));
+        assert_eq!(ctx.parse("sq(1"), Err(ParseError::MissingClosingParen));
+    }
+
+    #[test]
+    fn context_remove_and_clear() {
+        let mut ctx = EvalContext::new();
+        ctx.set_variable("x", 1.0);
+        ctx.define_function("f", vec!["x".to_owned()], "x + 1").unwrap();
+        assert!(ctx.remove("f"));
+        assert!(!ctx.remove("f"));
+        assert_eq!(ctx.functions().len(), 0);
+        ctx.clear();
+        assert_eq!(ctx, EvalContext::new());
+    }
+}
@@ -0,0 +1,125 @@
+use crate::ast::Ast;
+use crate::parse::Expr;
+
+impl Expr {
+    // Count each operator subtree, keeping them in the order they are first reached
+    // Leaves are cheaper to read than any binding, so they are never counted
+    fn count_subtrees(&self, counts: &mut Vec<(Expr, usize)>) {
+        let children = match self {
+            Expr::BinaryOp(_, e1, e2) => vec![e1, e2],
+            Expr::UnaryOp(_, e) => vec![e],
+            Expr::Let(_, value, body) => {
+                value.count_subtrees(counts);
+                body.count_subtrees(counts);
+                return
+            },
+            Expr::Number(_) | Expr::Complex(_, _) | Expr::Variable(_) | Expr::Eof => return
+        };
+        match counts.iter_mut().find(|(e, _)| e == self) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((self.clone(), 1))
+        }
+        children.into_iter().for_each(|child| child.count_subtrees(counts));
+    }
+
+    fn replace_all(&self, target: &Expr, replacement: &Expr) -> Expr {
+        if self == target {
+            return replacement.clone()
+        }
+        match self {
+            Expr::BinaryOp(op, e1, e2) => {
+                Expr::BinaryOp(*op, Box::new(e1.replace_all(target, replacement)), Box::new(e2.replace_all(target, replacement)))
+            },
+            Expr::UnaryOp(op, e) => Expr::UnaryOp(*op, Box::new(e.replace_all(target, replacement))),
+            e => e.clone()
+        }
+    }
+}
+
+impl Ast {
+    /// The subexpressions that appear more than once along with how often they appear,
+    /// most frequent first and in order of first appearance among equally frequent ones
+    pub fn common_subexpressions(&self) -> Vec<(Expr, usize)> {
+        let mut counts = Vec::new();
+        self.expr.count_subtrees(&mut counts);
+        counts.retain(|(_, count)| *count > 1);
+        counts.sort_by(|(_, a), (_, b)| b.cmp(a));
+        counts
+    }
+
+    /// Bind each repeated subexpression to a new variable t0, t1, ... with `Expr::Let`,
+    /// so that evaluating the result computes each of them only once
+    /// The smallest are bound first, so later bindings can be built from earlier ones
+    pub fn eliminate_common_subexpressions(&self) -> Ast {
+        let taken = self.expr.free_variables();
+        let mut names = (0..).map(|k| format!("t{}", k)).filter(|name| !taken.contains(name));
+        let mut body = self.expr.inline_lets();
+        let mut bindings = Vec::new();
+        while let Some((repeated, _)) = Ast::new(body.clone()).common_subexpressions()
+            .into_iter()
+            .min_by_key(|(e, _)| e.complexity())
+        {
+            let name = names.next().expect("There are always more names");
+            body = body.replace_all(&repeated, &Expr::var(&name));
+            bindings.push((name, repeated));
+        }
+        let expr = bindings.into_iter()
+            .rev()
+            .fold(body, |body, (name, value)| Expr::Let(name, Box::new(value), Box::new(body)));
+        Ast::new(expr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::*;
+
+    fn expr(s: &str) -> Expr {
+        Ast::string_to_ast(s).expr
+    }
+
+    #[test]
+    fn common_subexpressions_by_count() {
+        assert_eq!(
+            Ast::string_to_ast("sin(x) * sin(x) + cos(x) * cos(x)").common_subexpressions(),
+            vec![(expr("sin(x)"), 2), (expr("cos(x)"), 2)]
+        );
+        assert_eq!(
+            Ast::string_to_ast("sin(y) + sin(y) + (x + 1) * ln(x + 1) / (x + 1)").common_subexpressions(),
+            vec![(expr("x + 1"), 3), (expr("sin(y)"), 2)]
+        );
+        assert!(Ast::string_to_ast("x * x + y").common_subexpressions().is_empty());
+    }
+
+    #[test]
+    fn eliminate_common_subexpressions() {
+        for (s, expected) in [
+            ("sin(x) * sin(x) + cos(x) * cos(x)", "let t0 = sin(x) in let t1 = cos(x) in t0 * t0 + t1 * t1"),
+            ("(x + 1)^2 + (x + 1)^2", "let t0 = x + 1 in let t1 = t0^2 in t1 + t1"),
+            ("t0 * exp(t0) - exp(t0)", "let t1 = exp(t0) in t0 * t1 - t1"),
+            ("x * y + 1", "x * y + 1"),
+        ] {
+            let ast = Ast::string_to_ast(s);
+            let eliminated = ast.eliminate_common_subexpressions();
+            assert_eq!(eliminated.expr.to_string(), expected);
+            assert_eq!(eliminated.expr.inline_lets(), ast.expr);
+            assert_eq!(eliminated.expr.free_variables(), ast.expr.free_variables());
+        }
+    }
+
+    #[test]
+    fn eliminated_evaluates_the_same_in_fewer_steps() {
+        let ast = Ast::string_to_ast("sin(x) * sin(x) + cos(x) * cos(x) + exp(sin(x))");
+        let eliminated = ast.eliminate_common_subexpressions();
+        assert!(eliminated.steps_to_evaluate() < ast.steps_to_evaluate());
+        for x in [0.0, 0.5, 2.0] {
+            let vars = HashMap::from([("x".to_owned(), x)]);
+            assert_eq!(eliminated.eval_multivar(&vars), ast.eval_multivar(&vars));
+        }
+        assert_eq!(
+            eliminated.to_rust_closure(),
+            "|x: f64| -> f64 { { let t0 = x.sin(); { let t1 = x.cos(); ((t0 * t0) + (t1 * t1)) + t0.exp() } } }"
+        );
+    }
+}
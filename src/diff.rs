@@ -0,0 +1,115 @@
+use crate::parse::{BinOp, Expr, UnOp};
+
+fn bin(op: BinOp, e1: Expr, e2: Expr) -> Expr {
+    Expr::BinaryOp(op, Box::new(e1), Box::new(e2))
+}
+
+fn un(op: UnOp, e: Expr) -> Expr {
+    Expr::UnaryOp(op, Box::new(e))
+}
+
+impl Expr {
+    /// Differentiate symbolically with respect to `var`, folding constants and removing identities like x * 1
+    /// Returns None for operations without a derivative rule, such as factorials of `var`
+    pub(crate) fn diff(&self, var: &str) -> Option<Expr> {
+        Some(self.diff_raw(var)?.fold_constants().simplify_identities())
+    }
+
+    fn diff_raw(&self, var: &str) -> Option<Expr> {
+        if !self.is_eof() && !self.free_variables().contains(var) {
+            return Some(Expr::zero())
+        }
+
+        let d = match self {
+            Expr::Variable(_) => Expr::one(),
+            Expr::BinaryOp(op, e1, e2) => {
+                let (e1, e2) = (e1.as_ref().clone(), e2.as_ref().clone());
+                match op {
+                    BinOp::Plus | BinOp::Minus => bin(*op, e1.diff_raw(var)?, e2.diff_raw(var)?),
+                    BinOp::Multiply => bin(
+                        BinOp::Plus,
+                        bin(BinOp::Multiply, e1.diff_raw(var)?, e2.clone()),
+                        bin(BinOp::Multiply, e1, e2.diff_raw(var)?)
+                    ),
+                    BinOp::Divide => bin(
+                        BinOp::Divide,
+                        bin(
+                            BinOp::Minus,
+                            bin(BinOp::Multiply, e1.diff_raw(var)?, e2.clone()),
+                            bin(BinOp::Multiply, e1, e2.diff_raw(var)?)
+                        ),
+                        bin(BinOp::Power, e2, Expr::const_(2.0))
+                    ),
+                    // Power rule for constant exponents, otherwise differentiate exp(e2 * ln(e1))
+                    BinOp::Power if !e2.free_variables().contains(var) => bin(
+                        BinOp::Multiply,
+                        bin(BinOp::Multiply, e2.clone(), bin(BinOp::Power, e1.clone(), bin(BinOp::Minus, e2, Expr::one()))),
+                        e1.diff_raw(var)?
+                    ),
+                    BinOp::Power => {
+                        let exponent = bin(BinOp::Multiply, e2, un(UnOp::Log, e1));
+                        bin(BinOp::Multiply, self.clone(), exponent.diff_raw(var)?)
+                    },
+                    // logb(b, x) is ln(x) / ln(b)
+                    BinOp::LogBase => bin(BinOp::Divide, un(UnOp::Log, e2), un(UnOp::Log, e1)).diff_raw(var)?
+                }
+            },
+            Expr::UnaryOp(op, e) => {
+                let inner = e.diff_raw(var)?;
+                let e = e.as_ref().clone();
+                let outer = match op {
+                    UnOp::Negative => return Some(un(UnOp::Negative, inner)),
+                    UnOp::Sin => un(UnOp::Cos, e),
+                    UnOp::Cos => un(UnOp::Negative, un(UnOp::Sin, e)),
+                    UnOp::Tan => bin(BinOp::Divide, Expr::one(), bin(BinOp::Power, un(UnOp::Cos, e), Expr::const_(2.0))),
+                    UnOp::Exp => un(UnOp::Exp, e),
+                    UnOp::Log => bin(BinOp::Divide, Expr::one(), e),
+                    UnOp::Sqrt => bin(BinOp::Divide, Expr::one(), bin(BinOp::Multiply, Expr::const_(2.0), un(UnOp::Sqrt, e))),
+                    UnOp::Asinh => bin(BinOp::Divide, Expr::one(), un(UnOp::Sqrt, bin(BinOp::Plus, bin(BinOp::Power, e, Expr::const_(2.0)), Expr::one()))),
+                    UnOp::Acosh => bin(BinOp::Divide, Expr::one(), un(UnOp::Sqrt, bin(BinOp::Minus, bin(BinOp::Power, e, Expr::const_(2.0)), Expr::one()))),
+                    UnOp::Atanh => bin(BinOp::Divide, Expr::one(), bin(BinOp::Minus, Expr::one(), bin(BinOp::Power, e, Expr::const_(2.0)))),
+                    UnOp::Percent => Expr::const_(0.01),
+                    UnOp::Factorial => return None
+                };
+                bin(BinOp::Multiply, outer, inner)
+            },
+            Expr::Let(..) => self.inline_lets().diff_raw(var)?,
+            Expr::Number(_) | Expr::Complex(_, _) | Expr::Eof => return None
+        };
+
+        Some(d)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use crate::ast::Ast;
+
+    fn derivative(s: &str) -> Option<String> {
+        Ast::string_to_ast(s).expr.diff("x").map(|d| d.to_string())
+    }
+
+    #[test]
+    fn diff_rules() {
+        assert_eq!(derivative("x^2 - 2"), Some("2 * x".to_owned()));
+        assert_eq!(derivative("3 * x"), Some("3".to_owned()));
+        assert_eq!(derivative("sin(x)"), Some("cos(x)".to_owned()));
+        assert_eq!(derivative("y^2"), Some("0".to_owned()));
+        assert_eq!(derivative("x!"), None);
+        assert_eq!(derivative(""), None);
+    }
+
+    #[test]
+    fn diff_matches_central_difference() {
+        for s in ["x^3 * sin(x)", "exp(2 * x) / (1 + x^2)", "x^x", "sqrt(x) - ln(x) + tan(x)", "logb(2, x^2)", "2^x - cos(x)%"] {
+            let ast = Ast::string_to_ast(s);
+            let d = ast.expr.diff("x").unwrap();
+            for x in [0.3, 0.7, 1.2] {
+                let vars = HashMap::from([("x".to_owned(), x)]);
+                let exact = d.eval_with(&vars).unwrap();
+                assert!((exact - ast.derivative_at(x).unwrap()).abs() < 1e-5 * exact.abs().max(1.0), "{}", s);
+            }
+        }
+    }
+}
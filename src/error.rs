@@ -0,0 +1,223 @@
+use std::fmt::{Display, Formatter};
+use crate::lex::Token;
+
+/// Input that is not the start of any token
+#[derive(Debug, Clone, PartialEq)]
+pub struct LexError {
+    /// Byte offset of the unrecognized character in the input
+    pub position: usize,
+    pub character: char,
+    /// The input surrounding the character
+    pub context: String,
+}
+
+impl LexError {
+    // Characters of context kept on each side of the unrecognized one
+    const CONTEXT_CHARS: usize = 8;
+
+    pub(crate) fn at(source: &str, position: usize) -> Self {
+        let character = source[position..].chars().next().unwrap_or_default();
+        let before: Vec<char> = source[..position].chars().rev().take(Self::CONTEXT_CHARS).collect();
+        let after = source[position..].chars().take(Self::CONTEXT_CHARS + 1);
+        let context = before.into_iter().rev().chain(after).collect();
+        LexError { position, character, context }
+    }
+}
+
+impl Display for LexError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unrecognized character {:?} at position {} in {:?}", self.character, self.position, self.context)
+    }
+}
+
+impl std::error::Error for LexError {}
+
+/// Errors that can occur while parsing an expression
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    LexError(LexError),
+    UnexpectedToken(Token),
+    UnexpectedEof,
+    MissingClosingParen,
+    WrongArity { name: String, expected: usize, found: usize },
+    Io(String),
+    MissingName(String),
+    TooDeep { max_depth: usize },
+    UnsupportedLatex(String),
+    InvalidMathml(String),
+    InvalidJson(String),
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::LexError(e) => write!(f, "{}", e),
+            ParseError::UnexpectedToken(t) => write!(f, "Unexpected token {:?}", t),
+            ParseError::UnexpectedEof => write!(f, "Unexpected end of input"),
+            ParseError::MissingClosingParen => write!(f, "Missing closing parenthesis"),
+            ParseError::WrongArity { name, expected, found } => {
+                write!(f, "Function {} takes {} argument(s) but was given {}", name, expected, found)
+            },
+            ParseError::Io(e) => write!(f, "Could not read input: {}", e),
+            ParseError::MissingName(line) => write!(f, "Expected a line like name = expression but found {}", line),
+            ParseError::TooDeep { max_depth } => write!(f, "Expression is nested more than {} levels deep", max_depth),
+            ParseError::UnsupportedLatex(s) => write!(f, "Unsupported LaTeX {}", s),
+            ParseError::InvalidMathml(s) => write!(f, "Invalid MathML: {}", s),
+            ParseError::InvalidJson(s) => write!(f, "Invalid JSON: {}", s),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<LexError> for ParseError {
+    fn from(value: LexError) -> Self {
+        ParseError::LexError(value)
+    }
+}
+
+/// Errors that can occur while evaluating an expression
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalError {
+    EmptyExpression,
+    UnboundVariable(String),
+    NonIntegerFactorial(f64),
+    FactorialNegative(i64),
+    FactorialOverflow(u64),
+    WrongArity { expected: usize, found: usize },
+    NotReal,
+    NotRational,
+    NotInteger(f64),
+    Overflow,
+    InvalidArgument(String),
+    NotANumber,
+}
+
+impl Display for EvalError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EvalError::EmptyExpression => write!(f, "Cannot evaluate an empty expression"),
+            EvalError::UnboundVariable(name) => write!(f, "Variable {} has no value", name),
+            EvalError::NonIntegerFactorial(n) => write!(f, "Cannot evaluate factorial on decimal {}", n),
+            EvalError::FactorialNegative(n) => write!(f, "Cannot evaluate factorial on negative integer {}", n),
+            EvalError::FactorialOverflow(n) => write!(f, "Factorial of {} is too large to compute exactly", n),
+            EvalError::WrongArity { expected, found } => {
+                write!(f, "Expected {} free variable(s) but found {}", expected, found)
+            },
+            EvalError::NotReal => write!(f, "Expression has an imaginary part, try complex mode"),
+            EvalError::NotRational => write!(f, "Expression does not have an exact rational value"),
+            EvalError::NotInteger(n) => write!(f, "Expression evaluates to {} which is not an i64 integer", n),
+            EvalError::Overflow => write!(f, "Result is too large to represent exactly"),
+            EvalError::InvalidArgument(e) => write!(f, "Invalid argument: {}", e),
+            EvalError::NotANumber => write!(f, "Expression produced NaN"),
+        }
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+/// Errors from following a path of child indices down an expression tree
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathError {
+    IndexOutOfBounds { at_depth: usize },
+}
+
+impl Display for PathError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PathError::IndexOutOfBounds { at_depth } => write!(f, "Path has no such child at depth {}", at_depth),
+        }
+    }
+}
+
+impl std::error::Error for PathError {}
+
+/// Arguments outside the domain of a function, found without any variables set
+#[derive(Debug, Clone, PartialEq)]
+pub enum DomainError {
+    LogOfNonPositive { arg: f64 },
+    SqrtOfNegative { arg: f64 },
+    TanOfPiOver2,
+    AcoshBelowOne { arg: f64 },
+    AtanhOutsideUnit { arg: f64 },
+}
+
+impl Display for DomainError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DomainError::LogOfNonPositive { arg } => write!(f, "Logarithm of non-positive number {}", arg),
+            DomainError::SqrtOfNegative { arg } => write!(f, "Square root of negative number {}", arg),
+            DomainError::TanOfPiOver2 => write!(f, "Tangent of an odd multiple of pi / 2 is undefined"),
+            DomainError::AcoshBelowOne { arg } => write!(f, "Inverse hyperbolic cosine of {}, which is less than 1", arg),
+            DomainError::AtanhOutsideUnit { arg } => write!(f, "Inverse hyperbolic tangent of {}, which is not between -1 and 1", arg),
+        }
+    }
+}
+
+impl std::error::Error for DomainError {}
+
+/// Errors from solving an equation exactly
+#[derive(Debug, Clone, PartialEq)]
+pub enum SolveError {
+    NotQuadratic,
+    ComplexRoots { discriminant: f64 },
+    NoSignChange,
+    NoConvergence { iterations: usize },
+    ZeroDerivative { x: f64 },
+    Eval(EvalError),
+}
+
+impl Display for SolveError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SolveError::NotQuadratic => write!(f, "Expression is not a quadratic polynomial"),
+            SolveError::ComplexRoots { discriminant } => {
+                write!(f, "Roots are complex since the discriminant {} is negative", discriminant)
+            },
+            SolveError::NoSignChange => write!(f, "Function must have opposite signs at the ends of the interval"),
+            SolveError::NoConvergence { iterations } => write!(f, "No root found within {} iterations", iterations),
+            SolveError::ZeroDerivative { x } => write!(f, "Derivative vanishes at {}, so Newton's method cannot continue", x),
+            SolveError::Eval(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for SolveError {}
+
+impl From<EvalError> for SolveError {
+    fn from(value: EvalError) -> Self {
+        SolveError::Eval(value)
+    }
+}
+
+/// Any error from going between an input string and its value
+#[derive(Debug, Clone, PartialEq)]
+pub enum CalcError {
+    Parse(ParseError),
+    Eval(EvalError),
+    Io(String),
+}
+
+impl Display for CalcError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CalcError::Parse(e) => write!(f, "{}", e),
+            CalcError::Eval(e) => write!(f, "{}", e),
+            CalcError::Io(e) => write!(f, "Could not write output: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CalcError {}
+
+impl From<ParseError> for CalcError {
+    fn from(value: ParseError) -> Self {
+        CalcError::Parse(value)
+    }
+}
+
+impl From<EvalError> for CalcError {
+    fn from(value: EvalError) -> Self {
+        CalcError::Eval(value)
+    }
+}
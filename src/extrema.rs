@@ -0,0 +1,116 @@
+use crate::ast::Ast;
+use crate::error::EvalError;
+
+// Second derivatives smaller than this are too flat to call a minimum or maximum
+const FLAT_CURVATURE: f64 = 1e-6;
+
+/// How the function behaves around a point where its derivative vanishes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CriticalPointKind {
+    Minimum,
+    Maximum,
+    /// Neither a minimum nor a maximum, like 0 for x^3
+    Saddle,
+}
+
+/// A point where the derivative of a function of one variable is zero
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CriticalPoint {
+    pub x: f64,
+    pub kind: CriticalPointKind,
+    /// The value of the function at x
+    pub value: f64,
+}
+
+impl Ast {
+    // Estimate the second derivative at x with a central difference
+    fn second_derivative_at(&self, x: f64) -> Result<f64, EvalError> {
+        let h = 1e-4 * x.abs().max(1.0);
+        Ok((self.eval_point(x + h)? - 2.0 * self.eval_point(x)? + self.eval_point(x - h)?) / (h * h))
+    }
+
+    // Narrow [a, b], where the derivative changes sign, down to a width of tol
+    fn bisect_derivative(&self, mut a: f64, mut b: f64, tol: f64) -> Result<f64, EvalError> {
+        let mut da = self.derivative_at(a)?;
+        while b - a > tol {
+            let mid = (a + b) / 2.0;
+            let dm = self.derivative_at(mid)?;
+            if dm == 0.0 {
+                return Ok(mid)
+            }
+            if (dm < 0.0) == (da < 0.0) {
+                a = mid;
+                da = dm;
+            } else {
+                b = mid;
+            }
+        }
+        Ok((a + b) / 2.0)
+    }
+
+    /// Find the critical points of a function of at most one variable on [a, b] and classify them
+    /// The derivative is sampled at the ends of `n` equal subintervals, each sign change is narrowed
+    /// down by bisection to within `tol`, and the sign of the second derivative decides the kind
+    /// Points where the derivative touches zero without changing sign are only found when it is
+    /// within `tol` of zero at one of the samples
+    pub fn critical_points(&self, a: f64, b: f64, n: usize, tol: f64) -> Result<Vec<CriticalPoint>, EvalError> {
+        let step = (b - a) / n as f64;
+        let xs: Vec<f64> = (0..=n).map(|i| a + i as f64 * step).collect();
+        let derivs = xs.iter().map(|x| self.derivative_at(*x)).collect::<Result<Vec<f64>, _>>()?;
+
+        let mut roots = Vec::new();
+        for i in 0..=n {
+            if derivs[i].abs() <= tol {
+                roots.push(xs[i]);
+            } else if i < n && derivs[i + 1].abs() > tol && (derivs[i] < 0.0) != (derivs[i + 1] < 0.0) {
+                roots.push(self.bisect_derivative(xs[i], xs[i + 1], tol)?);
+            }
+        }
+
+        roots.into_iter()
+            .map(|x| {
+                let curvature = self.second_derivative_at(x)?;
+                let kind = if curvature > FLAT_CURVATURE {
+                    CriticalPointKind::Minimum
+                } else if curvature < -FLAT_CURVATURE {
+                    CriticalPointKind::Maximum
+                } else {
+                    CriticalPointKind::Saddle
+                };
+                Ok(CriticalPoint { x, kind, value: self.eval_point(x)? })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cubic_minimum_and_maximum() {
+        let points = Ast::string_to_ast("x^3 - 3*x").critical_points(-3.0, 3.0, 100, 1e-9).unwrap();
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0].kind, CriticalPointKind::Maximum);
+        assert!((points[0].x + 1.0).abs() < 1e-6 && (points[0].value - 2.0).abs() < 1e-9);
+        assert_eq!(points[1].kind, CriticalPointKind::Minimum);
+        assert!((points[1].x - 1.0).abs() < 1e-6 && (points[1].value + 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn saddles_and_periodic_functions() {
+        let points = Ast::string_to_ast("x^3").critical_points(-1.0, 1.0, 2, 1e-9).unwrap();
+        assert_eq!(points, vec![CriticalPoint { x: 0.0, kind: CriticalPointKind::Saddle, value: 0.0 }]);
+
+        let kinds: Vec<CriticalPointKind> = Ast::string_to_ast("sin(t)")
+            .critical_points(0.0, 10.0, 50, 1e-9)
+            .unwrap()
+            .iter()
+            .map(|p| p.kind)
+            .collect();
+        assert_eq!(kinds, vec![CriticalPointKind::Maximum, CriticalPointKind::Minimum, CriticalPointKind::Maximum]);
+
+        assert_eq!(Ast::string_to_ast("2 * x + 1").critical_points(-5.0, 5.0, 10, 1e-9), Ok(Vec::new()));
+        assert_eq!(Ast::string_to_ast("x * y").critical_points(0.0, 1.0, 10, 1e-9), Err(EvalError::WrongArity { expected: 1, found: 2 }));
+    }
+}
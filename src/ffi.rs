@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+use std::ffi::{c_char, c_int, CStr};
+use crate::ast::Ast;
+
+// Status codes returned by every function, mirrored in ffi/ast_calc.h
+pub const AST_CALC_OK: c_int = 0;
+pub const AST_CALC_INVALID_INPUT: c_int = 1;
+pub const AST_CALC_PARSE_ERROR: c_int = 2;
+pub const AST_CALC_EVAL_ERROR: c_int = 3;
+pub const AST_CALC_INVALID_VARS: c_int = 4;
+
+// Borrow a C string as a &str, failing on null pointers and invalid UTF-8
+unsafe fn to_str<'a>(s: *const c_char) -> Result<&'a str, c_int> {
+    if s.is_null() {
+        return Err(AST_CALC_INVALID_INPUT)
+    }
+    CStr::from_ptr(s).to_str().map_err(|_| AST_CALC_INVALID_INPUT)
+}
+
+// Read a flat JSON object of numbers such as {"x": 1, "y": 2.5}
+// Names may not contain quotes or commas, which is enough for variable names
+fn parse_vars(json: &str) -> Option<HashMap<String, f64>> {
+    let body = json.trim().strip_prefix('{')?.strip_suffix('}')?;
+    if body.trim().is_empty() {
+        return Some(HashMap::new())
+    }
+    body.split(',')
+        .map(|entry| {
+            let (name, val) = entry.split_once(':')?;
+            let name = name.trim().strip_prefix('"')?.strip_suffix('"')?;
+            Some((name.to_owned(), val.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+unsafe fn eval_ast(ast: &Ast, vars_json: *const c_char, result: *mut f64) -> c_int {
+    if result.is_null() {
+        return AST_CALC_INVALID_INPUT
+    }
+    let vars = if vars_json.is_null() {
+        HashMap::new()
+    } else {
+        match to_str(vars_json).map(parse_vars) {
+            Ok(Some(vars)) => vars,
+            Ok(None) => return AST_CALC_INVALID_VARS,
+            Err(code) => return code
+        }
+    };
+    match ast.eval_multivar(&vars) {
+        Ok(val) => {
+            *result = val;
+            AST_CALC_OK
+        },
+        Err(_) => AST_CALC_EVAL_ERROR
+    }
+}
+
+/// Parse and evaluate `expr`, writing its value to `result`
+///
+/// # Safety
+/// `expr` must be a null terminated string and `result` must point to a writable f64
+#[no_mangle]
+pub unsafe extern "C" fn ast_calc_eval(expr: *const c_char, result: *mut f64) -> c_int {
+    ast_calc_eval_with_vars(expr, std::ptr::null(), result)
+}
+
+/// Parse and evaluate `expr` with its variables taken from the JSON object `vars_json`,
+/// such as `{"x": 1, "y": 2.5}`, writing its value to `result`
+///
+/// # Safety
+/// `expr` and `vars_json` must be null terminated strings, where `vars_json` may also be null,
+/// and `result` must point to a writable f64
+#[no_mangle]
+pub unsafe extern "C" fn ast_calc_eval_with_vars(expr: *const c_char, vars_json: *const c_char, result: *mut f64) -> c_int {
+    let ast = match to_str(expr).map(Ast::try_from_str) {
+        Ok(Ok(ast)) => ast,
+        Ok(Err(_)) => return AST_CALC_PARSE_ERROR,
+        Err(code) => return code
+    };
+    eval_ast(&ast, vars_json, result)
+}
+
+/// Parse `expr` once into a handle written to `out_handle`, which can be evaluated many times
+/// with `ast_calc_eval_handle` and must be released with `ast_calc_free_handle`
+///
+/// # Safety
+/// `expr` must be a null terminated string and `out_handle` must point to a writable pointer
+#[no_mangle]
+pub unsafe extern "C" fn ast_calc_parse(expr: *const c_char, out_handle: *mut *mut Ast) -> c_int {
+    if out_handle.is_null() {
+        return AST_CALC_INVALID_INPUT
+    }
+    match to_str(expr).map(Ast::try_from_str) {
+        Ok(Ok(ast)) => {
+            *out_handle = Box::into_raw(Box::new(ast));
+            AST_CALC_OK
+        },
+        Ok(Err(_)) => AST_CALC_PARSE_ERROR,
+        Err(code) => code
+    }
+}
+
+/// Evaluate a parsed handle with its variables taken from `vars_json`, which may be null
+///
+/// # Safety
+/// `handle` must come from `ast_calc_parse` and not be freed yet, `vars_json` must be null
+/// or a null terminated string, and `result` must point to a writable f64
+#[no_mangle]
+pub unsafe extern "C" fn ast_calc_eval_handle(handle: *const Ast, vars_json: *const c_char, result: *mut f64) -> c_int {
+    match handle.as_ref() {
+        Some(ast) => eval_ast(ast, vars_json, result),
+        None => AST_CALC_INVALID_INPUT
+    }
+}
+
+/// Release a handle from `ast_calc_parse`, doing nothing when it is null
+///
+/// # Safety
+/// `handle` must come from `ast_calc_parse` and not have been freed already
+#[no_mangle]
+pub unsafe extern "C" fn ast_calc_free_handle(handle: *mut Ast) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+    use super::*;
+
+    #[test]
+    fn ffi_eval() {
+        let mut result = 0.0;
+        let expr = CString::new("-2 + 4 * -(5^3 + 7 * 3!)").unwrap();
+        assert_eq!(unsafe { ast_calc_eval(expr.as_ptr(), &mut result) }, AST_CALC_OK);
+        assert_eq!(result, -670.0);
+
+        let bad = CString::new("1 +").unwrap();
+        assert_eq!(unsafe { ast_calc_eval(bad.as_ptr(), &mut result) }, AST_CALC_PARSE_ERROR);
+        let unbound = CString::new("x").unwrap();
+        assert_eq!(unsafe { ast_calc_eval(unbound.as_ptr(), &mut result) }, AST_CALC_EVAL_ERROR);
+        assert_eq!(unsafe { ast_calc_eval(std::ptr::null(), &mut result) }, AST_CALC_INVALID_INPUT);
+        assert_eq!(unsafe { ast_calc_eval(expr.as_ptr(), std::ptr::null_mut()) }, AST_CALC_INVALID_INPUT);
+        assert_eq!(result, -670.0);
+    }
+
+    #[test]
+    fn ffi_eval_with_vars() {
+        let mut result = 0.0;
+        let expr = CString::new("x * y + z").unwrap();
+        let vars = CString::new(r#"{"x": 2, "y": 3.5, "z": -1e1}"#).unwrap();
+        assert_eq!(unsafe { ast_calc_eval_with_vars(expr.as_ptr(), vars.as_ptr(), &mut result) }, AST_CALC_OK);
+        assert_eq!(result, -3.0);
+
+        for bad in [r#"{"x": 2"#, r#"{x: 2}"#, r#"{"x": "two"}"#] {
+            let vars = CString::new(bad).unwrap();
+            assert_eq!(unsafe { ast_calc_eval_with_vars(expr.as_ptr(), vars.as_ptr(), &mut result) }, AST_CALC_INVALID_VARS);
+        }
+        let vars = CString::new(" { } ").unwrap();
+        assert_eq!(unsafe { ast_calc_eval_with_vars(expr.as_ptr(), vars.as_ptr(), &mut result) }, AST_CALC_EVAL_ERROR);
+    }
+
+    #[test]
+    fn ffi_handles() {
+        let expr = CString::new("x^2 + 1").unwrap();
+        let mut handle = std::ptr::null_mut();
+        assert_eq!(unsafe { ast_calc_parse(expr.as_ptr(), &mut handle) }, AST_CALC_OK);
+
+        let mut result = 0.0;
+        for (x, expected) in [(0.0, 1.0), (3.0, 10.0)] {
+            let vars = CString::new(format!(r#"{{"x": {}}}"#, x)).unwrap();
+            assert_eq!(unsafe { ast_calc_eval_handle(handle, vars.as_ptr(), &mut result) }, AST_CALC_OK);
+            assert_eq!(result, expected);
+        }
+        unsafe { ast_calc_free_handle(handle) };
+
+        let bad = CString::new("(x").unwrap();
+        let mut handle = std::ptr::null_mut();
+        assert_eq!(unsafe { ast_calc_parse(bad.as_ptr(), &mut handle) }, AST_CALC_PARSE_ERROR);
+        assert!(handle.is_null());
+        assert_eq!(unsafe { ast_calc_eval_handle(handle, std::ptr::null(), &mut result) }, AST_CALC_INVALID_INPUT);
+        unsafe { ast_calc_free_handle(handle) };
+    }
+}
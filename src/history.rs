@@ -0,0 +1,80 @@
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+/// Previously submitted lines, persisted to a file so they survive restarts
+pub struct History {
+    path: Option<PathBuf>,
+    entries: Vec<String>
+}
+
+impl History {
+    /// Load the history file, which is $AST_CALC_HISTFILE if set or ~/.ast_calc_history otherwise
+    pub fn load_default() -> Self {
+        let path = env::var_os("AST_CALC_HISTFILE")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".ast_calc_history")));
+        Self::load(path)
+    }
+
+    /// Load the entries stored at `path`, if any, keeping only an in-memory history when it is None
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let entries = path.as_ref()
+            .and_then(|p| fs::read_to_string(p).ok())
+            .map(|contents| contents.lines().map(str::to_owned).collect())
+            .unwrap_or_default();
+        Self {
+            path,
+            entries
+        }
+    }
+
+    /// Record a line, appending it to the history file
+    pub fn push(&mut self, line: &str) -> io::Result<()> {
+        self.entries.push(line.to_owned());
+        if let Some(path) = &self.path {
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            writeln!(file, "{}", line)?;
+        }
+        Ok(())
+    }
+
+    /// Look up an entry by the 1-based number shown by `recent`
+    pub fn get(&self, n: usize) -> Option<&str> {
+        self.entries.get(n.checked_sub(1)?).map(String::as_str)
+    }
+
+    /// The last `count` entries paired with their 1-based numbers
+    pub fn recent(&self, count: usize) -> impl Iterator<Item = (usize, &str)> {
+        let start = self.entries.len().saturating_sub(count);
+        self.entries.iter().enumerate().skip(start).map(|(i, line)| (i + 1, line.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn history_persists() {
+        let path = env::temp_dir().join(format!("ast-calc-history-{}.txt", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut history = History::load(Some(path.clone()));
+        for i in 0..25 {
+            history.push(&format!("{} + 1", i)).unwrap();
+        }
+
+        let reloaded = History::load(Some(path.clone()));
+        fs::remove_file(&path).unwrap();
+        assert_eq!(reloaded.get(1), Some("0 + 1"));
+        assert_eq!(reloaded.get(0), None);
+        assert_eq!(reloaded.get(26), None);
+
+        let recent: Vec<(usize, &str)> = reloaded.recent(20).collect();
+        assert_eq!(recent.len(), 20);
+        assert_eq!(recent[0], (6, "5 + 1"));
+        assert_eq!(recent[19], (25, "24 + 1"));
+    }
+}
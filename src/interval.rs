@@ -0,0 +1,220 @@
+use std::f64::consts::{FRAC_PI_2, PI, TAU};
+use crate::ast::Ast;
+use crate::error::EvalError;
+use crate::parse::{factorial, BinOp, Expr, UnOp};
+
+// A closed interval [lo, hi] containing the true value of an expression
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Interval {
+    lo: f64,
+    hi: f64
+}
+
+impl Interval {
+    fn new(lo: f64, hi: f64) -> Self {
+        Self {
+            lo,
+            hi
+        }
+    }
+
+    fn point(x: f64) -> Self {
+        Self::new(x, x)
+    }
+
+    fn everything() -> Self {
+        Self::new(f64::NEG_INFINITY, f64::INFINITY)
+    }
+
+    fn contains(&self, x: f64) -> bool {
+        self.lo <= x && x <= self.hi
+    }
+
+    // Whether the interval contains offset + k * period for some integer k
+    fn contains_periodic(&self, offset: f64, period: f64) -> bool {
+        let k = ((self.lo - offset) / period).ceil();
+        self.contains(offset + k * period)
+    }
+
+    fn hull(vals: &[f64]) -> Self {
+        let lo = vals.iter().cloned().fold(f64::INFINITY, f64::min);
+        let hi = vals.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        Self::new(lo, hi)
+    }
+
+    fn monotone(self, f: impl Fn(f64) -> f64) -> Self {
+        Self::new(f(self.lo), f(self.hi))
+    }
+
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.lo + rhs.lo, self.hi + rhs.hi)
+    }
+
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.lo - rhs.hi, self.hi - rhs.lo)
+    }
+
+    fn mul(self, rhs: Self) -> Self {
+        Self::hull(&[self.lo * rhs.lo, self.lo * rhs.hi, self.hi * rhs.lo, self.hi * rhs.hi])
+    }
+
+    fn div(self, rhs: Self) -> Self {
+        if rhs.contains(0.0) {
+            return Self::everything()
+        }
+        self.mul(Self::new(1.0 / rhs.hi, 1.0 / rhs.lo))
+    }
+
+    fn pow(self, rhs: Self) -> Self {
+        if rhs.lo == rhs.hi && rhs.lo.fract() == 0.0 {
+            let n = rhs.lo;
+            let (a, b) = (self.lo.powf(n), self.hi.powf(n));
+            if n > 0.0 && n % 2.0 == 0.0 && self.contains(0.0) {
+                Self::new(0.0, a.max(b))
+            } else if n < 0.0 && self.contains(0.0) {
+                Self::everything()
+            } else {
+                Self::hull(&[a, b])
+            }
+        } else if self.lo >= 0.0 {
+            // x^y = exp(y * ln(x)) composes monotone pieces
+            rhs.mul(self.monotone(f64::ln)).monotone(f64::exp)
+        } else {
+            Self::everything()
+        }
+    }
+
+    fn sin(self) -> Self {
+        if self.hi - self.lo >= TAU {
+            return Self::new(-1.0, 1.0)
+        }
+        let ends = self.monotone(f64::sin);
+        let hi = if self.contains_periodic(FRAC_PI_2, TAU) { 1.0 } else { ends.lo.max(ends.hi) };
+        let lo = if self.contains_periodic(-FRAC_PI_2, TAU) { -1.0 } else { ends.lo.min(ends.hi) };
+        Self::new(lo, hi)
+    }
+
+    fn cos(self) -> Self {
+        self.add(Self::point(FRAC_PI_2)).sin()
+    }
+
+    fn tan(self) -> Self {
+        if self.hi - self.lo >= PI || self.contains_periodic(FRAC_PI_2, PI) {
+            Self::everything()
+        } else {
+            self.monotone(f64::tan)
+        }
+    }
+
+    fn factorial(self) -> Result<Self, EvalError> {
+        let (lo, hi) = (self.lo.max(0.0).ceil(), self.hi.floor());
+        if lo > hi {
+            return Err(EvalError::NonIntegerFactorial(self.lo))
+        }
+        Ok(Self::new(factorial(lo)?, factorial(hi)?))
+    }
+}
+
+impl Expr {
+    fn eval_interval(&self, x: Interval) -> Result<Interval, EvalError> {
+        let val = match self {
+            Expr::BinaryOp(op, e1, e2) => {
+                let (v1, v2) = (e1.eval_interval(x)?, e2.eval_interval(x)?);
+                match op {
+                    BinOp::Plus => v1.add(v2),
+                    BinOp::Minus => v1.sub(v2),
+                    BinOp::Multiply => v1.mul(v2),
+                    BinOp::Divide => v1.div(v2),
+                    BinOp::Power => v1.pow(v2),
+                    BinOp::LogBase => {
+                        if v1.lo == 1.0 && v1.hi == 1.0 {
+                            return Err(EvalError::InvalidArgument("logb is undefined for base 1".to_owned()))
+                        }
+                        let ln = |v: f64| v.max(0.0).ln();
+                        v2.monotone(ln).div(v1.monotone(ln))
+                    }
+                }
+            },
+            Expr::UnaryOp(op, e) => {
+                let val = e.eval_interval(x)?;
+                match op {
+                    UnOp::Negative => Interval::new(-val.hi, -val.lo),
+                    UnOp::Sin => val.sin(),
+                    UnOp::Cos => val.cos(),
+                    UnOp::Tan => val.tan(),
+                    UnOp::Exp => val.monotone(f64::exp),
+                    UnOp::Log => val.monotone(|v| v.max(0.0).ln()),
+                    UnOp::Sqrt => val.monotone(|v| v.max(0.0).sqrt()),
+                    UnOp::Asinh => val.monotone(f64::asinh),
+                    UnOp::Acosh => val.monotone(|v| v.max(1.0).acosh()),
+                    UnOp::Atanh => val.monotone(|v| v.clamp(-1.0, 1.0).atanh()),
+                    UnOp::Factorial => val.factorial()?,
+                    UnOp::Percent => val.monotone(|v| v / 100.0)
+                }
+            },
+            Expr::Let(..) => self.inline_lets().eval_interval(x)?,
+            Expr::Number(n) => Interval::point(*n),
+            Expr::Complex(_, _) => return Err(EvalError::NotReal),
+            Expr::Variable(_) => x,
+            Expr::Eof => return Err(EvalError::EmptyExpression)
+        };
+
+        Ok(val)
+    }
+}
+
+impl Ast {
+    /// Bound the values of a single variable expression as its variable ranges over [x_lo, x_hi]
+    /// Up to floating point rounding the returned (lo, hi) contains the true range, but may be wider than it
+    pub fn eval_interval(&self, x_lo: f64, x_hi: f64) -> Result<(f64, f64), EvalError> {
+        let vars = self.expr.free_variables();
+        if vars.len() > 1 {
+            return Err(EvalError::WrongArity { expected: 1, found: vars.len() })
+        }
+        let val = self.expr.eval_interval(Interval::new(x_lo, x_hi))?;
+        Ok((val.lo, val.hi))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interval_square() {
+        assert_eq!(Ast::string_to_ast("x^2").eval_interval(-3.0, 2.0), Ok((0.0, 9.0)));
+        assert_eq!(Ast::string_to_ast("x^3").eval_interval(-3.0, 2.0), Ok((-27.0, 8.0)));
+    }
+
+    #[test]
+    fn interval_arithmetic() {
+        assert_eq!(Ast::string_to_ast("2 * x - 1").eval_interval(0.0, 1.0), Ok((-1.0, 1.0)));
+        assert_eq!(Ast::string_to_ast("x * x").eval_interval(-1.0, 2.0), Ok((-2.0, 4.0)));
+        assert_eq!(Ast::string_to_ast("1 / x").eval_interval(1.0, 4.0), Ok((0.25, 1.0)));
+        assert_eq!(Ast::string_to_ast("1 / x").eval_interval(-1.0, 1.0), Ok((f64::NEG_INFINITY, f64::INFINITY)));
+        assert_eq!(Ast::string_to_ast("-exp(x)").eval_interval(0.0, 1.0), Ok((-std::f64::consts::E, -1.0)));
+    }
+
+    #[test]
+    fn interval_trig() {
+        assert_eq!(Ast::string_to_ast("sin(x)").eval_interval(0.0, 3.0), Ok((0.0, 1.0)));
+        assert_eq!(Ast::string_to_ast("sin(x)").eval_interval(0.0, 100.0), Ok((-1.0, 1.0)));
+        let (lo, hi) = Ast::string_to_ast("cos(x)").eval_interval(0.5, 1.0).unwrap();
+        assert_eq!((lo, hi), (1f64.cos(), 0.5f64.cos()));
+    }
+
+    #[test]
+    fn interval_contains_samples() {
+        let ast = Ast::string_to_ast("sin(x) * x^2 - exp(x / 3) + sqrt(x)");
+        let (lo, hi) = ast.eval_interval(0.5, 4.0).unwrap();
+        for i in 0..=100 {
+            let val = ast.eval_point(0.5 + 3.5 * i as f64 / 100.0).unwrap();
+            assert!(lo <= val && val <= hi);
+        }
+    }
+
+    #[test]
+    fn interval_errors() {
+        assert_eq!(Ast::string_to_ast("x + y").eval_interval(0.0, 1.0), Err(EvalError::WrongArity { expected: 1, found: 2 }));
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use std::iter::Peekable;
+use std::str::Chars;
+use crate::ast::Ast;
+use crate::error::ParseError;
+use crate::lex::TokenStream;
+use crate::parse::{parse_expr_with_options, ParseOptions};
+
+// Rewrite LaTeX into the calculator's own infix syntax, up to the closing brace of the
+// current group when `in_group` is set or the end of input otherwise
+// Words are padded with spaces so that they never run into a neighbouring name
+fn translate(chars: &mut Peekable<Chars>, in_group: bool) -> Result<String, ParseError> {
+    let mut out = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => out.push_str(&command(chars)?),
+            '{' => out.push_str(&format!("({})", translate(chars, true)?)),
+            '}' if in_group => return Ok(out),
+            '}' => return Err(ParseError::UnsupportedLatex("unmatched }".to_owned())),
+            c => out.push(c)
+        }
+    }
+    if in_group {
+        return Err(ParseError::MissingClosingParen)
+    }
+    Ok(out)
+}
+
+// The argument of a command like \frac, either a braced group or a single character
+fn argument(chars: &mut Peekable<Chars>) -> Result<String, ParseError> {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    match chars.next() {
+        Some('{') => translate(chars, true),
+        Some('\\') => command(chars),
+        Some(c) => Ok(c.to_string()),
+        None => Err(ParseError::UnexpectedEof)
+    }
+}
+
+// Translate the command following a backslash
+fn command(chars: &mut Peekable<Chars>) -> Result<String, ParseError> {
+    let mut name = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_ascii_alphabetic()) {
+        name.push(c);
+    }
+    // Spacing commands like \, and \; have a single symbol as their name
+    if name.is_empty() {
+        return match chars.next() {
+            Some(',' | ';' | ':' | '!' | ' ') => Ok(" ".to_owned()),
+            Some(c) => Err(ParseError::UnsupportedLatex(format!("\\{}", c))),
+            None => Err(ParseError::UnexpectedEof)
+        }
+    }
+
+    let s = match name.as_str() {
+        "frac" | "dfrac" | "tfrac" => {
+            let num = argument(chars)?;
+            let den = argument(chars)?;
+            format!(" (({}) / ({})) ", num, den)
+        },
+        "sqrt" => {
+            // \sqrt[n]{x} is the nth root
+            if chars.next_if_eq(&'[').is_some() {
+                let mut index = String::new();
+                for c in chars.by_ref() {
+                    if c == ']' {
+                        let radicand = argument(chars)?;
+                        return Ok(format!(" (({})^(1 / ({}))) ", radicand, translate(&mut index.chars().peekable(), false)?))
+                    }
+                    index.push(c);
+                }
+                return Err(ParseError::UnsupportedLatex("unclosed [ in \\sqrt".to_owned()))
+            }
+            format!(" sqrt({}) ", argument(chars)?)
+        },
+        "sin" | "cos" | "tan" | "exp" | "ln" | "pi" | "tau" | "phi" => format!(" {} ", name),
+        "varphi" => " phi ".to_owned(),
+        "infty" => " inf ".to_owned(),
+        "cdot" | "times" => " * ".to_owned(),
+        "div" => " / ".to_owned(),
+        // Sizing commands only change how the following delimiter is drawn
+        "left" | "right" => " ".to_owned(),
+        _ => return Err(ParseError::UnsupportedLatex(format!("\\{}", name)))
+    };
+    Ok(s)
+}
+
+impl Ast {
+    /// Parse basic LaTeX math such as `\frac{\pi}{2} + \sqrt{x^{2} + 1} \cdot \sin(y)`
+    /// Fractions, square and nth roots, powers with braced exponents, sin, cos, tan, exp, ln,
+    /// the constants pi, tau, phi and infinity, and the operators \cdot, \times and \div are
+    /// understood, along with implicit products like `2\pi r`
+    pub fn latex_to_ast(latex: &str) -> Result<Ast, ParseError> {
+        let infix = translate(&mut latex.chars().peekable(), false)?;
+        let functions = HashMap::new();
+        let options = ParseOptions { functions: &functions, implicit_multiply: true };
+        let expr = parse_expr_with_options(&mut TokenStream::new(&infix), &options)?;
+        Ok(Ast::new(expr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latex_fractions_and_constants() {
+        let val = Ast::latex_to_ast(r"\frac{\pi}{2}").unwrap().eval();
+        assert!((val - std::f64::consts::FRAC_PI_2).abs() < 1e-12);
+        assert_eq!(Ast::latex_to_ast(r"\frac12 + \frac{3}{4}").unwrap().eval(), 1.25);
+        assert_eq!(Ast::latex_to_ast(r"\frac{\frac{1}{2}}{4}").unwrap().eval(), 0.125);
+    }
+
+    #[test]
+    fn latex_operators_and_functions() {
+        let latex = |s| Ast::latex_to_ast(s).unwrap();
+        assert_eq!(latex(r"x^{2} \cdot y"), Ast::string_to_ast("x^2 * y"));
+        assert_eq!(latex(r"2^{3 + 1} \times 3 \div 6").eval(), 8.0);
+        assert_eq!(latex(r"\sqrt{16} + \sqrt[3]{27}").eval(), 7.0);
+        assert_eq!(latex(r"\sin(x) + \cos{y}"), Ast::string_to_ast("sin(x) + cos(y)"));
+        assert_eq!(latex(r"\left( a + b \right)^{2}"), Ast::string_to_ast("(a + b)^2"));
+        assert_eq!(latex(r"2\pi r"), Ast::string_to_ast("2 * pi * r"));
+        assert_eq!(latex(r"\exp{\ln{2}}\,x"), Ast::string_to_ast("exp(ln(2)) * x"));
+    }
+
+    #[test]
+    fn latex_errors() {
+        assert_eq!(Ast::latex_to_ast(r"\int x"), Err(ParseError::UnsupportedLatex(r"\int".to_owned())));
+        assert_eq!(Ast::latex_to_ast(r"\frac{1}{2"), Err(ParseError::MissingClosingParen));
+        assert_eq!(Ast::latex_to_ast(r"1 + 2}"), Err(ParseError::UnsupportedLatex("unmatched }".to_owned())));
+        assert_eq!(Ast::latex_to_ast(r"\frac{1}"), Err(ParseError::UnexpectedEof));
+    }
+}
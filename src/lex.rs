@@ -1,6 +1,7 @@
-use logos::Logos;
+use logos::{Lexer, Logos, Span};
+use crate::error::{LexError, ParseError};
 
-#[derive(Logos, Debug, PartialEq, Clone, Copy)]
+#[derive(Logos, Debug, PartialEq, Clone)]
 #[logos(skip r"[ \t\n\f]+")] // Ignore this regex pattern between tokens
 pub enum Token {
     #[token("+")]
@@ -21,6 +22,9 @@ pub enum Token {
     #[token("!")]
     Factorial,
 
+    #[token("%")]
+    Percent,
+
     #[token("sin")]
     Sin,
 
@@ -36,16 +40,148 @@ pub enum Token {
     #[token("ln")]
     Log,
 
+    #[token("logb")]
+    LogBase,
+
+    #[token("sqrt")]
+    Sqrt,
+
+    #[token("asinh")]
+    Asinh,
+
+    #[token("acosh")]
+    Acosh,
+
+    #[token("atanh")]
+    Atanh,
+
+    #[token("pi")]
+    Pi,
+
+    #[token("e")]
+    Euler,
+
+    #[token("tau")]
+    Tau,
+
+    #[token("phi")]
+    Phi,
+
+    #[token("inf")]
+    Inf,
+
+    #[token("i")]
+    Imaginary,
+
     #[token("(")]
     LParens,
 
     #[token(")")]
     RParens,
 
+    #[token(",")]
+    Comma,
+
     // Regex from the Logos tutorial book
     // https://logos.maciej.codes/examples/json.html
     #[regex(r"(?:0|[1-9]\d*)(?:\.\d+)?(?:[eE][+-]?\d+)?", |lex| lex.slice().parse::<f64>().unwrap())]
+    // Logos has no lookahead, so instead match the longer forms starting with 0 that are not
+    // numbers yet, like 07 or the radix prefixes 0b, 0o and 0x, and reject them as a whole
+    // rather than reading the 0 as a number by itself
+    #[regex(r"0\d[0-9.]*", |_| None::<f64>)]
+    #[regex(r"0[bBoOxX][0-9a-zA-Z_]*", |_| None::<f64>)]
     Number(f64),
+
+    // Lowest priority so keyword tokens above win on equal-length matches
+    #[regex(r"[a-zA-Z_][a-zA-Z0-9_]*", |lex| lex.slice().to_owned(), priority = 1)]
+    Identifier(String),
+}
+
+impl Token {
+    /// Named constants recognized by the lexer
+    pub const CONSTANTS: [Token; 6] = [Token::Pi, Token::Euler, Token::Tau, Token::Phi, Token::Inf, Token::Imaginary];
+
+    /// The source text matched by a keyword or operator token
+    pub fn symbol(&self) -> Option<&'static str> {
+        let s = match self {
+            Token::Plus => "+",
+            Token::Minus => "-",
+            Token::Multiply => "*",
+            Token::Divide => "/",
+            Token::Power => "^",
+            Token::Factorial => "!",
+            Token::Percent => "%",
+            Token::Sin => "sin",
+            Token::Cos => "cos",
+            Token::Tan => "tan",
+            Token::Exp => "exp",
+            Token::Log => "ln",
+            Token::LogBase => "logb",
+            Token::Sqrt => "sqrt",
+            Token::Asinh => "asinh",
+            Token::Acosh => "acosh",
+            Token::Atanh => "atanh",
+            Token::Pi => "pi",
+            Token::Euler => "e",
+            Token::Tau => "tau",
+            Token::Phi => "phi",
+            Token::Inf => "inf",
+            Token::Imaginary => "i",
+            Token::LParens => "(",
+            Token::RParens => ")",
+            Token::Comma => ",",
+            Token::Number(_) | Token::Identifier(_) => return None
+        };
+        Some(s)
+    }
+}
+
+/// The tokens of a source string, which can be peeked one ahead like `Peekable` while still
+/// knowing where in the source the last token came from
+pub(crate) struct TokenStream<'s> {
+    lexer: Lexer<'s, Token>,
+    peeked: Option<(Option<Result<Token, ()>>, Span)>,
+    span: Span
+}
+
+impl<'s> TokenStream<'s> {
+    pub(crate) fn new(source: &'s str) -> Self {
+        Self { lexer: Token::lexer(source), peeked: None, span: 0..0 }
+    }
+
+    pub(crate) fn peek(&mut self) -> Option<&Result<Token, ()>> {
+        if self.peeked.is_none() {
+            let token = self.lexer.next();
+            self.peeked = Some((token, self.lexer.span()));
+        }
+        self.peeked.as_ref().and_then(|(token, _)| token.as_ref())
+    }
+
+    /// The byte range of the token last returned by `next`
+    pub(crate) fn span(&self) -> Span {
+        self.span.clone()
+    }
+
+    /// The error for the unrecognized input just returned by `next`
+    pub(crate) fn error(&self) -> ParseError {
+        ParseError::LexError(LexError::at(self.lexer.source(), self.span().start))
+    }
+}
+
+impl Iterator for TokenStream<'_> {
+    type Item = Result<Token, ()>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (token, span) = match self.peeked.take() {
+            Some(peeked) => peeked,
+            None => {
+                let token = self.lexer.next();
+                (token, self.lexer.span())
+            }
+        };
+        self.span = span;
+        token
+    }
 }
 
 #[cfg(test)]
@@ -74,4 +210,106 @@ mod tests {
         assert_eq!(lex.next(), Some(Ok(RParens)));
         assert_eq!(lex.next(), Some(Ok(RParens)));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn parse_identifiers() {
+        let mut lex = Token::lexer("sin(x) + sinx");
+
+        assert_eq!(lex.next(), Some(Ok(Sin)));
+        assert_eq!(lex.next(), Some(Ok(LParens)));
+        assert_eq!(lex.next(), Some(Ok(Identifier("x".to_owned()))));
+        assert_eq!(lex.next(), Some(Ok(RParens)));
+        assert_eq!(lex.next(), Some(Ok(Plus)));
+        assert_eq!(lex.next(), Some(Ok(Identifier("sinx".to_owned()))));
+        assert_eq!(lex.next(), None);
+    }
+
+    #[test]
+    fn keywords_outrank_identifiers() {
+        let keywords = [Sin, Cos, Tan, Exp, Log, LogBase, Sqrt, Asinh, Acosh, Atanh, Pi, Euler, Tau, Phi, Inf, Imaginary];
+        for keyword in keywords {
+            let word = keyword.symbol().unwrap();
+            assert_eq!(Token::lexer(word).collect::<Vec<_>>(), vec![Ok(keyword.clone())], "{}", word);
+            // Anything longer is a name of its own
+            for name in [format!("{}_", word), format!("{}2", word), format!("_{}", word)] {
+                assert_eq!(Token::lexer(&name).collect::<Vec<_>>(), vec![Ok(Identifier(name.clone()))]);
+            }
+        }
+        assert_eq!(Token::lexer("foobar").next(), Some(Ok(Identifier("foobar".to_owned()))));
+    }
+
+    #[test]
+    fn parse_log_base() {
+        let mut lex = Token::lexer("logb(2, 8) + ln(logbx)");
+
+        assert_eq!(lex.next(), Some(Ok(LogBase)));
+        assert_eq!(lex.next(), Some(Ok(LParens)));
+        assert_eq!(lex.next(), Some(Ok(Number(2f64))));
+        assert_eq!(lex.next(), Some(Ok(Comma)));
+        assert_eq!(lex.next(), Some(Ok(Number(8f64))));
+        assert_eq!(lex.next(), Some(Ok(RParens)));
+        assert_eq!(lex.next(), Some(Ok(Plus)));
+        assert_eq!(lex.next(), Some(Ok(Log)));
+        assert_eq!(lex.next(), Some(Ok(LParens)));
+        assert_eq!(lex.next(), Some(Ok(Identifier("logbx".to_owned()))));
+    }
+
+    #[test]
+    fn parse_leading_zeros() {
+        for s in ["0b10", "0x1F", "0o17", "07", "00.5", "0x"] {
+            let mut lex = Token::lexer(s);
+            assert_eq!(lex.next(), Some(Err(())), "{} should not lex", s);
+            assert_eq!(lex.next(), None);
+        }
+
+        let mut lex = Token::lexer("0 + 0.25 * 10e2 - 0e1");
+        assert_eq!(lex.next(), Some(Ok(Number(0f64))));
+        assert_eq!(lex.next(), Some(Ok(Plus)));
+        assert_eq!(lex.next(), Some(Ok(Number(0.25))));
+        assert_eq!(lex.next(), Some(Ok(Multiply)));
+        assert_eq!(lex.next(), Some(Ok(Number(1000f64))));
+        assert_eq!(lex.next(), Some(Ok(Minus)));
+        assert_eq!(lex.next(), Some(Ok(Number(0f64))));
+        assert_eq!(lex.next(), None);
+    }
+
+    #[test]
+    fn token_stream_spans() {
+        let mut tokens = TokenStream::new("12 + x1 @");
+
+        assert_eq!(tokens.next(), Some(Ok(Number(12f64))));
+        assert_eq!(tokens.span(), 0..2);
+        // Peeking does not move the span on
+        assert_eq!(tokens.peek(), Some(&Ok(Plus)));
+        assert_eq!(tokens.span(), 0..2);
+        assert_eq!(tokens.next(), Some(Ok(Plus)));
+        assert_eq!(tokens.span(), 3..4);
+        assert_eq!(tokens.next(), Some(Ok(Identifier("x1".to_owned()))));
+        assert_eq!(tokens.next(), Some(Err(())));
+        assert_eq!(tokens.span(), 8..9);
+        assert_eq!(tokens.peek(), None);
+        assert_eq!(tokens.next(), None);
+    }
+
+    #[test]
+    fn parse_constants() {
+        let mut lex = Token::lexer("pi*e + pie - 2e3");
+
+        assert_eq!(lex.next(), Some(Ok(Pi)));
+        assert_eq!(lex.next(), Some(Ok(Multiply)));
+        assert_eq!(lex.next(), Some(Ok(Euler)));
+        assert_eq!(lex.next(), Some(Ok(Plus)));
+        assert_eq!(lex.next(), Some(Ok(Identifier("pie".to_owned()))));
+        assert_eq!(lex.next(), Some(Ok(Minus)));
+        assert_eq!(lex.next(), Some(Ok(Number(2000f64))));
+
+        let mut lex = Token::lexer("tau - phi / inf + infinity");
+        assert_eq!(lex.next(), Some(Ok(Tau)));
+        assert_eq!(lex.next(), Some(Ok(Minus)));
+        assert_eq!(lex.next(), Some(Ok(Phi)));
+        assert_eq!(lex.next(), Some(Ok(Divide)));
+        assert_eq!(lex.next(), Some(Ok(Inf)));
+        assert_eq!(lex.next(), Some(Ok(Plus)));
+        assert_eq!(lex.next(), Some(Ok(Identifier("infinity".to_owned()))));
+    }
+}
@@ -1,3 +1,32 @@
 pub mod lex;
 pub mod parse;
-pub mod ast;
\ No newline at end of file
+pub mod ast;
+pub mod error;
+pub mod context;
+pub mod config;
+mod analysis;
+mod codegen;
+mod complex;
+mod cse;
+mod diff;
+mod interval;
+mod latex;
+mod mathml;
+mod numeric;
+mod ode;
+mod roots;
+mod polynomial;
+mod simplify;
+pub mod rewrite;
+pub mod optimize;
+pub mod extrema;
+pub mod memoize;
+pub mod serialize;
+pub mod openmath;
+pub mod profile;
+pub mod rational;
+pub mod path;
+pub mod library;
+pub mod ffi;
+#[cfg(feature = "wasm")]
+pub mod wasm;
@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+use crate::ast::Ast;
+use crate::error::{CalcError, ParseError};
+
+/// A collection of named expressions that can be saved to and loaded from a file
+/// The file has one `name = expression` line per entry, with the expression in infix notation
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExpressionLibrary {
+    entries: HashMap<String, Ast>
+}
+
+impl ExpressionLibrary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store `ast` under `name`, replacing any expression already saved with that name
+    pub fn insert(&mut self, name: &str, ast: Ast) {
+        self.entries.insert(name.to_owned(), ast);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Ast> {
+        self.entries.get(name)
+    }
+
+    /// All entries with their expressions, sorted by name
+    pub fn entries(&self) -> Vec<(&str, &Ast)> {
+        let mut entries: Vec<(&str, &Ast)> = self.entries.iter().map(|(name, ast)| (name.as_str(), ast)).collect();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+        entries
+    }
+
+    /// Write every entry to the file at `path`, sorted by name
+    pub fn save_to_file(&self, path: &Path) -> io::Result<()> {
+        let mut file = File::create(path)?;
+        for (name, ast) in self.entries() {
+            writeln!(file, "{} = {}", name, ast.to_infix_string())?;
+        }
+        Ok(())
+    }
+
+    /// Read a library written by `save_to_file`, skipping blank lines
+    pub fn load_from_file(path: &Path) -> Result<Self, CalcError> {
+        let contents = fs::read_to_string(path).map_err(|e| ParseError::Io(e.to_string()))?;
+        let mut library = Self::new();
+        for line in contents.lines().filter(|l| !l.trim().is_empty()) {
+            let (name, expr) = line.split_once('=').ok_or_else(|| ParseError::MissingName(line.to_owned()))?;
+            library.insert(name.trim(), Ast::try_from_str(expr)?);
+        }
+        Ok(library)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn library_round_trip() {
+        let mut library = ExpressionLibrary::new();
+        library.insert("kinetic", Ast::string_to_ast("m * v^2 / 2"));
+        library.insert("gaussian", Ast::string_to_ast("exp(-(x - mu)^2 / (2 * s^2)) / sqrt(2 * pi * s^2)"));
+        library.insert("third", Ast::string_to_ast("1 / 3"));
+        library.insert("neg", Ast::string_to_ast("-x!"));
+        // Shared subexpressions are saved inlined so that they parse back
+        library.insert("shared", Ast::string_to_ast("sin(x) * sin(x) + 1").eliminate_common_subexpressions());
+        assert_eq!(library.get("third"), Some(&Ast::string_to_ast("1/3")));
+        assert_eq!(library.get("missing"), None);
+
+        let path = std::env::temp_dir().join(format!("ast-calc-library-{}.txt", std::process::id()));
+        library.save_to_file(&path).unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        assert!(saved.starts_with("gaussian = "));
+        assert!(saved.ends_with("third = 1 / 3\n"));
+
+        let loaded = ExpressionLibrary::load_from_file(&path);
+        fs::remove_file(&path).unwrap();
+        library.insert("shared", Ast::string_to_ast("sin(x) * sin(x) + 1"));
+        assert_eq!(loaded, Ok(library));
+    }
+
+    #[test]
+    fn library_load_errors() {
+        let path = std::env::temp_dir().join(format!("ast-calc-library-bad-{}.txt", std::process::id()));
+        fs::write(&path, "a = 1 +\n").unwrap();
+        assert_eq!(ExpressionLibrary::load_from_file(&path), Err(CalcError::Parse(ParseError::UnexpectedEof)));
+        fs::write(&path, "\nb = 2\n1 + 2\n").unwrap();
+        assert_eq!(ExpressionLibrary::load_from_file(&path), Err(CalcError::Parse(ParseError::MissingName("1 + 2".to_owned()))));
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(ExpressionLibrary::load_from_file(&path), Err(CalcError::Parse(ParseError::Io(_)))));
+    }
+}
@@ -1,14 +1,31 @@
 use std::fmt::{Display, Formatter};
+use std::fs::File;
 use std::io;
-use std::io::{BufRead, Write};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
 use std::process::exit;
 use ast_calc::ast::Ast;
+use ast_calc::context::EvalContext;
+use ast_calc::lex::Token;
+use ast_calc::library::ExpressionLibrary;
+use ast_calc::parse::{BinOp, UnOp};
 use clap::{Parser, ValueEnum};
+use history::History;
+
+mod history;
+
+// Relative difference between f32 and f64 results above which --f32 mode warns
+const F32_WARN_THRESHOLD: f64 = 1e-6;
+
+// Number of entries printed by :history
+const HISTORY_SHOWN: usize = 20;
 
 #[derive(ValueEnum, Debug, Clone, Copy)]
 enum AstView {
     Hierarchy,
     Tree,
+    Rpn,
+    Prefix,
 }
 
 impl Display for AstView {
@@ -16,6 +33,8 @@ impl Display for AstView {
         let string = match &self {
             AstView::Hierarchy => "hierarchy",
             AstView::Tree => "tree",
+            AstView::Rpn => "rpn",
+            AstView::Prefix => "prefix",
         };
         write!(f, "{}", string)
     }
@@ -29,37 +48,531 @@ struct Args {
 
     /// Choose the printing mode when viewing ASTs
     #[arg(short='v', long, default_value_t=AstView::Hierarchy, requires="ast_mode")]
-    ast_view: AstView
+    ast_view: AstView,
+
+    /// Only print the AST of each submitted expression without evaluating it
+    #[arg(long, default_value_t=false, requires="ast_mode")]
+    no_eval: bool,
+
+    /// Evaluate using single precision, warning when the result differs from double precision
+    #[arg(long, default_value_t=false)]
+    f32: bool,
+
+    /// Evaluate over the complex numbers, with i as the imaginary unit
+    #[arg(long, default_value_t=false, conflicts_with="f32")]
+    complex: bool,
+
+    /// Only check that each line of input parses, exiting with code 1 if any do not
+    #[arg(long, default_value_t=false, conflicts_with="ast_mode")]
+    validate: bool,
+
+    /// Hide the welcome message and prompts, printing only the output of each line
+    #[arg(short, long, default_value_t=false)]
+    quiet: bool,
+
+    /// Evaluate each line as a plain expression and print only its value, for use in scripts
+    #[arg(long, default_value_t=false, conflicts_with_all=["ast_mode", "validate"])]
+    batch: bool
 }
 
-fn main() {
-    let args = Args::parse();
+/// Parse and evaluate a single expression, returning whether it succeeded
+fn run_expression(args: &Args, ctx: &EvalContext, line: &str) -> bool {
+    let ast = match ctx.parse(line) {
+        Ok(ast) => ast,
+        Err(e) => {
+            println!("Could not parse expression: {}", e);
+            return false
+        }
+    };
+    if args.ast_mode {
+        println!("Here is the AST for your expression:");
+        match args.ast_view {
+            AstView::Hierarchy => ast.print_hierarchy(),
+            AstView::Tree => println!("{}", ast),
+            AstView::Rpn => println!("{}", ast.to_postfix_string()),
+            AstView::Prefix => println!("{}", ast.to_prefix_string())
+        }
+    }
+    for warning in ast.lint() {
+        println!("Warning: {}", warning);
+    }
+    if args.no_eval {
+        return true
+    }
+    let ast = ast.bind_variables(ctx);
+    if args.complex {
+        match ast.eval_complex() {
+            Ok((re, im)) if im < 0.0 => println!("The expression evaluates to: {} - {}i", re, -im),
+            Ok((re, im)) => println!("The expression evaluates to: {} + {}i", re, im),
+            Err(e) => {
+                println!("Could not evaluate expression: {}", e);
+                return false
+            }
+        }
+        return true
+    }
+    if args.f32 {
+        match (ast.eval_f32(), ast.try_eval()) {
+            (Ok(val), Ok(val64)) => {
+                println!("The expression evaluates to: {}", val);
+                if ((val as f64) - val64).abs() > F32_WARN_THRESHOLD * val64.abs().max(1.0) {
+                    println!("Warning: the double precision result {} differs significantly", val64);
+                }
+            },
+            (Err(e), _) | (_, Err(e)) => {
+                println!("Could not evaluate expression: {}", e);
+                return false
+            }
+        }
+        return true
+    }
+    match ast.try_eval() {
+        Ok(val) => {
+            println!("The expression evaluates to: {}", val);
+            true
+        },
+        Err(e) => {
+            println!("Could not evaluate expression: {}", e);
+            false
+        }
+    }
+}
+
+// Split a definition like f(x, y) = body into its name, parameters and body
+fn parse_definition(def: &str) -> Option<(&str, Vec<String>, &str)> {
+    let (head, body) = def.split_once('=')?;
+    let (name, params) = head.trim().strip_suffix(')')?.split_once('(')?;
+    let params = params.split(',').map(|p| p.trim().to_owned()).filter(|p| !p.is_empty()).collect();
+    Some((name.trim(), params, body))
+}
 
-    println!("Type exit or quit to stop the program!");
+// Write the context as let and def lines that replay it when loaded
+fn save_session(ctx: &EvalContext, mut w: impl Write) -> io::Result<()> {
+    for (name, val) in ctx.variables() {
+        writeln!(w, "let {} = {}", name, val)?;
+    }
+    for (name, f) in ctx.functions() {
+        writeln!(w, "def {}({}) = {}", name, f.params().join(", "), f.body())?;
+    }
+    Ok(())
+}
 
-    let stdin = io::stdin();
-    print!(">>> ");
+fn load_session(args: &Args, ctx: &mut EvalContext, r: impl BufRead) -> io::Result<()> {
+    for line in r.lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            run_line(args, ctx, &line);
+        }
+    }
+    Ok(())
+}
+
+/// Handle one line of REPL input, returning whether it was an expression or definition that succeeded
+fn run_line(args: &Args, ctx: &mut EvalContext, line: &str) -> bool {
+    let line = line.trim();
+    if line == ":help" {
+        println!("{}", help_text());
+    } else if line == ":vars" {
+        let vars = ctx.variables();
+        if vars.is_empty() {
+            println!("No variables defined");
+        }
+        for (name, val) in vars {
+            println!("{} = {}", name, val);
+        }
+    } else if line == ":fns" {
+        let fns = ctx.functions();
+        if fns.is_empty() {
+            println!("No functions defined");
+        }
+        for (name, f) in fns {
+            println!("{}({}) = {}", name, f.params().join(", "), f.body());
+        }
+    } else if line == ":clear" {
+        ctx.clear();
+    } else if let Some(name) = line.strip_prefix(":del ") {
+        if !ctx.remove(name.trim()) {
+            println!("Nothing named {} is defined", name.trim());
+        }
+    } else if let Some(path) = line.strip_prefix(":save ") {
+        let result = File::create(path.trim()).and_then(|f| save_session(ctx, f));
+        if let Err(e) = result {
+            println!("Could not save session to {}: {}", path.trim(), e);
+        }
+    } else if let Some(path) = line.strip_prefix(":load ") {
+        let result = File::open(path.trim()).and_then(|f| load_session(args, ctx, BufReader::new(f)));
+        if let Err(e) = result {
+            println!("Could not load session from {}: {}", path.trim(), e);
+        }
+    } else if let Some(binding) = line.strip_prefix("let ") {
+        let Some((name, expr)) = binding.split_once('=') else {
+            println!("Expected a binding like: let x = 1 + 2");
+            return false
+        };
+        let val = ctx.parse(expr)
+            .map_err(|e| e.to_string())
+            .and_then(|ast| ast.eval_with_context(ctx).map_err(|e| e.to_string()));
+        match val {
+            Ok(val) => {
+                ctx.set_variable(name.trim(), val);
+                println!("{} = {}", name.trim(), val);
+                return true
+            },
+            Err(e) => println!("Could not bind {}: {}", name.trim(), e)
+        }
+    } else if let Some(def) = line.strip_prefix("def ") {
+        let Some((name, params, body)) = parse_definition(def) else {
+            println!("Expected a definition like: def f(x, y) = x^2 + y");
+            return false
+        };
+        match ctx.define_function(name, params, body) {
+            Ok(()) => return true,
+            Err(e) => println!("Could not parse function body: {}", e)
+        }
+    } else {
+        return run_expression(args, ctx, line)
+    }
+    false
+}
+
+/// Handle a :lib command, which lists, adds to, saves or loads the library of named expressions
+fn run_lib_command(ctx: &EvalContext, library: &mut ExpressionLibrary, cmd: &str) {
+    let cmd = cmd.trim();
+    if cmd.is_empty() {
+        let entries = library.entries();
+        if entries.is_empty() {
+            println!("No expressions in the library");
+        }
+        for (name, ast) in entries {
+            println!("{} = {}", name, ast.to_infix_string());
+        }
+    } else if let Some(entry) = cmd.strip_prefix("add ") {
+        let Some((name, expr)) = entry.split_once('=') else {
+            println!("Expected an entry like: :lib add area = pi * r^2");
+            return
+        };
+        match ctx.parse(expr) {
+            Ok(ast) => library.insert(name.trim(), ast),
+            Err(e) => println!("Could not parse expression: {}", e)
+        }
+    } else if let Some(path) = cmd.strip_prefix("save ") {
+        if let Err(e) = library.save_to_file(Path::new(path.trim())) {
+            println!("Could not save library to {}: {}", path.trim(), e);
+        }
+    } else if let Some(path) = cmd.strip_prefix("load ") {
+        match ExpressionLibrary::load_from_file(Path::new(path.trim())) {
+            Ok(loaded) => *library = loaded,
+            Err(e) => println!("Could not load library from {}: {}", path.trim(), e)
+        }
+    } else {
+        println!("Expected one of :lib, :lib add name = expr, :lib save path or :lib load path");
+    }
+}
+
+/// Handle a line typed at the prompt, recording it in the history when it succeeds
+fn run_repl_line(args: &Args, ctx: &mut EvalContext, library: &mut ExpressionLibrary, history: &mut History, line: &str) {
+    if let Some(cmd) = line.trim().strip_prefix(":lib") {
+        return run_lib_command(ctx, library, cmd)
+    }
+    let line = match line.trim().strip_prefix(":history") {
+        Some("") => {
+            for (n, entry) in history.recent(HISTORY_SHOWN) {
+                println!("{:>4}  {}", n, entry);
+            }
+            return
+        },
+        Some(n) => match n.trim().parse().ok().and_then(|n| history.get(n)) {
+            Some(entry) => {
+                println!("{}", entry);
+                entry.to_owned()
+            },
+            None => {
+                println!("No history entry {}", n.trim());
+                return
+            }
+        },
+        None => line.to_owned()
+    };
+    if run_line(args, ctx, &line) {
+        if let Err(e) = history.push(&line) {
+            println!("Could not write to history file: {}", e);
+        }
+    }
+}
+
+fn help_text() -> String {
+    let symbol = |token: Token| token.symbol().unwrap_or_default();
+    let mut lines = vec!["Binary operators (higher precedence binds tighter):".to_owned()];
+    for op in BinOp::ALL {
+        lines.push(format!("  a {} b    precedence {}", op, op.precedence()));
+    }
+
+    lines.push("Unary operators and functions:".to_owned());
+    for op in UnOp::ALL {
+        let name = symbol(Token::from(op));
+        let usage = match op {
+            UnOp::Negative => format!("{}x", name),
+            op if op.is_postfix() => format!("x{}", name),
+            _ => format!("{}(x)", name)
+        };
+        lines.push(format!("  {:<8}  precedence {}", usage, op.precedence()));
+    }
+
+    lines.push(format!("  logb(b, x)  precedence {}", BinOp::LogBase.precedence()));
+
+    let constants: Vec<&str> = Token::CONSTANTS.into_iter().map(symbol).collect();
+    lines.push(format!("Constants: {}", constants.join(", ")));
+    lines.push("Notes:".to_owned());
+    lines.push("  Factorial and percent are written after their operand, and factorial only accepts non-negative integers".to_owned());
+    lines.push("  Any other name is a variable, and i is the imaginary unit when using --complex".to_owned());
+    lines.push("Session commands:".to_owned());
+    lines.push("  let x = expr         bind a variable to the value of expr".to_owned());
+    lines.push("  def f(x, y) = expr   define a function".to_owned());
+    lines.push("  :vars, :fns          list the bound variables or defined functions".to_owned());
+    lines.push("  :del name, :clear    remove one or all variables and functions".to_owned());
+    lines.push("  :save path, :load path   write the session to a file or replay one".to_owned());
+    lines.push("  :history, :history n     list recent inputs or rerun entry n".to_owned());
+    lines.push("  :lib, :lib add name = expr   list or add to the library of named expressions".to_owned());
+    lines.push("  :lib save path, :lib load path   write the library to a file or read one back".to_owned());
+    lines.push("  Type exit, quit or q to stop the program".to_owned());
+    lines.join("\n")
+}
+
+fn validate_lines(input: impl BufRead) -> bool {
+    let mut all_valid = true;
+    for line in input.lines() {
+        match line {
+            Ok(l) => match Ast::try_from_str(&l) {
+                Ok(_) => println!("OK"),
+                Err(e) => {
+                    println!("{}", e);
+                    all_valid = false;
+                }
+            },
+            Err(e) => {
+                println!("Cannot read line from stdin: {}", e);
+                all_valid = false;
+            }
+        }
+    }
+    all_valid
+}
+
+// Evaluate each line as a standalone expression, writing one value per line and reporting
+// failures on stderr, returning whether every line succeeded
+fn batch_lines(args: &Args, input: impl BufRead, mut out: impl Write) -> bool {
+    let mut all_ok = true;
+    for line in input.lines() {
+        let result = line.map_err(|e| e.to_string()).and_then(|l| {
+            let ast = Ast::try_from_str(&l).map_err(|e| e.to_string())?;
+            let val = if args.complex {
+                let (re, im) = ast.eval_complex().map_err(|e| e.to_string())?;
+                if im < 0.0 { format!("{} - {}i", re, -im) } else { format!("{} + {}i", re, im) }
+            } else if args.f32 {
+                ast.eval_f32().map_err(|e| e.to_string())?.to_string()
+            } else {
+                ast.try_eval().map_err(|e| e.to_string())?.to_string()
+            };
+            Ok(val)
+        });
+        match result {
+            Ok(val) => {
+                let _ = writeln!(out, "{}", val);
+            },
+            Err(e) => {
+                eprintln!("{}", e);
+                all_ok = false;
+            }
+        }
+    }
+    all_ok
+}
+
+fn prompt(args: &Args, s: &str) {
+    if args.quiet {
+        return
+    }
+    print!("{}", s);
     let _ = io::stdout().flush();
-    for line in stdin.lock().lines() {
+}
+
+/// Read and run lines from the input until it ends or the user quits
+/// Lines ending in a backslash are joined with the next line before being run
+fn repl(args: &Args, input: impl BufRead, ctx: &mut EvalContext, library: &mut ExpressionLibrary, history: &mut History) {
+    let mut pending = String::new();
+    prompt(args, ">>> ");
+    for line in input.lines() {
         if let Ok(l) = line {
+            if let Some(start) = l.strip_suffix('\\') {
+                pending.push_str(start);
+                prompt(args, "... ");
+                continue
+            }
+            pending.push_str(&l);
+            let l = std::mem::take(&mut pending);
             if l == "exit" || l == "quit" || l == "q" {
-                exit(0)
+                return
             } else {
-                let ast = Ast::string_to_ast(&l);
-                if args.ast_mode {
-                    println!("Here is the AST for your expression:");
-                    match args.ast_view {
-                        AstView::Hierarchy => ast.print_hierarchy(),
-                        AstView::Tree => println!("{}", ast)
-                    }
-                }
-                println!("The expression evaluates to: {}", ast.eval());
+                run_repl_line(args, ctx, library, history, &l);
             }
         } else {
             println!("Cannot read line from stdin!");
         }
-        print!(">>> ");
-        let _ = io::stdout().flush();
+        prompt(args, ">>> ");
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+
+    if args.validate {
+        let valid = validate_lines(io::stdin().lock());
+        exit(if valid { 0 } else { 1 })
     }
+    if args.batch {
+        let ok = batch_lines(&args, io::stdin().lock(), io::stdout().lock());
+        exit(if ok { 0 } else { 1 })
+    }
+
+    if !args.quiet {
+        println!("Type exit or quit to stop the program, or :help to list what is supported!");
+    }
+
+    let mut ctx = EvalContext::new();
+    let mut library = ExpressionLibrary::new();
+    let mut history = History::load_default();
+    repl(&args, io::stdin().lock(), &mut ctx, &mut library, &mut history);
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_eval_skips_evaluation() {
+        let args = Args::parse_from(["ast-calc", "--no-eval", "--ast-mode"]);
+        assert!(args.no_eval);
+        let ctx = EvalContext::new();
+        run_expression(&args, &ctx, "1/0");
+        run_expression(&args, &ctx, "3.5!");
+
+        for view in ["tree", "rpn", "prefix"] {
+            let args = Args::parse_from(["ast-calc", "--no-eval", "-a", "-v", view]);
+            assert!(run_expression(&args, &ctx, "1/0"));
+        }
+
+        assert!(Args::try_parse_from(["ast-calc", "--no-eval"]).is_err());
+    }
+
+    #[test]
+    fn session_commands_update_context() {
+        let args = Args::parse_from(["ast-calc"]);
+        let mut ctx = EvalContext::new();
+        run_line(&args, &mut ctx, "let x = 2 + 1");
+        run_line(&args, &mut ctx, "let y = x^2");
+        run_line(&args, &mut ctx, "def f(a, b) = a * b + x");
+        assert_eq!(ctx.variables(), vec![("x", 3.0), ("y", 9.0)]);
+        assert_eq!(ctx.function("f").map(|f| f.params().to_vec()), Some(vec!["a".to_owned(), "b".to_owned()]));
+        run_line(&args, &mut ctx, ":vars");
+        run_line(&args, &mut ctx, ":fns");
+
+        run_line(&args, &mut ctx, ":del y");
+        assert_eq!(ctx.variable("y"), None);
+        run_line(&args, &mut ctx, ":clear");
+        assert_eq!(ctx, EvalContext::new());
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let args = Args::parse_from(["ast-calc"]);
+        let mut ctx = EvalContext::new();
+        run_line(&args, &mut ctx, "let x = 1 / 3");
+        run_line(&args, &mut ctx, "let y = -2");
+        run_line(&args, &mut ctx, "def f(a) = ln(a)^2 + x");
+        run_line(&args, &mut ctx, "def g(a, b) = f(a) * b");
+
+        let path = std::env::temp_dir().join(format!("ast-calc-session-{}.txt", std::process::id()));
+        run_line(&args, &mut ctx, &format!(":save {}", path.display()));
+        let saved = std::fs::read_to_string(&path).unwrap();
+        assert!(saved.starts_with("let x = 0.3333333333333333\nlet y = -2\ndef f(a) = ln(a)^2 + x\n"));
+
+        let mut loaded = EvalContext::new();
+        run_line(&args, &mut loaded, &format!(":load {}", path.display()));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, ctx);
+    }
+
+    #[test]
+    fn history_records_successes() {
+        let args = Args::parse_from(["ast-calc"]);
+        let mut ctx = EvalContext::new();
+        let mut library = ExpressionLibrary::new();
+        let mut history = History::load(None);
+        run_repl_line(&args, &mut ctx, &mut library, &mut history, "1 + 2");
+        run_repl_line(&args, &mut ctx, &mut library, &mut history, "1 +");
+        run_repl_line(&args, &mut ctx, &mut library, &mut history, "let x = 4");
+        run_repl_line(&args, &mut ctx, &mut library, &mut history, ":vars");
+        run_repl_line(&args, &mut ctx, &mut library, &mut history, ":history");
+        assert_eq!(history.recent(HISTORY_SHOWN).collect::<Vec<_>>(), vec![(1, "1 + 2"), (2, "let x = 4")]);
+
+        run_repl_line(&args, &mut ctx, &mut library, &mut history, ":history 1");
+        run_repl_line(&args, &mut ctx, &mut library, &mut history, ":history 9");
+        assert_eq!(history.get(3), Some("1 + 2"));
+        assert_eq!(history.get(4), None);
+    }
+
+    #[test]
+    fn lib_commands_save_and_load() {
+        let args = Args::parse_from(["ast-calc"]);
+        let mut ctx = EvalContext::new();
+        let mut library = ExpressionLibrary::new();
+        let mut history = History::load(None);
+        run_repl_line(&args, &mut ctx, &mut library, &mut history, ":lib add area = pi * r^2");
+        run_repl_line(&args, &mut ctx, &mut library, &mut history, ":lib add bad = 1 +");
+        run_repl_line(&args, &mut ctx, &mut library, &mut history, ":lib");
+        assert_eq!(library.get("area"), Some(&Ast::string_to_ast("pi * r^2")));
+        assert_eq!(library.entries().len(), 1);
+
+        let path = std::env::temp_dir().join(format!("ast-calc-lib-{}.txt", std::process::id()));
+        run_repl_line(&args, &mut ctx, &mut library, &mut history, &format!(":lib save {}", path.display()));
+        let mut loaded = ExpressionLibrary::new();
+        run_repl_line(&args, &mut ctx, &mut loaded, &mut history, &format!(":lib load {}", path.display()));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, library);
+        assert_eq!(history.get(1), None);
+    }
+
+    #[test]
+    fn backslash_continues_line() {
+        let args = Args::parse_from(["ast-calc"]);
+        let mut ctx = EvalContext::new();
+        let mut library = ExpressionLibrary::new();
+        let mut history = History::load(None);
+        let input = io::Cursor::new("sin(1)^2 + cos(1)^2 + \\\ntan(1)^2\n1 + \\\n2 \\\n* 3\nquit\n4\n");
+        repl(&args, input, &mut ctx, &mut library, &mut history);
+        let entries: Vec<(usize, &str)> = history.recent(HISTORY_SHOWN).collect();
+        assert_eq!(entries, vec![(1, "sin(1)^2 + cos(1)^2 + tan(1)^2"), (2, "1 + 2 * 3")]);
+    }
+
+    #[test]
+    fn batch_prints_only_values() {
+        let args = Args::parse_from(["ast-calc", "--batch"]);
+        let mut out = Vec::new();
+        assert!(batch_lines(&args, io::Cursor::new("1 + 2\n2^10\n"), &mut out));
+        assert_eq!(String::from_utf8(out).unwrap(), "3\n1024\n");
+
+        let mut out = Vec::new();
+        assert!(!batch_lines(&args, io::Cursor::new(":help\nlet x = 1\n3!\n"), &mut out));
+        assert_eq!(String::from_utf8(out).unwrap(), "6\n");
+
+        assert!(Args::try_parse_from(["ast-calc", "--batch", "-a"]).is_err());
+    }
+
+    #[test]
+    fn help_lists_every_operator() {
+        let help = help_text();
+        for name in ["a ^ b    precedence 5", "sqrt(x)", "ln(x)", "logb(b, x)", "x!", "-x", "pi, e, tau, phi, inf, i"] {
+            assert!(help.contains(name), "{} missing from help", name);
+        }
+    }
+}
@@ -0,0 +1,357 @@
+use std::collections::HashMap;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use crate::ast::Ast;
+use crate::error::ParseError;
+use crate::lex::TokenStream;
+use crate::parse::{parse_expr_with_options, BinOp, Expr, ParseOptions, UnOp};
+
+// An element of a MathML document along with its children, or the text inside one
+enum Node {
+    Element(String, Vec<Node>),
+    Text(String)
+}
+
+fn invalid(e: impl ToString) -> ParseError {
+    ParseError::InvalidMathml(e.to_string())
+}
+
+fn push_text(children: &mut Vec<Node>, s: &str) {
+    match children.last_mut() {
+        Some(Node::Text(text)) => text.push_str(s),
+        _ => children.push(Node::Text(s.to_owned()))
+    }
+}
+
+// Read the document into a list of top level nodes, ignoring attributes, comments and declarations
+fn read_nodes(xml: &str) -> Result<Vec<Node>, ParseError> {
+    let mut reader = Reader::from_str(xml);
+    reader.config_mut().trim_text(true);
+    // Elements still waiting for their end tag, below an unnamed root holding the top level nodes
+    let mut open = vec![(String::new(), Vec::new())];
+    loop {
+        let (_, children) = open.last_mut().expect("The root is never closed");
+        match reader.read_event().map_err(invalid)? {
+            Event::Start(e) => open.push((e.local_name().as_ref().to_owned(), Vec::new())),
+            Event::Empty(e) => children.push(Node::Element(e.local_name().as_ref().to_owned(), Vec::new())),
+            Event::End(_) => {
+                let (name, children) = open.pop().expect("The root is never closed");
+                match open.last_mut() {
+                    Some((_, parent)) => parent.push(Node::Element(name, children)),
+                    None => return Err(invalid("unexpected end tag"))
+                }
+            },
+            Event::Text(text) => push_text(children, &text),
+            Event::CData(text) => push_text(children, &text),
+            Event::GeneralRef(r) => {
+                let c = match r.resolve_char_ref().map_err(invalid)? {
+                    Some(c) => c,
+                    None => match &*r {
+                        "lt" => '<',
+                        "gt" => '>',
+                        "amp" => '&',
+                        "quot" => '"',
+                        "apos" => '\'',
+                        name => return Err(invalid(format!("unknown entity &{};", name)))
+                    }
+                };
+                push_text(children, c.encode_utf8(&mut [0; 4]));
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    match open.pop() {
+        Some((_, nodes)) if open.is_empty() => Ok(nodes),
+        _ => Err(invalid("unclosed element"))
+    }
+}
+
+// The text inside a token element like <mn>, which may not contain other elements
+fn text(name: &str, children: &[Node]) -> Result<String, ParseError> {
+    children.iter().map(|node| match node {
+        Node::Text(text) => Ok(text.trim()),
+        Node::Element(child, _) => Err(invalid(format!("<{}> inside <{}>", child, name)))
+    }).collect()
+}
+
+fn operator(s: &str) -> Result<&'static str, ParseError> {
+    let op = match s {
+        "+" => "+",
+        "-" | "\u{2212}" => "-",
+        // Invisible times is how MathML marks implicit products
+        "*" | "\u{d7}" | "\u{b7}" | "\u{22c5}" | "\u{2062}" => "*",
+        "/" | "\u{f7}" => "/",
+        "^" => "^",
+        "!" => "!",
+        "%" => "%",
+        "(" => "(",
+        ")" => ")",
+        "," => ",",
+        // Function application sits between a function name and its argument
+        "\u{2061}" => "",
+        _ => return Err(invalid(format!("unsupported operator {}", s)))
+    };
+    Ok(op)
+}
+
+fn identifier(s: &str) -> Result<&str, ParseError> {
+    let name = match s {
+        "\u{3c0}" => "pi",
+        "\u{3c4}" => "tau",
+        "\u{3c6}" | "\u{3d5}" => "phi",
+        "\u{221e}" => "inf",
+        s if !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') => s,
+        _ => return Err(invalid(format!("unsupported identifier {}", s)))
+    };
+    Ok(name)
+}
+
+// Rewrite a MathML node into the calculator's own infix syntax
+// Every grouping element is wrapped in parentheses so the tree keeps the shape of the markup
+fn translate(node: &Node, out: &mut String) -> Result<(), ParseError> {
+    let (name, children) = match node {
+        Node::Element(name, children) => (name.as_str(), children),
+        Node::Text(text) => return Err(invalid(format!("unexpected text {}", text)))
+    };
+    let translate_all = |out: &mut String| children.iter().try_for_each(|child| translate(child, out));
+    let pair = || match children.as_slice() {
+        [first, second] => Ok((first, second)),
+        _ => Err(invalid(format!("<{}> needs exactly two children", name)))
+    };
+    match name {
+        "math" => translate_all(out)?,
+        "mrow" => {
+            out.push_str(" (");
+            translate_all(out)?;
+            out.push_str(") ");
+        },
+        "mn" => {
+            let n = text(name, children)?;
+            if n.is_empty() || !n.chars().all(|c| c.is_ascii_digit() || c == '.') {
+                return Err(invalid(format!("{} is not a number", n)))
+            }
+            out.push_str(&format!(" {} ", n));
+        },
+        "mi" => out.push_str(&format!(" {} ", identifier(&text(name, children)?)?)),
+        "mo" => out.push_str(&format!(" {} ", operator(&text(name, children)?)?)),
+        "mfrac" => {
+            let (num, den) = pair()?;
+            out.push_str(" ((");
+            translate(num, out)?;
+            out.push_str(") / (");
+            translate(den, out)?;
+            out.push_str(")) ");
+        },
+        "msup" => {
+            let (base, exponent) = pair()?;
+            out.push_str(" ((");
+            translate(base, out)?;
+            out.push_str(")^(");
+            translate(exponent, out)?;
+            out.push_str(")) ");
+        },
+        "msqrt" => {
+            out.push_str(" sqrt(");
+            translate_all(out)?;
+            out.push_str(") ");
+        },
+        _ => return Err(invalid(format!("unsupported element <{}>", name)))
+    }
+    Ok(())
+}
+
+fn parens(e: &Expr, needs_parens: bool, out: &mut String) {
+    if needs_parens {
+        out.push_str("<mrow><mo>(</mo>");
+        e.write_mathml(out);
+        out.push_str("<mo>)</mo></mrow>");
+    } else {
+        e.write_mathml(out);
+    }
+}
+
+// A number, with negatives written as a minus sign before <mn> and infinity as the symbol
+fn number(n: f64, out: &mut String) {
+    if n.is_sign_negative() {
+        out.push_str("<mrow><mo>-</mo>");
+        number(-n, out);
+        out.push_str("</mrow>");
+    } else if n.is_infinite() {
+        out.push_str("<mi>\u{221e}</mi>");
+    } else {
+        out.push_str(&format!("<mn>{}</mn>", n));
+    }
+}
+
+impl Expr {
+    // Write presentation MathML, adding the same parentheses as the infix form
+    fn write_mathml(&self, out: &mut String) {
+        let prec = self.display_prec();
+        match self {
+            Expr::BinaryOp(BinOp::LogBase, base, e) => {
+                out.push_str("<mrow><mi>logb</mi><mo>(</mo>");
+                base.write_mathml(out);
+                out.push_str("<mo>,</mo>");
+                e.write_mathml(out);
+                out.push_str("<mo>)</mo></mrow>");
+            },
+            Expr::BinaryOp(BinOp::Divide, e1, e2) => {
+                out.push_str("<mfrac>");
+                e1.write_mathml(out);
+                e2.write_mathml(out);
+                out.push_str("</mfrac>");
+            },
+            Expr::BinaryOp(BinOp::Power, e1, e2) => {
+                out.push_str("<msup>");
+                parens(e1, e1.display_prec() <= prec, out);
+                e2.write_mathml(out);
+                out.push_str("</msup>");
+            },
+            Expr::BinaryOp(op, e1, e2) => {
+                out.push_str("<mrow>");
+                parens(e1, e1.display_prec() < prec, out);
+                let symbol = if *op == BinOp::Multiply { "\u{d7}".to_owned() } else { op.to_string() };
+                out.push_str(&format!("<mo>{}</mo>", symbol));
+                parens(e2, e2.display_prec() <= prec, out);
+                out.push_str("</mrow>");
+            },
+            Expr::UnaryOp(op @ (UnOp::Factorial | UnOp::Percent), e) => {
+                out.push_str("<mrow>");
+                parens(e, e.display_prec() < prec, out);
+                out.push_str(&format!("<mo>{}</mo></mrow>", op));
+            },
+            Expr::UnaryOp(UnOp::Negative, e) => {
+                out.push_str("<mrow><mo>-</mo>");
+                parens(e, e.display_prec() < prec, out);
+                out.push_str("</mrow>");
+            },
+            Expr::UnaryOp(UnOp::Sqrt, e) => {
+                out.push_str("<msqrt>");
+                e.write_mathml(out);
+                out.push_str("</msqrt>");
+            },
+            Expr::UnaryOp(op, e) => {
+                // The lexer only accepts ln for the natural log
+                let name = if *op == UnOp::Log { "ln".to_owned() } else { op.to_string() };
+                out.push_str(&format!("<mrow><mi>{}</mi><mo>(</mo>", name));
+                e.write_mathml(out);
+                out.push_str("<mo>)</mo></mrow>");
+            },
+            Expr::Number(n) => number(*n, out),
+            Expr::Complex(re, im) => {
+                let imaginary = |out: &mut String| {
+                    if *im == 1.0 {
+                        out.push_str("<mi>i</mi>");
+                    } else {
+                        out.push_str("<mrow>");
+                        number(*im, out);
+                        out.push_str("<mo>\u{d7}</mo><mi>i</mi></mrow>");
+                    }
+                };
+                if *re == 0.0 {
+                    imaginary(out);
+                } else {
+                    out.push_str("<mrow>");
+                    number(*re, out);
+                    out.push_str("<mo>+</mo>");
+                    imaginary(out);
+                    out.push_str("</mrow>");
+                }
+            },
+            Expr::Variable(name) => out.push_str(&format!("<mi>{}</mi>", name)),
+            Expr::Let(..) => self.inline_lets().write_mathml(out),
+            Expr::Eof => {}
+        }
+    }
+}
+
+impl Ast {
+    /// Write the expression as presentation MathML inside a `<math>` element, using `<mfrac>` for
+    /// division, `<msup>` for powers and `<msqrt>` for square roots
+    pub fn to_mathml(&self) -> String {
+        let mut out = String::from(r#"<math xmlns="http://www.w3.org/1998/Math/MathML">"#);
+        self.expr.write_mathml(&mut out);
+        out.push_str("</math>");
+        out
+    }
+
+    /// Parse presentation MathML built from `<mrow>`, `<mn>`, `<mi>`, `<mo>`, `<mfrac>`, `<msup>`
+    /// and `<msqrt>`, the inverse of `to_mathml`
+    /// Operands placed next to each other, as in `<mn>2</mn><mi>x</mi>`, are multiplied
+    pub fn from_mathml(xml: &str) -> Result<Ast, ParseError> {
+        let mut infix = String::new();
+        for node in read_nodes(xml)? {
+            translate(&node, &mut infix)?;
+        }
+        let functions = HashMap::new();
+        let options = ParseOptions { functions: &functions, implicit_multiply: true };
+        let expr = parse_expr_with_options(&mut TokenStream::new(&infix), &options)?;
+        Ok(Ast::new(expr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mathml_round_trip() {
+        for s in [
+            "x + y * z", "(x + y) * z", "a - (b - c)", "a - b - c", "a / b / c", "a / (b / c)",
+            "2^3^2", "(2^3)^2", "-x^2", "-(x + 1)", "x! + 5%", "(x + 1)!", "x^(1 / 2)",
+            "sqrt(x^2 + 1) / (2 * pi)", "logb(2, x) - ln(y)", "sin(x) * cos(y) + tan(z) / exp(w)",
+            "2 * i + 1", "inf - x", ""
+        ] {
+            let ast = Ast::string_to_ast(s);
+            let mathml = ast.to_mathml();
+            let parsed = Ast::from_mathml(&mathml).unwrap();
+            assert_eq!(parsed, ast, "{}", mathml);
+            assert_eq!(parsed.to_mathml(), mathml);
+        }
+    }
+
+    #[test]
+    fn write_mathml() {
+        assert_eq!(
+            Ast::string_to_ast("x^2 + 1").to_mathml(),
+            r#"<math xmlns="http://www.w3.org/1998/Math/MathML"><mrow><msup><mi>x</mi><mn>2</mn></msup><mo>+</mo><mn>1</mn></mrow></math>"#
+        );
+        assert_eq!(
+            Ast::string_to_ast("-(a * b) / 2").to_mathml(),
+            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\"><mfrac><mrow><mo>-</mo><mrow><mo>(</mo>\
+             <mrow><mi>a</mi><mo>\u{d7}</mo><mi>b</mi></mrow><mo>)</mo></mrow></mrow><mn>2</mn></mfrac></math>"
+        );
+    }
+
+    #[test]
+    fn read_handwritten_mathml() {
+        let mathml = |s| Ast::from_mathml(s).unwrap();
+        assert_eq!(
+            mathml("<math><mrow><mn>2</mn><mo>&#x2062;</mo><mi>x</mi><mo>&#x2212;</mo><mfrac><mn>1</mn><mn>2</mn></mfrac></mrow></math>"),
+            Ast::string_to_ast("2 * x - 1 / 2")
+        );
+        assert_eq!(mathml("<mrow><mn>2</mn><mi>π</mi><mi>r</mi></mrow>"), Ast::string_to_ast("2 * pi * r"));
+        assert_eq!(
+            mathml("<math>\n  <msup>\n    <mrow><mi>sin</mi><mo>&#x2061;</mo><mo>(</mo><mi>x</mi><mo>)</mo></mrow>\n    <mn>2</mn>\n  </msup>\n</math>"),
+            Ast::string_to_ast("sin(x)^2")
+        );
+    }
+
+    #[test]
+    fn mathml_errors() {
+        let error = |s| match Ast::from_mathml(s) {
+            Err(ParseError::InvalidMathml(e)) => e,
+            other => panic!("Expected invalid MathML but found {:?}", other)
+        };
+        assert_eq!(error("<math><mtable/></math>"), "unsupported element <mtable>");
+        assert_eq!(error("<mfrac><mn>1</mn></mfrac>"), "<mfrac> needs exactly two children");
+        assert_eq!(error("<mrow><mn>1</mn><mo>=</mo><mn>1</mn></mrow>"), "unsupported operator =");
+        assert_eq!(error("<mi>x+y</mi>"), "unsupported identifier x+y");
+        assert_eq!(error("<mn>1e5</mn>"), "1e5 is not a number");
+        assert_eq!(error("<mn><mi>x</mi></mn>"), "<mi> inside <mn>");
+        assert_eq!(error("<mrow><mn>1</mn>"), "unclosed element");
+        assert_eq!(error("<math>1 + 2</math>"), "unexpected text 1 + 2");
+        assert!(matches!(Ast::from_mathml("<mrow></math>"), Err(ParseError::InvalidMathml(_))));
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use crate::ast::Ast;
+use crate::error::EvalError;
+use crate::numeric::check_point_arity;
+use crate::parse::Expr;
+
+// Nodes are numbered like a binary heap: the root is 1 and the children of node k are 2k and 2k + 1
+// Numbers run out below depth 63, where subtrees are simply evaluated without the cache
+fn child_id(id: Option<u64>, right: bool) -> Option<u64> {
+    id?.checked_mul(2)?.checked_add(right as u64)
+}
+
+/// An expression of one variable with every constant subtree evaluated ahead of time,
+/// for evaluating many times at different points
+#[derive(Debug, Clone)]
+pub struct MemoizedAst {
+    ast: Ast,
+    constant_cache: HashMap<u64, f64>,
+    // Number of distinct variables, checked on every evaluation
+    arity: usize
+}
+
+impl MemoizedAst {
+    /// Evaluate with the free variable, if any, set to x
+    pub fn eval_at(&self, x: f64) -> Result<f64, EvalError> {
+        check_point_arity(self.arity)?;
+        self.eval_node(&self.ast.expr, Some(1), x)
+    }
+
+    /// Number of constant subtrees whose values were precomputed
+    pub fn cached_subtrees(&self) -> usize {
+        self.constant_cache.len()
+    }
+
+    fn eval_node(&self, expr: &Expr, id: Option<u64>, x: f64) -> Result<f64, EvalError> {
+        if let Some(val) = id.and_then(|id| self.constant_cache.get(&id)) {
+            return Ok(*val)
+        }
+        match expr {
+            Expr::BinaryOp(op, e1, e2) => op.apply(self.eval_node(e1, child_id(id, false), x)?, self.eval_node(e2, child_id(id, true), x)?),
+            Expr::UnaryOp(op, e) => op.apply(self.eval_node(e, child_id(id, false), x)?),
+            Expr::Variable(_) => Ok(x),
+            e => e.eval()
+        }
+    }
+}
+
+impl Expr {
+    // Cache the values of the largest constant subtrees below this node, returning whether the
+    // node itself is constant so that its parent can decide whether to cache it instead
+    fn cache_constants(&self, id: Option<u64>, cache: &mut HashMap<u64, f64>) -> bool {
+        let children: Vec<(&Expr, Option<u64>)> = match self {
+            Expr::BinaryOp(_, e1, e2) => vec![(e1, child_id(id, false)), (e2, child_id(id, true))],
+            Expr::UnaryOp(_, e) => vec![(e, child_id(id, false))],
+            Expr::Variable(_) => return false,
+            Expr::Let(..) => unreachable!("Bindings are inlined before caching"),
+            Expr::Number(_) | Expr::Complex(_, _) | Expr::Eof => return true
+        };
+        let constant: Vec<bool> = children.iter().map(|(child, child_id)| child.cache_constants(*child_id, cache)).collect();
+        if constant.iter().all(|c| *c) {
+            return true
+        }
+        for ((child, child_id), constant) in children.into_iter().zip(constant) {
+            if constant {
+                child.cache_value(child_id, cache);
+            }
+        }
+        false
+    }
+
+    // Leaves are as quick to read as the cache, and subtrees that fail to evaluate are left to fail again
+    fn cache_value(&self, id: Option<u64>, cache: &mut HashMap<u64, f64>) {
+        if let (Some(id), Expr::BinaryOp(..) | Expr::UnaryOp(..)) = (id, self) {
+            if let Ok(val) = self.eval() {
+                cache.insert(id, val);
+            }
+        }
+    }
+}
+
+impl Ast {
+    /// Evaluate every subtree that does not depend on the variable once, so that repeated
+    /// evaluation with `MemoizedAst::eval_at` only computes the parts that change
+    pub fn memoize_eval(&self) -> MemoizedAst {
+        let ast = Ast::new(self.expr.inline_lets());
+        let mut constant_cache = HashMap::new();
+        if ast.expr.cache_constants(Some(1), &mut constant_cache) {
+            ast.expr.cache_value(Some(1), &mut constant_cache);
+        }
+        let arity = ast.expr.free_variables().len();
+        MemoizedAst { ast, constant_cache, arity }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memoized_matches_direct_evaluation() {
+        for (s, cached) in [
+            ("sin(2) * x + (3 + 4)", 2),
+            ("exp(ln(5) / 2)^x - 3!", 2),
+            ("x^2 - 2 * x + 1", 0),
+            ("sqrt(16) + 1", 1),
+            ("-t + logb(2, 8)", 1),
+        ] {
+            let ast = Ast::string_to_ast(s);
+            let memoized = ast.memoize_eval();
+            assert_eq!(memoized.cached_subtrees(), cached, "{}", s);
+            for x in [-1.5, 0.0, 0.25, 3.0] {
+                assert_eq!(memoized.eval_at(x), ast.eval_point(x), "{} at {}", s, x);
+            }
+        }
+    }
+
+    #[test]
+    fn memoized_deep_trees_and_errors() {
+        // Deeper than the node numbering reaches, so the innermost constants are not cached
+        let deep = Ast::string_to_ast(&format!("{}(1 + 2){}", "(x + ".repeat(70), ")".repeat(70)));
+        let memoized = deep.memoize_eval();
+        assert_eq!(memoized.cached_subtrees(), 0);
+        assert_eq!(memoized.eval_at(1.0), Ok(73.0));
+
+        assert_eq!(Ast::string_to_ast("x * y").memoize_eval().eval_at(1.0), Err(EvalError::WrongArity { expected: 1, found: 2 }));
+        assert_eq!(Ast::string_to_ast("x + (0 - 1)!").memoize_eval().eval_at(1.0), Err(EvalError::FactorialNegative(-1)));
+        assert_eq!(Ast::string_to_ast("").memoize_eval().eval_at(1.0), Err(EvalError::EmptyExpression));
+    }
+}
@@ -0,0 +1,487 @@
+use std::collections::HashMap;
+use crate::ast::Ast;
+use crate::error::EvalError;
+use crate::parse::{BinOp, Expr, UnOp};
+
+// Next number from a splitmix64 generator, uniform on [0, 1)
+fn next_uniform(state: &mut u64) -> f64 {
+    *state = state.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^= z >> 31;
+    (z >> 11) as f64 / (1u64 << 53) as f64
+}
+
+// Pearson correlation of paired samples, treating a constant side as perfectly correlated
+// only when both sides agree everywhere
+fn pearson(pairs: &[(f64, f64)]) -> f64 {
+    if pairs.iter().all(|p| p.0 == pairs[0].0) || pairs.iter().all(|p| p.1 == pairs[0].1) {
+        return if pairs.iter().all(|(a, b)| a == b) { 1.0 } else { 0.0 }
+    }
+    let n = pairs.len() as f64;
+    let (sum_a, sum_b) = pairs.iter().fold((0.0, 0.0), |(sa, sb), (a, b)| (sa + a, sb + b));
+    let (mean_a, mean_b) = (sum_a / n, sum_b / n);
+    let (mut cov, mut var_a, mut var_b) = (0.0, 0.0, 0.0);
+    for (a, b) in pairs {
+        cov += (a - mean_a) * (b - mean_b);
+        var_a += (a - mean_a).powi(2);
+        var_b += (b - mean_b).powi(2);
+    }
+    cov / (var_a * var_b).sqrt()
+}
+
+// Functions evaluated at a point may have at most one free variable
+pub(crate) fn check_point_arity(arity: usize) -> Result<(), EvalError> {
+    if arity > 1 {
+        return Err(EvalError::WrongArity { expected: 1, found: arity })
+    }
+    Ok(())
+}
+
+impl Ast {
+    // Bind the free variable of a function of at most one variable to x
+    fn point_bindings(&self, x: f64) -> Result<HashMap<String, f64>, EvalError> {
+        let vars = self.expr.free_variables();
+        check_point_arity(vars.len())?;
+        Ok(vars.into_iter().map(|v| (v, x)).collect())
+    }
+
+    // Evaluate a function of at most one free variable at the point x
+    pub(crate) fn eval_point(&self, x: f64) -> Result<f64, EvalError> {
+        self.expr.eval_with(&self.point_bindings(x)?)
+    }
+
+    // Estimate the derivative at x with a central difference
+    pub(crate) fn derivative_at(&self, x: f64) -> Result<f64, EvalError> {
+        let h = 1e-6 * x.abs().max(1.0);
+        Ok((self.eval_point(x + h)? - self.eval_point(x - h)?) / (2.0 * h))
+    }
+
+    // Sample the derivative at the midpoints of n equal subintervals of [a, b]
+    fn sample_derivatives(&self, a: f64, b: f64, n: usize) -> Result<Vec<f64>, EvalError> {
+        let step = (b - a) / n as f64;
+        (0..n).map(|i| self.derivative_at(a + (i as f64 + 0.5) * step)).collect()
+    }
+
+    /// Check whether the function is strictly increasing on [a, b] by sampling its derivative at `n` points
+    pub fn is_increasing_on(&self, a: f64, b: f64, n: usize) -> Result<bool, EvalError> {
+        Ok(self.sample_derivatives(a, b, n)?.iter().all(|d| *d > 0.0))
+    }
+
+    /// Check whether the function is strictly decreasing on [a, b] by sampling its derivative at `n` points
+    pub fn is_decreasing_on(&self, a: f64, b: f64, n: usize) -> Result<bool, EvalError> {
+        Ok(self.sample_derivatives(a, b, n)?.iter().all(|d| *d < 0.0))
+    }
+
+    /// Check whether the function is strictly increasing or strictly decreasing on [a, b]
+    /// The derivative is sampled at `n` points, all of which must share the same sign
+    pub fn is_monotone_on(&self, a: f64, b: f64, n: usize) -> Result<bool, EvalError> {
+        let derivs = self.sample_derivatives(a, b, n)?;
+        Ok(derivs.iter().all(|d| *d > 0.0) || derivs.iter().all(|d| *d < 0.0))
+    }
+
+    // Largest difference between f(x) and f(x + t) over the sample points
+    fn periodic_deviation(&self, xs: &[f64], t: f64) -> Option<f64> {
+        xs.iter().map(|x| Some((self.eval_point(*x).ok()? - self.eval_point(x + t).ok()?).abs()))
+            .try_fold(0f64, |acc, d| Some(acc.max(d?)))
+    }
+
+    /// Search for the smallest period T of a single variable function such that f(x) ≈ f(x + T)
+    /// Candidates are tried in increments of `step` up to `max_period`, comparing within `tol` at points
+    /// spread over [a, a + max_period]. Shifts so small that f barely moves are skipped, and the best
+    /// candidate of the first run that matches is returned
+    pub fn period(&self, a: f64, step: f64, max_period: f64, tol: f64) -> Option<f64> {
+        let xs: Vec<f64> = (0..16).map(|k| a + k as f64 * max_period / 16.0).collect();
+        let mut departed = false;
+        let mut best: Option<(f64, f64)> = None;
+
+        let mut t = step;
+        while t <= max_period {
+            let dev = self.periodic_deviation(&xs, t).unwrap_or(f64::INFINITY);
+            if dev > tol {
+                if best.is_some() {
+                    break
+                }
+                departed = true;
+            } else if departed && best.is_none_or(|(_, best_dev)| dev < best_dev) {
+                best = Some((t, dev));
+            }
+            t += step;
+        }
+
+        best.map(|(t, _)| t)
+    }
+
+    /// Evaluate a function of at most one free variable at x along with a bound on the
+    /// accumulated floating point error, returned as (value, error_bound)
+    /// Literals and x are taken as exact, and each operation contributes `f64::EPSILON * |result|`
+    /// on top of the error it propagates from its inputs
+    pub fn eval_with_error_bound(&self, x: f64) -> Result<(f64, f64), EvalError> {
+        self.expr.eval_with_error(&self.point_bindings(x)?)
+    }
+
+    /// Compute the discrete linear convolution of this function with `other`
+    /// Both are sampled at `n` evenly spaced points on [a, b) and the 2n - 1 outputs
+    /// are returned as (x, convolved_val) pairs, scaled by the step so that they
+    /// approximate the continuous convolution integral
+    pub fn convolve(&self, other: &Ast, a: f64, b: f64, n: usize) -> Result<Vec<(f64, f64)>, EvalError> {
+        if n == 0 {
+            return Ok(Vec::new())
+        }
+
+        let step = (b - a) / n as f64;
+        let f = (0..n).map(|i| self.eval_point(a + i as f64 * step)).collect::<Result<Vec<f64>, _>>()?;
+        let g = (0..n).map(|i| other.eval_point(a + i as f64 * step)).collect::<Result<Vec<f64>, _>>()?;
+
+        let mut out = vec![0f64; 2 * n - 1];
+        for (i, fi) in f.iter().enumerate() {
+            for (j, gj) in g.iter().enumerate() {
+                out[i + j] += fi * gj * step;
+            }
+        }
+
+        Ok(out.into_iter().enumerate().map(|(k, val)| (2.0 * a + k as f64 * step, val)).collect())
+    }
+
+    /// Evaluate this function and `other` at each of the points, pairing up their values
+    /// Each may have at most one free variable, which is bound to the point
+    pub fn zip_eval(&self, other: &Ast, points: &[f64]) -> Result<Vec<(f64, f64)>, EvalError> {
+        points.iter().map(|x| Ok((self.eval_point(*x)?, other.eval_point(*x)?))).collect()
+    }
+
+    /// Lazily evaluate the expression with `var` set to lo, lo + step, lo + 2 * step and so on
+    /// up to hi, which is included when it lies on the grid up to rounding
+    /// Nothing is produced unless step is positive and finite
+    pub fn generate_sequence(&self, var: &str, lo: f64, hi: f64, step: f64) -> impl Iterator<Item = Result<f64, EvalError>> + '_ {
+        let count = if step > 0.0 && step.is_finite() && hi >= lo {
+            ((hi - lo) / step + 1e-9).floor() as usize + 1
+        } else {
+            0
+        };
+        let mut vars = HashMap::from([(var.to_owned(), lo)]);
+        let var = var.to_owned();
+        (0..count).map(move |k| {
+            vars.insert(var.clone(), lo + k as f64 * step);
+            self.expr.eval_with(&vars)
+        })
+    }
+
+    /// Estimate the integral of a function of at most one variable over [a, b] by averaging it at
+    /// `n` points drawn uniformly from the interval and scaling by b - a
+    /// The same seed always gives the same points, and the error shrinks like 1 / sqrt(n)
+    pub fn monte_carlo_integrate(&self, a: f64, b: f64, n: usize, seed: u64) -> Result<f64, EvalError> {
+        if n == 0 {
+            return Ok(0.0)
+        }
+        let mut state = seed;
+        let mut total = 0.0;
+        for _ in 0..n {
+            total += self.eval_point(a + (b - a) * next_uniform(&mut state))?;
+        }
+        Ok(total / n as f64 * (b - a))
+    }
+
+    /// Evaluate a function of at most one variable at `n` points drawn uniformly from `range`
+    /// The same seed always gives the same points, so expressions that agree on the samples
+    /// for a seed are likely equivalent
+    pub fn random_sample_eval(&self, n: usize, range: std::ops::Range<f64>, seed: u64) -> Result<Vec<f64>, EvalError> {
+        let mut state = seed;
+        (0..n)
+            .map(|_| self.eval_point(range.start + (range.end - range.start) * next_uniform(&mut state)))
+            .collect()
+    }
+
+    /// Compute the Fourier coefficients (a_k, b_k) for k = 0 to n of a function of at most one
+    /// variable with the given period, so that f(x) is approximately the sum of
+    /// a_k * cos(2 pi k x / period) + b_k * sin(2 pi k x / period), with a_0 the mean and b_0 = 0
+    /// The integrals over one period are taken with the rectangle rule at `num_samples` points,
+    /// which is very accurate for smooth periodic functions
+    /// Zero samples or a period that is not positive and finite give `EvalError::InvalidArgument`
+    pub fn fourier_coefficients(&self, n: usize, period: f64, num_samples: usize) -> Result<Vec<(f64, f64)>, EvalError> {
+        if num_samples == 0 {
+            return Err(EvalError::InvalidArgument("Fourier coefficients need at least one sample".to_owned()))
+        }
+        if !period.is_finite() || period <= 0.0 {
+            return Err(EvalError::InvalidArgument(format!("Fourier coefficients need a positive period, not {}", period)))
+        }
+        let step = period / num_samples as f64;
+        let samples = (0..num_samples)
+            .map(|i| {
+                let x = i as f64 * step;
+                Ok((x, self.eval_point(x)?))
+            })
+            .collect::<Result<Vec<(f64, f64)>, EvalError>>()?;
+
+        Ok((0..=n).map(|k| {
+            let omega = 2.0 * std::f64::consts::PI * k as f64 / period;
+            let (a, b) = samples.iter().fold((0.0, 0.0), |(a, b), (x, fx)| {
+                (a + fx * (omega * x).cos(), b + fx * (omega * x).sin())
+            });
+            // The constant term is the mean rather than twice it
+            let scale = if k == 0 { 1.0 } else { 2.0 } / num_samples as f64;
+            (a * scale, b * scale)
+        }).collect())
+    }
+
+    /// Score how alike this expression and `other` are as the Pearson correlation of their values
+    /// at `n` random points, where each variable of either is drawn uniformly from [0, 10]
+    /// The same seed always gives the same points. A score near 1 suggests the two are equivalent
+    /// up to scaling and shifting, and near 0 that they are unrelated. Points where either side
+    /// fails to evaluate or is not finite are skipped, and 0 is returned if fewer than two remain
+    pub fn comparable_to(&self, other: &Ast, n: usize, seed: u64) -> f64 {
+        let mut vars = self.variables_ordered();
+        for var in other.variables_ordered() {
+            if !vars.contains(&var) {
+                vars.push(var);
+            }
+        }
+
+        let mut state = seed;
+        let pairs: Vec<(f64, f64)> = (0..n)
+            .filter_map(|_| {
+                let point: HashMap<String, f64> = vars.iter().map(|v| (v.clone(), 10.0 * next_uniform(&mut state))).collect();
+                let (a, b) = (self.expr.eval_with(&point).ok()?, other.expr.eval_with(&point).ok()?);
+                (a.is_finite() && b.is_finite()).then_some((a, b))
+            })
+            .collect();
+        if pairs.len() < 2 {
+            return 0.0
+        }
+        pearson(&pairs)
+    }
+}
+
+// The error passed on through a derivative of the given slope
+// Exact inputs stay exact even where the slope is infinite, as for sqrt at 0
+fn scaled_error(slope: f64, err: f64) -> f64 {
+    if err == 0.0 {
+        0.0
+    } else {
+        slope.abs() * err
+    }
+}
+
+impl Expr {
+    fn eval_with_error(&self, vars: &HashMap<String, f64>) -> Result<(f64, f64), EvalError> {
+        let (val, propagated) = match self {
+            Expr::BinaryOp(op, e1, e2) => {
+                let ((v1, err1), (v2, err2)) = (e1.eval_with_error(vars)?, e2.eval_with_error(vars)?);
+                let val = op.apply(v1, v2)?;
+                let propagated = match op {
+                    BinOp::Plus | BinOp::Minus => err1 + err2,
+                    BinOp::Multiply => scaled_error(v2, err1) + scaled_error(v1, err2),
+                    BinOp::Divide => scaled_error(1.0 / v2, err1) + scaled_error(val / v2, err2),
+                    BinOp::Power => scaled_error(v2 * v1.powf(v2 - 1.0), err1) + scaled_error(val * v1.abs().ln(), err2),
+                    BinOp::LogBase => scaled_error(val / (v1 * v1.ln()), err1) + scaled_error(1.0 / (v2 * v1.ln()), err2)
+                };
+                (val, propagated)
+            },
+            Expr::UnaryOp(op, e) => {
+                let (v, err) = e.eval_with_error(vars)?;
+                let val = op.apply(v)?;
+                // Scale the input error by the size of the derivative
+                let slope = match op {
+                    UnOp::Negative => return Ok((val, err)),
+                    UnOp::Sin => v.cos(),
+                    UnOp::Cos => v.sin(),
+                    UnOp::Tan => 1.0 / v.cos().powi(2),
+                    UnOp::Exp => val,
+                    UnOp::Log => 1.0 / v,
+                    UnOp::Sqrt => 1.0 / (2.0 * val),
+                    UnOp::Asinh => 1.0 / (v * v + 1.0).sqrt(),
+                    UnOp::Acosh => 1.0 / (v * v - 1.0).sqrt(),
+                    UnOp::Atanh => 1.0 / (1.0 - v * v),
+                    // Inputs to factorial are exact integers
+                    UnOp::Factorial => 0.0,
+                    UnOp::Percent => 0.01
+                };
+                (val, scaled_error(slope, err))
+            },
+            e => return Ok((e.eval_with(vars)?, 0.0))
+        };
+
+        Ok((val, propagated + f64::EPSILON * val.abs()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convolve_constant_integrates() {
+        let one = Ast::string_to_ast("1");
+        let sin = Ast::string_to_ast("sin(x)");
+        let result = one.convolve(&sin, 0.0, std::f64::consts::PI, 1000).unwrap();
+
+        assert_eq!(result.len(), 1999);
+        // First half is a running integral of sin from 0, i.e. 1 - cos(x)
+        for (x, val) in result.iter().take(1000).step_by(100) {
+            assert!((val - (1.0 - x.cos())).abs() < 1e-2);
+        }
+    }
+
+    #[test]
+    fn convolve_too_many_variables() {
+        let one = Ast::string_to_ast("1");
+        let xy = Ast::string_to_ast("x + y");
+        assert_eq!(one.convolve(&xy, 0.0, 1.0, 10), Err(EvalError::WrongArity { expected: 1, found: 2 }));
+    }
+
+    #[test]
+    fn zip_eval_sin_cos() {
+        let sin = Ast::string_to_ast("sin(x)");
+        let cos = Ast::string_to_ast("cos(t)");
+        let pi = std::f64::consts::PI;
+        let pairs = sin.zip_eval(&cos, &[0.0, pi / 2.0, pi]).unwrap();
+        let expected = [(0.0, 1.0), (1.0, 0.0), (0.0, -1.0)];
+        assert_eq!(pairs.len(), expected.len());
+        for ((s, c), (es, ec)) in pairs.iter().zip(expected) {
+            assert!((s - es).abs() < 1e-12 && (c - ec).abs() < 1e-12);
+        }
+        assert_eq!(sin.zip_eval(&Ast::string_to_ast("x * y"), &[1.0]), Err(EvalError::WrongArity { expected: 1, found: 2 }));
+        assert_eq!(sin.zip_eval(&cos, &[]), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn generate_sequences() {
+        // i is the imaginary unit, so use k as the index
+        let squares: Result<Vec<f64>, _> = Ast::string_to_ast("k^2").generate_sequence("k", 0.0, 1.0, 0.25).collect();
+        assert_eq!(squares, Ok(vec![0.0, 0.0625, 0.25, 0.5625, 1.0]));
+        // hi is reached despite rounding in 1 / 0.1, and steps that miss it stop before it
+        assert_eq!(Ast::string_to_ast("x").generate_sequence("x", 0.0, 1.0, 0.1).count(), 11);
+        assert_eq!(Ast::string_to_ast("x").generate_sequence("x", 0.0, 1.0, 0.3).count(), 4);
+        assert_eq!(Ast::string_to_ast("x").generate_sequence("x", 1.0, 0.0, 0.5).count(), 0);
+        assert_eq!(Ast::string_to_ast("x").generate_sequence("x", 0.0, 1.0, 0.0).count(), 0);
+
+        // The iterator is lazy, so a long sequence can be consumed a piece at a time
+        let double = Ast::string_to_ast("2 * n");
+        let mut evens = double.generate_sequence("n", 0.0, 1e15, 1.0).skip(5);
+        assert_eq!(evens.next(), Some(Ok(10.0)));
+
+        let sum = Ast::string_to_ast("n + m");
+        assert_eq!(sum.generate_sequence("n", 0.0, 2.0, 1.0).next(), Some(Err(EvalError::UnboundVariable("m".to_owned()))));
+    }
+
+    #[test]
+    fn monte_carlo_integrals() {
+        let square = Ast::string_to_ast("x^2");
+        let estimate = square.monte_carlo_integrate(0.0, 1.0, 100_000, 42).unwrap();
+        assert!((estimate - 1.0 / 3.0).abs() < 0.01 / 3.0);
+        assert_eq!(square.monte_carlo_integrate(0.0, 1.0, 100, 1), square.monte_carlo_integrate(0.0, 1.0, 100, 1));
+
+        let estimate = Ast::string_to_ast("sin(t)").monte_carlo_integrate(0.0, std::f64::consts::PI, 100_000, 7).unwrap();
+        assert!((estimate - 2.0).abs() < 0.02);
+        assert_eq!(Ast::string_to_ast("3").monte_carlo_integrate(1.0, 5.0, 10, 0), Ok(12.0));
+        assert_eq!(square.monte_carlo_integrate(0.0, 1.0, 0, 0), Ok(0.0));
+        assert_eq!(Ast::string_to_ast("x * y").monte_carlo_integrate(0.0, 1.0, 10, 0), Err(EvalError::WrongArity { expected: 1, found: 2 }));
+    }
+
+    #[test]
+    fn random_samples() {
+        let zero = Ast::string_to_ast("x^2 - x^2").random_sample_eval(50, -10.0..10.0, 7).unwrap();
+        assert_eq!(zero, vec![0.0; 50]);
+
+        let samples = Ast::string_to_ast("x").random_sample_eval(100, 2.0..3.0, 1).unwrap();
+        assert!(samples.iter().all(|x| (2.0..3.0).contains(x)));
+        assert_eq!(Ast::string_to_ast("t").random_sample_eval(100, 2.0..3.0, 1), Ok(samples.clone()));
+        assert_ne!(Ast::string_to_ast("x").random_sample_eval(100, 2.0..3.0, 2), Ok(samples));
+
+        // Equivalent forms agree point for point, up to rounding
+        let a = Ast::string_to_ast("(x + 1)^2").random_sample_eval(20, 0.0..5.0, 3).unwrap();
+        let b = Ast::string_to_ast("x^2 + 2 * x + 1").random_sample_eval(20, 0.0..5.0, 3).unwrap();
+        assert!(a.iter().zip(&b).all(|(a, b)| (a - b).abs() < 1e-9));
+
+        assert_eq!(Ast::string_to_ast("x * y").random_sample_eval(3, 0.0..1.0, 0), Err(EvalError::WrongArity { expected: 1, found: 2 }));
+        assert_eq!(Ast::string_to_ast("x").random_sample_eval(0, 0.0..1.0, 0), Ok(vec![]));
+    }
+
+    #[test]
+    fn fourier_sine_and_cosine_terms() {
+        let tau = 2.0 * std::f64::consts::PI;
+        let coeffs = Ast::string_to_ast("sin(x)").fourier_coefficients(5, tau, 256).unwrap();
+        assert_eq!(coeffs.len(), 6);
+        for (k, (a, b)) in coeffs.iter().enumerate() {
+            let expected_b = if k == 1 { 1.0 } else { 0.0 };
+            assert!(a.abs() < 1e-12 && (b - expected_b).abs() < 1e-12, "k = {}", k);
+        }
+
+        let coeffs = Ast::string_to_ast("3 + 2 * cos(2 * t) - sin(3 * t)").fourier_coefficients(3, tau, 64).unwrap();
+        let expected = [(3.0, 0.0), (0.0, 0.0), (2.0, 0.0), (0.0, -1.0)];
+        for ((a, b), (ea, eb)) in coeffs.iter().zip(expected) {
+            assert!((a - ea).abs() < 1e-12 && (b - eb).abs() < 1e-12);
+        }
+        assert_eq!(Ast::string_to_ast("x * y").fourier_coefficients(1, tau, 8), Err(EvalError::WrongArity { expected: 1, found: 2 }));
+        let sin = Ast::string_to_ast("sin(x)");
+        assert_eq!(sin.fourier_coefficients(1, tau, 0),
+                   Err(EvalError::InvalidArgument("Fourier coefficients need at least one sample".to_owned())));
+        for period in [0.0, -tau, f64::INFINITY, f64::NAN] {
+            assert_eq!(sin.fourier_coefficients(1, period, 8),
+                       Err(EvalError::InvalidArgument(format!("Fourier coefficients need a positive period, not {}", period))));
+        }
+    }
+
+    #[test]
+    fn comparable_scores() {
+        let square = Ast::string_to_ast("x^2");
+        assert!(square.comparable_to(&Ast::string_to_ast("x^2 + 1e-10"), 100, 7) > 1.0 - 1e-12);
+        assert!(square.comparable_to(&Ast::string_to_ast("3 * x * x - 2"), 100, 7) > 1.0 - 1e-12);
+        assert!(square.comparable_to(&Ast::string_to_ast("5 - x^2"), 100, 7) < -1.0 + 1e-12);
+        assert!(square.comparable_to(&Ast::string_to_ast("sin(40 * y)"), 500, 7).abs() < 0.2);
+        assert_eq!(square.comparable_to(&Ast::string_to_ast("y"), 50, 3), square.comparable_to(&Ast::string_to_ast("y"), 50, 3));
+        assert_eq!(Ast::string_to_ast("2").comparable_to(&Ast::string_to_ast("1 + 1"), 10, 0), 1.0);
+        assert_eq!(Ast::string_to_ast("2").comparable_to(&square, 10, 0), 0.0);
+        assert_eq!(Ast::string_to_ast("ln(-x - 1)").comparable_to(&square, 10, 0), 0.0);
+    }
+
+    #[test]
+    fn monotone_exp_and_cos() {
+        let exp = Ast::string_to_ast("exp(x)");
+        assert_eq!(exp.is_increasing_on(-50.0, 50.0, 100), Ok(true));
+        assert_eq!(exp.is_monotone_on(-50.0, 50.0, 100), Ok(true));
+
+        let cos = Ast::string_to_ast("cos(x)");
+        assert_eq!(cos.is_decreasing_on(0.0, std::f64::consts::PI, 100), Ok(true));
+        assert_eq!(cos.is_increasing_on(0.0, std::f64::consts::PI, 100), Ok(false));
+        assert_eq!(cos.is_monotone_on(0.0, 5.0, 100), Ok(false));
+    }
+
+    #[test]
+    fn period_detection() {
+        let tau = std::f64::consts::TAU;
+        let sin = Ast::string_to_ast("sin(x)").period(0.0, 1e-3, 10.0, 1e-2).unwrap();
+        assert!((sin - tau).abs() < 1e-2);
+        let cos = Ast::string_to_ast("cos(2*x)").period(0.0, 1e-3, 10.0, 1e-2).unwrap();
+        assert!((cos - tau / 2.0).abs() < 1e-2);
+        assert_eq!(Ast::string_to_ast("x^2").period(0.0, 1e-3, 10.0, 1e-2), None);
+    }
+
+    #[test]
+    fn error_bound_small_sum() {
+        let (val, err) = Ast::string_to_ast("1 + 1e-16").eval_with_error_bound(0.0).unwrap();
+        assert_eq!(val, 1.0);
+        assert!(err > 0.0 && err < 1e-15);
+        assert_eq!(Ast::string_to_ast("x").eval_with_error_bound(2.0), Ok((2.0, 0.0)));
+    }
+
+    #[test]
+    fn error_bound_grows_with_depth() {
+        let (_, shallow) = Ast::string_to_ast("sin(x)").eval_with_error_bound(1.0).unwrap();
+        let (_, deep) = Ast::string_to_ast("sin(exp(sin(x) * x) + x / 3) * 5").eval_with_error_bound(1.0).unwrap();
+        assert!(deep > shallow);
+    }
+
+    #[test]
+    fn error_bound_at_domain_edges() {
+        let bound = |s| Ast::string_to_ast(s).eval_with_error_bound(0.0).unwrap();
+        assert_eq!(bound("x^2"), (0.0, 0.0));
+        assert_eq!(bound("x^3 + 1"), (1.0, f64::EPSILON));
+        assert_eq!(bound("sqrt(x)"), (0.0, 0.0));
+        assert_eq!(Ast::string_to_ast("acosh(x)").eval_with_error_bound(1.0), Ok((0.0, 0.0)));
+        // An inexact input still has its error scaled by the slope
+        let (val, err) = bound("(x + 0.1)^2");
+        assert!((val - 0.01).abs() < 1e-15);
+        assert!(err > 0.0 && err < 1e-15);
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+use crate::ast::Ast;
+use crate::error::EvalError;
+
+impl Ast {
+    // Evaluate the right hand side f(x, y) of dy/dx = f(x, y)
+    fn slope(&self, x: f64, y: f64) -> Result<f64, EvalError> {
+        let vars = HashMap::from([("x".to_owned(), x), ("y".to_owned(), y)]);
+        self.expr.eval_with(&vars)
+    }
+
+    /// Take one step of Euler's method for dy/dx = f(x, y), where the expression is f and may
+    /// use the variables x and y, returning y0 + h * f(x0, y0)
+    pub fn euler_step(&self, x0: f64, y0: f64, h: f64) -> Result<f64, EvalError> {
+        Ok(y0 + h * self.slope(x0, y0)?)
+    }
+
+    /// Integrate dy/dx = f(x, y) from (x0, y0) to x_end with `steps` Euler steps of equal size,
+    /// returning every (x, y) visited including the starting point
+    pub fn euler_solve(&self, x0: f64, y0: f64, x_end: f64, steps: usize) -> Result<Vec<(f64, f64)>, EvalError> {
+        let h = (x_end - x0) / steps as f64;
+        let mut points = vec![(x0, y0)];
+        let mut y = y0;
+        for k in 0..steps {
+            y = self.euler_step(x0 + k as f64 * h, y, h)?;
+            points.push((x0 + (k + 1) as f64 * h, y));
+        }
+        Ok(points)
+    }
+
+    /// Integrate dy/dx = f(x, y) from (x0, y0) to x_end with `steps` steps of the classic
+    /// fourth order Runge-Kutta method, returning every (x, y) visited including the starting point
+    /// Its error shrinks with the fourth power of the step size rather than linearly as for Euler
+    pub fn runge_kutta4(&self, x0: f64, y0: f64, x_end: f64, steps: usize) -> Result<Vec<(f64, f64)>, EvalError> {
+        let h = (x_end - x0) / steps as f64;
+        let mut points = vec![(x0, y0)];
+        let mut y = y0;
+        for k in 0..steps {
+            let x = x0 + k as f64 * h;
+            let k1 = self.slope(x, y)?;
+            let k2 = self.slope(x + h / 2.0, y + h * k1 / 2.0)?;
+            let k3 = self.slope(x + h / 2.0, y + h * k2 / 2.0)?;
+            let k4 = self.slope(x + h, y + h * k3)?;
+            y += h * (k1 + 2.0 * k2 + 2.0 * k3 + k4) / 6.0;
+            points.push((x0 + (k + 1) as f64 * h, y));
+        }
+        Ok(points)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn euler_step() {
+        let f = Ast::string_to_ast("x + y");
+        assert_eq!(f.euler_step(1.0, 2.0, 0.5), Ok(3.5));
+        assert_eq!(Ast::string_to_ast("y * z").euler_step(0.0, 1.0, 0.1), Err(EvalError::UnboundVariable("z".to_owned())));
+    }
+
+    #[test]
+    fn euler_solve_exponential() {
+        let points = Ast::string_to_ast("y").euler_solve(0.0, 1.0, 1.0, 10_000).unwrap();
+        assert_eq!(points.len(), 10_001);
+        assert_eq!(points[0], (0.0, 1.0));
+        let (x, y) = points[10_000];
+        assert!((x - 1.0).abs() < 1e-12);
+        // Euler's method has error proportional to the step size
+        assert!((y - std::f64::consts::E).abs() < 1e-3);
+        for (x, y) in points.iter().step_by(1000) {
+            assert!((y - x.exp()).abs() < 1e-3);
+        }
+        assert_eq!(Ast::string_to_ast("y").euler_solve(0.0, 1.0, 1.0, 0), Ok(vec![(0.0, 1.0)]));
+    }
+
+    #[test]
+    fn runge_kutta4_decay() {
+        let f = Ast::string_to_ast("0 - y");
+        let exact = (-1.0f64).exp();
+        let (_, rk4) = *f.runge_kutta4(0.0, 1.0, 1.0, 10).unwrap().last().unwrap();
+        let (_, euler) = *f.euler_solve(0.0, 1.0, 1.0, 10).unwrap().last().unwrap();
+        assert!((rk4 - 0.3679).abs() < 1e-4);
+        assert!((rk4 - exact).abs() < 1e-5);
+        // Euler needs far more steps to come close
+        assert!((euler - exact).abs() > 1e-2);
+        let (_, euler) = *f.euler_solve(0.0, 1.0, 1.0, 1000).unwrap().last().unwrap();
+        assert!((euler - exact).abs() > (rk4 - exact).abs());
+
+        // Both x and y can appear, here with solution y = x^2 + 1
+        let points = Ast::string_to_ast("2 * x").runge_kutta4(0.0, 1.0, 2.0, 4).unwrap();
+        assert_eq!(points.len(), 5);
+        for (x, y) in points {
+            assert!((y - (x * x + 1.0)).abs() < 1e-12);
+        }
+        assert_eq!(Ast::string_to_ast("t").runge_kutta4(0.0, 1.0, 1.0, 1), Err(EvalError::UnboundVariable("t".to_owned())));
+    }
+}
@@ -0,0 +1,183 @@
+use crate::ast::Ast;
+use crate::parse::{BinOp, Expr, UnOp};
+
+/// The encodings of OpenMath objects that `Ast::to_openmath` can write
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OpenMathFormat {
+    /// The XML encoding, an `<OMOBJ>` element
+    Xml,
+    /// The JSON encoding, nested objects with a `kind` field
+    Json
+}
+
+// An OpenMath object, where every application has a symbol from a content dictionary as its head
+enum OpenMath {
+    Integer(i64),
+    Float(f64),
+    Variable(String),
+    Symbol(&'static str, &'static str),
+    Apply(&'static str, &'static str, Vec<OpenMath>)
+}
+
+impl OpenMath {
+    fn from_expr(expr: &Expr) -> Option<Self> {
+        let om = match expr {
+            Expr::BinaryOp(op, e1, e2) => {
+                let (cd, name) = match op {
+                    BinOp::Plus => ("arith1", "plus"),
+                    BinOp::Minus => ("arith1", "minus"),
+                    BinOp::Multiply => ("arith1", "times"),
+                    BinOp::Divide => ("arith1", "divide"),
+                    BinOp::Power => ("arith1", "power"),
+                    // The base comes first, as it does in logb
+                    BinOp::LogBase => ("transc1", "log")
+                };
+                OpenMath::Apply(cd, name, vec![Self::from_expr(e1)?, Self::from_expr(e2)?])
+            },
+            Expr::UnaryOp(op, e) => {
+                let arg = Self::from_expr(e)?;
+                let (cd, name) = match op {
+                    UnOp::Negative => ("arith1", "unary_minus"),
+                    UnOp::Sin => ("transc1", "sin"),
+                    UnOp::Cos => ("transc1", "cos"),
+                    UnOp::Tan => ("transc1", "tan"),
+                    UnOp::Exp => ("transc1", "exp"),
+                    UnOp::Log => ("transc1", "ln"),
+                    UnOp::Asinh => ("transc1", "arcsinh"),
+                    UnOp::Acosh => ("transc1", "arccosh"),
+                    UnOp::Atanh => ("transc1", "arctanh"),
+                    UnOp::Sqrt => return Some(OpenMath::Apply("arith1", "root", vec![arg, OpenMath::Integer(2)])),
+                    UnOp::Factorial => ("integer1", "factorial"),
+                    UnOp::Percent => return Some(OpenMath::Apply("arith1", "divide", vec![arg, OpenMath::Integer(100)]))
+                };
+                OpenMath::Apply(cd, name, vec![arg])
+            },
+            Expr::Number(n) => Self::number(*n),
+            Expr::Complex(re, im) => OpenMath::Apply("complex1", "complex_cartesian", vec![Self::number(*re), Self::number(*im)]),
+            Expr::Variable(name) => OpenMath::Variable(name.clone()),
+            Expr::Let(..) => return Self::from_expr(&expr.inline_lets()),
+            Expr::Eof => return None
+        };
+        Some(om)
+    }
+
+    // Constants are lexed straight into numbers, so the ones with a symbol are recognised by value
+    fn number(n: f64) -> Self {
+        if n == std::f64::consts::PI {
+            OpenMath::Symbol("nums1", "pi")
+        } else if n == std::f64::consts::E {
+            OpenMath::Symbol("nums1", "e")
+        } else if n == f64::INFINITY {
+            OpenMath::Symbol("nums1", "infinity")
+        } else if n == f64::NEG_INFINITY {
+            OpenMath::Apply("arith1", "unary_minus", vec![OpenMath::Symbol("nums1", "infinity")])
+        } else if n.is_nan() {
+            OpenMath::Symbol("nums1", "NaN")
+        } else if n.fract() == 0.0 && n.abs() < 2f64.powi(53) {
+            OpenMath::Integer(n as i64)
+        } else {
+            OpenMath::Float(n)
+        }
+    }
+
+    fn to_xml(&self) -> String {
+        match self {
+            OpenMath::Integer(n) => format!("<OMI>{}</OMI>", n),
+            OpenMath::Float(n) => format!(r#"<OMF dec="{}"/>"#, n),
+            OpenMath::Variable(name) => format!(r#"<OMV name="{}"/>"#, name),
+            OpenMath::Symbol(cd, name) => format!(r#"<OMS cd="{}" name="{}"/>"#, cd, name),
+            OpenMath::Apply(cd, name, args) => {
+                let args: String = args.iter().map(|arg| arg.to_xml()).collect();
+                format!("<OMA>{}{}</OMA>", OpenMath::Symbol(cd, name).to_xml(), args)
+            }
+        }
+    }
+
+    fn to_json(&self) -> String {
+        match self {
+            OpenMath::Integer(n) => format!(r#"{{"kind":"OMI","integer":{}}}"#, n),
+            OpenMath::Float(n) => format!(r#"{{"kind":"OMF","float":{}}}"#, n),
+            OpenMath::Variable(name) => format!(r#"{{"kind":"OMV","name":"{}"}}"#, name),
+            OpenMath::Symbol(cd, name) => format!(r#"{{"kind":"OMS","cd":"{}","name":"{}"}}"#, cd, name),
+            OpenMath::Apply(cd, name, args) => {
+                let args: Vec<String> = args.iter().map(|arg| arg.to_json()).collect();
+                format!(r#"{{"kind":"OMA","applicant":{},"arguments":[{}]}}"#, OpenMath::Symbol(cd, name).to_json(), args.join(","))
+            }
+        }
+    }
+}
+
+impl Ast {
+    /// Write the expression as an OpenMath object, using symbols from the standard content
+    /// dictionaries such as `arith1.plus`, `transc1.sin` and `nums1.pi`
+    /// Square roots become `arith1.root` with index 2 and percentages a division by 100
+    pub fn to_openmath(&self, format: OpenMathFormat) -> String {
+        let object = OpenMath::from_expr(&self.expr);
+        match format {
+            OpenMathFormat::Xml => {
+                let object = object.map_or(String::new(), |om| om.to_xml());
+                format!(r#"<OMOBJ xmlns="http://www.openmath.org/OpenMath" version="2.0">{}</OMOBJ>"#, object)
+            },
+            OpenMathFormat::Json => {
+                let object = object.map_or(String::new(), |om| format!(r#","object":{}"#, om.to_json()));
+                format!(r#"{{"kind":"OMOBJ","openmath":"2.0"{}}}"#, object)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn xml(s: &str) -> String {
+        let object = Ast::string_to_ast(s).to_openmath(OpenMathFormat::Xml);
+        object.strip_prefix(r#"<OMOBJ xmlns="http://www.openmath.org/OpenMath" version="2.0">"#)
+            .and_then(|object| object.strip_suffix("</OMOBJ>"))
+            .expect("Objects are wrapped in OMOBJ")
+            .to_owned()
+    }
+
+    #[test]
+    fn openmath_xml() {
+        assert_eq!(
+            Ast::string_to_ast("sin(pi / 2)").to_openmath(OpenMathFormat::Xml),
+            concat!(
+                r#"<OMOBJ xmlns="http://www.openmath.org/OpenMath" version="2.0">"#,
+                r#"<OMA><OMS cd="transc1" name="sin"/>"#,
+                r#"<OMA><OMS cd="arith1" name="divide"/><OMS cd="nums1" name="pi"/><OMI>2</OMI></OMA>"#,
+                r#"</OMA></OMOBJ>"#
+            )
+        );
+        assert_eq!(xml("x * 2.5"), r#"<OMA><OMS cd="arith1" name="times"/><OMV name="x"/><OMF dec="2.5"/></OMA>"#);
+        assert_eq!(xml("-e"), r#"<OMA><OMS cd="arith1" name="unary_minus"/><OMS cd="nums1" name="e"/></OMA>"#);
+        assert_eq!(xml("sqrt(y)"), r#"<OMA><OMS cd="arith1" name="root"/><OMV name="y"/><OMI>2</OMI></OMA>"#);
+        assert_eq!(xml("n!"), r#"<OMA><OMS cd="integer1" name="factorial"/><OMV name="n"/></OMA>"#);
+        assert_eq!(xml("logb(2, x)"), r#"<OMA><OMS cd="transc1" name="log"/><OMI>2</OMI><OMV name="x"/></OMA>"#);
+        assert_eq!(xml("i"), r#"<OMA><OMS cd="complex1" name="complex_cartesian"/><OMI>0</OMI><OMI>1</OMI></OMA>"#);
+        assert_eq!(xml(""), "");
+    }
+
+    #[test]
+    fn openmath_json() {
+        assert_eq!(
+            Ast::string_to_ast("sin(pi / 2)").to_openmath(OpenMathFormat::Json),
+            concat!(
+                r#"{"kind":"OMOBJ","openmath":"2.0","object":"#,
+                r#"{"kind":"OMA","applicant":{"kind":"OMS","cd":"transc1","name":"sin"},"arguments":["#,
+                r#"{"kind":"OMA","applicant":{"kind":"OMS","cd":"arith1","name":"divide"},"arguments":["#,
+                r#"{"kind":"OMS","cd":"nums1","name":"pi"},{"kind":"OMI","integer":2}]}]}}"#
+            )
+        );
+        assert_eq!(
+            Ast::string_to_ast("ln(x) - 0.5").to_openmath(OpenMathFormat::Json),
+            concat!(
+                r#"{"kind":"OMOBJ","openmath":"2.0","object":"#,
+                r#"{"kind":"OMA","applicant":{"kind":"OMS","cd":"arith1","name":"minus"},"arguments":["#,
+                r#"{"kind":"OMA","applicant":{"kind":"OMS","cd":"transc1","name":"ln"},"arguments":[{"kind":"OMV","name":"x"}]},"#,
+                r#"{"kind":"OMF","float":0.5}]}}"#
+            )
+        );
+        assert_eq!(Ast::string_to_ast("").to_openmath(OpenMathFormat::Json), r#"{"kind":"OMOBJ","openmath":"2.0"}"#);
+    }
+}
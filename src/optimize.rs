@@ -0,0 +1,200 @@
+use crate::ast::Ast;
+use crate::rewrite::RewriteRule;
+
+/// A single transformation of an expression that an `OptimizationPipeline` can run
+pub trait OptimizationPass {
+    fn apply(&self, ast: &Ast) -> Ast;
+    fn name(&self) -> &str;
+}
+
+/// Evaluate every subtree without variables down to a number
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConstantFolding;
+
+impl OptimizationPass for ConstantFolding {
+    fn apply(&self, ast: &Ast) -> Ast {
+        Ast::new(ast.expr.fold_constants())
+    }
+
+    fn name(&self) -> &str {
+        "constant folding"
+    }
+}
+
+/// Remove operations that leave their operand unchanged, as in `Ast::simplify`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IdentitySimplification;
+
+impl OptimizationPass for IdentitySimplification {
+    fn apply(&self, ast: &Ast) -> Ast {
+        ast.simplify()
+    }
+
+    fn name(&self) -> &str {
+        "identity simplification"
+    }
+}
+
+/// Put the operands of commutative operators in a canonical order, as in `Ast::sort_commutative`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CommutativeSorting;
+
+impl OptimizationPass for CommutativeSorting {
+    fn apply(&self, ast: &Ast) -> Ast {
+        ast.sort_commutative()
+    }
+
+    fn name(&self) -> &str {
+        "commutative sorting"
+    }
+}
+
+/// Apply a set of rewrite rules bottom-up
+#[derive(Debug, Clone)]
+pub struct RewritePass {
+    name: String,
+    rules: Vec<RewriteRule>
+}
+
+impl RewritePass {
+    pub fn new(name: &str, rules: Vec<RewriteRule>) -> Self {
+        Self { name: name.to_owned(), rules }
+    }
+
+    /// The identity sin(x)^2 + cos(x)^2 = 1
+    /// Sorting puts cos before sin, so after `CommutativeSorting` the identity only needs matching
+    /// in that order, either on its own or at the end of a longer sum
+    pub fn pythagorean() -> Self {
+        Self::new("pythagorean identity", vec![
+            RewriteRule::parse("cos(x)^2 + sin(x)^2", "1"),
+            RewriteRule::parse("y + cos(x)^2 + sin(x)^2", "y + 1"),
+        ])
+    }
+}
+
+impl OptimizationPass for RewritePass {
+    fn apply(&self, ast: &Ast) -> Ast {
+        ast.rewrite(&self.rules)
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// An ordered list of passes, run one after the other
+#[derive(Default)]
+pub struct OptimizationPipeline {
+    passes: Vec<Box<dyn OptimizationPass>>
+}
+
+impl OptimizationPipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The passes used by `Ast::optimize`: constant folding, identity simplification,
+    /// commutative sorting and the pythagorean identity
+    pub fn standard() -> Self {
+        let mut pipeline = Self::new();
+        pipeline.add_pass(ConstantFolding);
+        pipeline.add_pass(IdentitySimplification);
+        pipeline.add_pass(CommutativeSorting);
+        pipeline.add_pass(RewritePass::pythagorean());
+        pipeline
+    }
+
+    pub fn add_pass(&mut self, pass: impl OptimizationPass + 'static) {
+        self.passes.push(Box::new(pass));
+    }
+
+    /// Names of the passes in the order they run
+    pub fn pass_names(&self) -> Vec<&str> {
+        self.passes.iter().map(|pass| pass.name()).collect()
+    }
+
+    /// Apply every pass once, in order
+    pub fn run_once(&self, ast: &Ast) -> Ast {
+        self.passes.iter().fold(ast.clone(), |ast, pass| pass.apply(&ast))
+    }
+
+    /// Apply the passes in order, repeating until a round changes nothing
+    pub fn run(&self, ast: &Ast) -> Ast {
+        let mut ast = ast.clone();
+        loop {
+            let next = self.run_once(&ast);
+            if next == ast {
+                return ast
+            }
+            ast = next;
+        }
+    }
+}
+
+impl Ast {
+    /// Look for a smaller equivalent expression by folding constants, removing identities,
+    /// sorting commutative operands and applying identities like sin(x)^2 + cos(x)^2 = 1,
+    /// repeating until none of them change anything
+    pub fn optimize(&self) -> Ast {
+        OptimizationPipeline::standard().run(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct NoOpPass;
+
+    impl OptimizationPass for NoOpPass {
+        fn apply(&self, ast: &Ast) -> Ast {
+            ast.clone()
+        }
+
+        fn name(&self) -> &str {
+            "no-op"
+        }
+    }
+
+    #[test]
+    fn optimize_reduces_complexity() {
+        for (s, expected) in [
+            ("sin(y)^2 + cos(y)^2", "1"),
+            ("(x * 1 + 0) * (2 + 3) + sin(z)^2 + cos(z * 1)^2", "1 + 5 * x"),
+            ("(1 + 1) * x^(3 - 2) + 0 * y", "2 * x"),
+            ("exp(0) * (b + a) / (2 - 1)", "a + b"),
+        ] {
+            let ast = Ast::string_to_ast(s);
+            let optimized = ast.optimize();
+            assert_eq!(optimized, Ast::string_to_ast(expected), "{}", s);
+            assert!(optimized.complexity() < ast.complexity());
+        }
+        let ast = Ast::string_to_ast("sin(x) + x * y");
+        assert_eq!(ast.optimize(), ast);
+    }
+
+    #[test]
+    fn no_op_pass() {
+        let mut pipeline = OptimizationPipeline::new();
+        pipeline.add_pass(NoOpPass);
+        assert_eq!(pipeline.pass_names(), vec!["no-op"]);
+        let ast = Ast::string_to_ast("(x + 0) * 1 + sin(y)^2 + cos(y)^2");
+        assert_eq!(pipeline.run_once(&ast), ast);
+        assert_eq!(pipeline.run(&ast), ast);
+        assert_eq!(OptimizationPipeline::new().run(&ast), ast);
+    }
+
+    #[test]
+    fn run_once_versus_fixed_point() {
+        let mut pipeline = OptimizationPipeline::new();
+        pipeline.add_pass(RewritePass::pythagorean());
+        pipeline.add_pass(CommutativeSorting);
+        // The identity is only matched once the operands are sorted, which takes a second round
+        let ast = Ast::string_to_ast("sin(x)^2 + cos(x)^2");
+        assert_eq!(pipeline.run_once(&ast), Ast::string_to_ast("cos(x)^2 + sin(x)^2"));
+        assert_eq!(pipeline.run(&ast), Ast::string_to_ast("1"));
+        assert_eq!(OptimizationPipeline::standard().pass_names(), vec![
+            "constant folding", "identity simplification", "commutative sorting", "pythagorean identity"
+        ]);
+    }
+}
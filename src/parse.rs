@@ -1,15 +1,18 @@
+use std::collections::{HashMap, HashSet};
 use std::fmt::{Display, Formatter};
-use std::iter::Peekable;
-use logos::Lexer;
-use crate::lex::Token;
+use crate::context::Function;
+use crate::error::{EvalError, ParseError};
+use crate::lex::{Token, TokenStream};
 
 #[derive(Debug, PartialEq, Clone, Copy)]
-pub(crate) enum BinOp {
+pub enum BinOp {
     Plus,
     Minus,
     Multiply,
     Divide,
-    Power
+    Power,
+    /// Logarithm of the right operand to the base of the left, written logb(base, x)
+    LogBase
 }
 
 impl Display for BinOp {
@@ -19,13 +22,67 @@ impl Display for BinOp {
             BinOp::Minus => "-",
             BinOp::Multiply => "*",
             BinOp::Divide => "/",
-            BinOp::Power => "^"
+            BinOp::Power => "^",
+            BinOp::LogBase => "logb"
         };
 
         write!(f, "{}", s)
     }
 }
 
+impl BinOp {
+    pub fn is_commutative(&self) -> bool {
+        matches!(self, BinOp::Plus | BinOp::Multiply)
+    }
+
+    pub fn is_associative(&self) -> bool {
+        matches!(self, BinOp::Plus | BinOp::Multiply)
+    }
+
+    /// The value e with x op e == e op x == x, if one exists
+    pub fn identity(&self) -> Option<f64> {
+        match self {
+            BinOp::Plus => Some(0.0),
+            BinOp::Multiply => Some(1.0),
+            _ => None
+        }
+    }
+
+    /// The value z with x op z == z op x == z, if one exists
+    pub fn absorbing(&self) -> Option<f64> {
+        match self {
+            BinOp::Multiply => Some(0.0),
+            _ => None
+        }
+    }
+}
+
+impl BinOp {
+    /// The operators written between their operands, which excludes logb
+    pub const ALL: [BinOp; 5] = [BinOp::Plus, BinOp::Minus, BinOp::Multiply, BinOp::Divide, BinOp::Power];
+
+    /// Binding power of the operator, where higher binds tighter
+    /// logb is written like a function call, so it binds like the other functions
+    pub fn precedence(&self) -> u8 {
+        match infix_prec(&Token::from(*self)) {
+            Some((prec, _)) => prec,
+            None => prefix_prec(&Token::Log).expect("Functions have a prefix precedence").1
+        }
+    }
+
+    pub(crate) fn apply(&self, v1: f64, v2: f64) -> Result<f64, EvalError> {
+        let result = match self {
+            BinOp::Plus => {v1 + v2},
+            BinOp::Minus => {v1 - v2},
+            BinOp::Multiply => {v1 * v2},
+            BinOp::Divide => {v1 / v2},
+            BinOp::Power => {v1.powf(v2)}
+            BinOp::LogBase => {log_base(v1, v2)?}
+        };
+        Ok(result)
+    }
+}
+
 impl From<Token> for BinOp {
     fn from(value: Token) -> Self {
         match value {
@@ -34,20 +91,39 @@ impl From<Token> for BinOp {
             Token::Multiply => Self::Multiply,
             Token::Divide => Self::Divide,
             Token::Power => Self::Power,
+            Token::LogBase => Self::LogBase,
             e => panic!("Cannot convert {:?} to binary operator", e),
         }
     }
 }
 
+impl From<BinOp> for Token {
+    fn from(value: BinOp) -> Self {
+        match value {
+            BinOp::Plus => Token::Plus,
+            BinOp::Minus => Token::Minus,
+            BinOp::Multiply => Token::Multiply,
+            BinOp::Divide => Token::Divide,
+            BinOp::Power => Token::Power,
+            BinOp::LogBase => Token::LogBase
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Copy)]
-pub(crate) enum UnOp {
+pub enum UnOp {
     Negative,
     Sin,
     Cos,
     Tan,
     Exp,
     Log,
-    Factorial
+    Sqrt,
+    Asinh,
+    Acosh,
+    Atanh,
+    Factorial,
+    Percent
 }
 
 impl Display for UnOp {
@@ -59,13 +135,56 @@ impl Display for UnOp {
             UnOp::Tan => "tan",
             UnOp::Exp => "exp",
             UnOp::Log => "log",
-            UnOp::Factorial => "!"
+            UnOp::Sqrt => "sqrt",
+            UnOp::Asinh => "asinh",
+            UnOp::Acosh => "acosh",
+            UnOp::Atanh => "atanh",
+            UnOp::Factorial => "!",
+            UnOp::Percent => "%"
         };
 
         write!(f, "{}", s)
     }
 }
 
+impl UnOp {
+    pub const ALL: [UnOp; 12] = [
+        UnOp::Negative, UnOp::Sin, UnOp::Cos, UnOp::Tan, UnOp::Exp, UnOp::Log, UnOp::Sqrt,
+        UnOp::Asinh, UnOp::Acosh, UnOp::Atanh, UnOp::Factorial, UnOp::Percent
+    ];
+
+    pub fn is_postfix(&self) -> bool {
+        postfix_prec(&Token::from(*self)).is_some()
+    }
+
+    /// Binding power of the operator, where higher binds tighter
+    pub fn precedence(&self) -> u8 {
+        let token = Token::from(*self);
+        match (prefix_prec(&token), postfix_prec(&token)) {
+            (Some(((), prec)), _) | (None, Some((prec, ()))) => prec,
+            (None, None) => unreachable!("Every unary operator has a prefix or postfix precedence")
+        }
+    }
+
+    pub(crate) fn apply(&self, val: f64) -> Result<f64, EvalError> {
+        let result = match self {
+            UnOp::Negative => {-val},
+            UnOp::Sin => {val.sin()},
+            UnOp::Cos => {val.cos()},
+            UnOp::Tan => {val.tan()},
+            UnOp::Exp => {val.exp()},
+            UnOp::Log => {val.ln()}
+            UnOp::Sqrt => {val.sqrt()}
+            UnOp::Asinh => {val.asinh()}
+            UnOp::Acosh => {acosh(val)?}
+            UnOp::Atanh => {atanh(val)?}
+            UnOp::Factorial => {factorial(val)?}
+            UnOp::Percent => {val / 100.0}
+        };
+        Ok(result)
+    }
+}
+
 impl From<Token> for UnOp {
     fn from(value: Token) -> Self {
         match value {
@@ -75,27 +194,231 @@ impl From<Token> for UnOp {
             Token::Tan => Self::Tan,
             Token::Exp => Self::Exp,
             Token::Log => Self::Log,
+            Token::Sqrt => Self::Sqrt,
+            Token::Asinh => Self::Asinh,
+            Token::Acosh => Self::Acosh,
+            Token::Atanh => Self::Atanh,
             Token::Factorial => Self::Factorial,
+            Token::Percent => Self::Percent,
             e => panic!("Cannot convert {:?} to unary operator", e)
         }
     }
 }
 
+impl From<UnOp> for Token {
+    fn from(value: UnOp) -> Self {
+        match value {
+            UnOp::Negative => Token::Minus,
+            UnOp::Sin => Token::Sin,
+            UnOp::Cos => Token::Cos,
+            UnOp::Tan => Token::Tan,
+            UnOp::Exp => Token::Exp,
+            UnOp::Log => Token::Log,
+            UnOp::Sqrt => Token::Sqrt,
+            UnOp::Asinh => Token::Asinh,
+            UnOp::Acosh => Token::Acosh,
+            UnOp::Atanh => Token::Atanh,
+            UnOp::Factorial => Token::Factorial,
+            UnOp::Percent => Token::Percent
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
-pub(crate) enum Expr {
+pub enum Expr {
     BinaryOp(BinOp, Box<Expr>, Box<Expr>),
     UnaryOp(UnOp, Box<Expr>),
     Number(f64),
+    /// A complex constant re + im * i
+    Complex(f64, f64),
+    Variable(String),
+    /// Bind a name to the value of the first expression while evaluating the second,
+    /// written let name = value in body
+    Let(String, Box<Expr>, Box<Expr>),
     Eof
 }
 
+impl Expr {
+    pub fn zero() -> Expr {
+        Expr::Number(0.0)
+    }
+
+    pub fn one() -> Expr {
+        Expr::Number(1.0)
+    }
+
+    pub fn const_(n: f64) -> Expr {
+        Expr::Number(n)
+    }
+
+    pub fn var(name: &str) -> Expr {
+        Expr::Variable(name.to_string())
+    }
+
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Expr::Number(n) => Some(*n),
+            _ => None
+        }
+    }
+
+    pub fn as_binop(&self) -> Option<(BinOp, &Expr, &Expr)> {
+        match self {
+            Expr::BinaryOp(op, e1, e2) => Some((*op, e1, e2)),
+            _ => None
+        }
+    }
+
+    pub fn as_unop(&self) -> Option<(UnOp, &Expr)> {
+        match self {
+            Expr::UnaryOp(op, e) => Some((*op, e)),
+            _ => None
+        }
+    }
+
+    pub fn as_variable(&self) -> Option<&str> {
+        match self {
+            Expr::Variable(name) => Some(name),
+            _ => None
+        }
+    }
+
+    pub fn is_number(&self) -> bool {
+        matches!(self, Expr::Number(_))
+    }
+
+    pub fn is_binop(&self) -> bool {
+        matches!(self, Expr::BinaryOp(..))
+    }
+
+    pub fn is_unop(&self) -> bool {
+        matches!(self, Expr::UnaryOp(..))
+    }
+
+    pub fn is_variable(&self) -> bool {
+        matches!(self, Expr::Variable(_))
+    }
+
+    pub fn is_eof(&self) -> bool {
+        matches!(self, Expr::Eof)
+    }
+}
+
+/// The zero constant, chosen as a neutral default since it is the additive identity
+impl Default for Expr {
+    fn default() -> Self {
+        Expr::Number(0.0)
+    }
+}
+
+impl From<f64> for Expr {
+    fn from(n: f64) -> Expr {
+        Expr::Number(n)
+    }
+}
+
+impl From<i64> for Expr {
+    fn from(n: i64) -> Expr {
+        Expr::Number(n as f64)
+    }
+}
+
+impl TryFrom<Expr> for f64 {
+    /// The expression is handed back when it is not a plain number
+    type Error = Expr;
+
+    fn try_from(value: Expr) -> Result<f64, Expr> {
+        match value {
+            Expr::Number(n) => Ok(n),
+            e => Err(e)
+        }
+    }
+}
+
+impl Expr {
+    // Binding power of the expression when printed, matching the parser's precedences
+    pub(crate) fn display_prec(&self) -> u8 {
+        match self {
+            Expr::BinaryOp(op, _, _) => match op {
+                BinOp::Plus | BinOp::Minus => 1,
+                BinOp::Multiply | BinOp::Divide => 3,
+                BinOp::Power => 5,
+                BinOp::LogBase => 10
+            },
+            Expr::UnaryOp(UnOp::Factorial | UnOp::Percent, _) => 9,
+            Expr::UnaryOp(_, _) => 8,
+            Expr::Number(n) if n.is_sign_negative() => 8,
+            Expr::Complex(re, im) if *re != 0.0 || im.is_sign_negative() => 1,
+            Expr::Complex(_, im) if *im != 1.0 => 3,
+            Expr::Number(_) | Expr::Complex(_, _) | Expr::Variable(_) | Expr::Eof => 10,
+            // The body of a let runs to the end, so one inside anything else needs parentheses
+            Expr::Let(..) => 0
+        }
+    }
+
+    fn fmt_child(&self, f: &mut Formatter<'_>, needs_parens: bool) -> std::fmt::Result {
+        if needs_parens {
+            write!(f, "({})", self)
+        } else {
+            write!(f, "{}", self)
+        }
+    }
+}
+
+/// Print the expression in infix notation, adding only the parentheses needed to parse it back
+impl Display for Expr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let prec = self.display_prec();
+        match self {
+            Expr::BinaryOp(BinOp::LogBase, base, e) => write!(f, "logb({}, {})", base, e),
+            Expr::BinaryOp(op, e1, e2) => {
+                // Power is the only right associative operator
+                let (left_parens, right_parens) = if *op == BinOp::Power {
+                    (e1.display_prec() <= prec, e2.display_prec() < prec)
+                } else {
+                    (e1.display_prec() < prec, e2.display_prec() <= prec)
+                };
+                e1.fmt_child(f, left_parens)?;
+                match op {
+                    BinOp::Power => write!(f, "{}", op)?,
+                    _ => write!(f, " {} ", op)?
+                }
+                e2.fmt_child(f, right_parens)
+            },
+            Expr::UnaryOp(op @ (UnOp::Factorial | UnOp::Percent), e) => {
+                e.fmt_child(f, e.display_prec() < prec)?;
+                write!(f, "{}", op)
+            },
+            Expr::UnaryOp(UnOp::Negative, e) => {
+                write!(f, "-")?;
+                e.fmt_child(f, e.display_prec() < prec)
+            },
+            // The lexer only accepts ln for the natural log
+            Expr::UnaryOp(UnOp::Log, e) => write!(f, "ln({})", e),
+            Expr::UnaryOp(op, e) => write!(f, "{}({})", op, e),
+            Expr::Number(n) => write!(f, "{}", n),
+            Expr::Complex(re, im) => {
+                match (*re == 0.0, *im == 1.0) {
+                    (true, true) => write!(f, "i"),
+                    (true, false) => write!(f, "{} * i", im),
+                    (false, _) => write!(f, "{} + {} * i", re, im)
+                }
+            },
+            Expr::Variable(name) => write!(f, "{}", name),
+            Expr::Let(name, value, body) => write!(f, "let {} = {} in {}", name, value, body),
+            Expr::Eof => Ok(())
+        }
+    }
+}
+
 impl Expr {
     // Get number of cells needed to display the corresponding AST
     pub(crate) fn get_width(&self) -> usize {
         match self {
             Expr::BinaryOp(_, e1, e2) => e1.get_width() + e2.get_width() + 3,
             Expr::UnaryOp(_, e) => e.get_width(),
-            Expr::Number(_) => 1usize,
+            Expr::Number(_) | Expr::Complex(_, _) | Expr::Variable(_) => 1usize,
+            Expr::Let(..) => self.inline_lets().get_width(),
             Expr::Eof => 0usize
         }
     }
@@ -106,73 +429,272 @@ impl Expr {
             Expr::BinaryOp(_, e1, e2) => std::cmp::max(e1.get_max_len(), e2.get_max_len()),
             Expr::UnaryOp(_, e) => e.get_max_len(),
             Expr::Number(n) => std::cmp::max(n.to_string().len(), 3usize),
+            Expr::Complex(_, _) => std::cmp::max(self.to_string().len(), 3usize),
+            Expr::Variable(name) => std::cmp::max(name.len(), 3usize),
+            Expr::Let(..) => self.inline_lets().get_max_len(),
             Expr::Eof => 0usize
         }
     }
 
-    /// Print the hierarchical representation of Expr
+    /// Write the hierarchical representation of Expr, one node per line
+    /// The root is written without a branch so that its children line up beneath it
     /// Adapted from: https://stackoverflow.com/a/51730733/22391278
-    pub(crate) fn print_hierarchy(&self, prefix: &str, is_left: bool) {
-        let second_part = if is_left {
+    pub(crate) fn write_hierarchy(&self, out: &mut String, prefix: &str, is_left: bool, is_root: bool) {
+        let _ = self.write_annotated_hierarchy(out, prefix, is_left, is_root, None);
+    }
+
+    /// Write the hierarchy as in `write_hierarchy`, and if values are given for the variables
+    /// annotate each node with the value of its subtree, or ? if it has none
+    /// Children are written to a buffer first, since their values are needed for the node's line
+    pub(crate) fn write_annotated_hierarchy(&self, out: &mut String, prefix: &str, is_left: bool, is_root: bool,
+                                            vars: Option<&HashMap<String, f64>>) -> Result<f64, EvalError> {
+        let second_part = if is_root {
+            ""
+        } else if is_left {
             "├── "
         } else {
             "└── "
         };
-        let new_prefix = prefix.to_owned() + if is_left {
+        let new_prefix = prefix.to_owned() + if is_root {
+            ""
+        } else if is_left {
             "│   "
         } else {
             "    "
         };
-        match self {
+        let mut children = String::new();
+        let (label, val) = match self {
             Expr::BinaryOp(op, e1, e2) => {
-                println!("{}{}{}", prefix, second_part, op);
-                e1.print_hierarchy(&new_prefix, true);
-                e2.print_hierarchy(&new_prefix, false);
+                let v1 = e1.write_annotated_hierarchy(&mut children, &new_prefix, true, false, vars);
+                let v2 = e2.write_annotated_hierarchy(&mut children, &new_prefix, false, false, vars);
+                (op.to_string(), v1.and_then(|v1| op.apply(v1, v2?)))
             },
             Expr::UnaryOp(op, e) => {
-                println!("{}{}{}", prefix, second_part, op);
-                e.print_hierarchy(&new_prefix, false);
+                let v = e.write_annotated_hierarchy(&mut children, &new_prefix, false, false, vars);
+                (op.to_string(), v.and_then(|v| op.apply(v)))
             },
-            Expr::Number(n) => {
-                println!("{}{}{}", prefix, second_part, n);
+            Expr::Number(n) => (n.to_string(), Ok(*n)),
+            Expr::Complex(_, _) => (self.to_string(), Err(EvalError::NotReal)),
+            Expr::Variable(name) => {
+                let val = vars.and_then(|vars| vars.get(name).copied())
+                    .ok_or_else(|| EvalError::UnboundVariable(name.clone()));
+                (name.clone(), val)
             },
-            Expr::Eof => {}
+            Expr::Let(..) => return self.inline_lets().write_annotated_hierarchy(out, prefix, is_left, is_root, vars),
+            Expr::Eof => return Err(EvalError::EmptyExpression)
+        };
+        let annotation = match (vars, &val) {
+            (None, _) => String::new(),
+            (Some(_), Ok(v)) => format!(" [{:?}]", v),
+            (Some(_), Err(_)) => " [?]".to_owned()
+        };
+        out.push_str(&format!("{}{}{}{}\n", prefix, second_part, label, annotation));
+        out.push_str(&children);
+        val
+    }
+
+    /// Collect all terms of a chain of additions, e.g. ((a + b) + c) gives [a, b, c]
+    pub fn flatten_additions(&self) -> Vec<&Expr> {
+        self.flatten_op(BinOp::Plus)
+    }
+
+    /// Collect all factors of a chain of multiplications, e.g. a * (b * c) gives [a, b, c]
+    pub fn flatten_multiplications(&self) -> Vec<&Expr> {
+        self.flatten_op(BinOp::Multiply)
+    }
+
+    pub(crate) fn flatten_op(&self, target: BinOp) -> Vec<&Expr> {
+        match self {
+            Expr::BinaryOp(op, e1, e2) if *op == target => {
+                let mut terms = e1.flatten_op(target);
+                terms.extend(e2.flatten_op(target));
+                terms
+            },
+            e => vec![e]
         }
     }
 
-    pub(crate) fn eval(&self) -> f64 {
+    /// Collect the names of all variables appearing in the expression
+    pub(crate) fn free_variables(&self) -> HashSet<String> {
+        let mut vars = HashSet::new();
+        self.collect_variables(&mut vars);
+        vars
+    }
+
+    fn collect_variables(&self, vars: &mut HashSet<String>) {
+        match self {
+            Expr::BinaryOp(_, e1, e2) => {
+                e1.collect_variables(vars);
+                e2.collect_variables(vars);
+            },
+            Expr::UnaryOp(_, e) => e.collect_variables(vars),
+            Expr::Variable(name) => {
+                vars.insert(name.clone());
+            },
+            Expr::Let(name, value, body) => {
+                value.collect_variables(vars);
+                let mut body_vars = body.free_variables();
+                body_vars.remove(name);
+                vars.extend(body_vars);
+            },
+            Expr::Number(_) | Expr::Complex(_, _) | Expr::Eof => {}
+        }
+    }
+
+    /// Replace each variable bound in `bindings` with a copy of its expression
+    pub(crate) fn substitute(&self, bindings: &HashMap<String, Expr>) -> Expr {
         match self {
             Expr::BinaryOp(op, e1, e2) => {
+                Expr::BinaryOp(*op, Box::new(e1.substitute(bindings)), Box::new(e2.substitute(bindings)))
+            },
+            Expr::UnaryOp(op, e) => Expr::UnaryOp(*op, Box::new(e.substitute(bindings))),
+            Expr::Variable(name) => bindings.get(name).cloned().unwrap_or_else(|| self.clone()),
+            // The name bound by a let hides any binding of the same name in its body
+            Expr::Let(name, value, body) => {
+                let mut inner = bindings.clone();
+                inner.remove(name);
+                Expr::Let(name.clone(), Box::new(value.substitute(bindings)), Box::new(body.substitute(&inner)))
+            },
+            e => e.clone()
+        }
+    }
+
+    /// Replace each let binding with copies of its value, giving an equivalent tree without `Expr::Let`
+    pub(crate) fn inline_lets(&self) -> Expr {
+        match self {
+            Expr::BinaryOp(op, e1, e2) => Expr::BinaryOp(*op, Box::new(e1.inline_lets()), Box::new(e2.inline_lets())),
+            Expr::UnaryOp(op, e) => Expr::UnaryOp(*op, Box::new(e.inline_lets())),
+            Expr::Let(name, value, body) => {
+                body.inline_lets().substitute(&HashMap::from([(name.clone(), value.inline_lets())]))
+            },
+            e => e.clone()
+        }
+    }
+
+    pub(crate) fn eval(&self) -> Result<f64, EvalError> {
+        self.eval_with(&HashMap::new())
+    }
+
+    /// Evaluate the expression, looking up any variables in `vars`
+    pub(crate) fn eval_with(&self, vars: &HashMap<String, f64>) -> Result<f64, EvalError> {
+        let val = match self {
+            Expr::BinaryOp(op, e1, e2) => op.apply(e1.eval_with(vars)?, e2.eval_with(vars)?)?,
+            Expr::UnaryOp(op, e) => op.apply(e.eval_with(vars)?)?,
+            Expr::Number(n) => *n,
+            Expr::Complex(re, im) => {
+                if *im != 0.0 {
+                    return Err(EvalError::NotReal)
+                }
+                *re
+            },
+            Expr::Variable(name) => {
+                match vars.get(name) {
+                    Some(v) => *v,
+                    None => return Err(EvalError::UnboundVariable(name.clone()))
+                }
+            },
+            Expr::Let(name, value, body) => {
+                let mut vars = vars.clone();
+                vars.insert(name.clone(), value.eval_with(&vars)?);
+                body.eval_with(&vars)?
+            },
+            Expr::Eof => return Err(EvalError::EmptyExpression)
+        };
+
+        Ok(val)
+    }
+
+    /// Evaluate the expression using single precision arithmetic throughout
+    pub(crate) fn eval_f32(&self) -> Result<f32, EvalError> {
+        let val = match self {
+            Expr::BinaryOp(op, e1, e2) => {
+                let (v1, v2) = (e1.eval_f32()?, e2.eval_f32()?);
                 match op {
-                    BinOp::Plus => {e1.eval() + e2.eval()},
-                    BinOp::Minus => {e1.eval() - e2.eval()},
-                    BinOp::Multiply => {e1.eval() * e2.eval()},
-                    BinOp::Divide => {e1.eval() / e2.eval()},
-                    BinOp::Power => {e1.eval().powf(e2.eval())}
+                    BinOp::Plus => {v1 + v2},
+                    BinOp::Minus => {v1 - v2},
+                    BinOp::Multiply => {v1 * v2},
+                    BinOp::Divide => {v1 / v2},
+                    BinOp::Power => {v1.powf(v2)}
+                    BinOp::LogBase => {log_base(v1 as f64, v2 as f64)? as f32}
                 }
             },
             Expr::UnaryOp(op, e) => {
+                let val = e.eval_f32()?;
                 match op {
-                    UnOp::Negative => {-e.eval()},
-                    UnOp::Sin => {e.eval().sin()},
-                    UnOp::Cos => {e.eval().cos()},
-                    UnOp::Tan => {e.eval().tan()},
-                    UnOp::Exp => {e.eval().exp()},
-                    UnOp::Log => {e.eval().ln()}
-                    UnOp::Factorial => {
-                        let val = e.eval();
-                        if val.fract() == 0.0 {
-                            let int_val = val as u64;
-                            (1..=int_val).product::<u64>() as f64
-                        } else {
-                            panic!("Cannot evaluate factorial on decimal.")
-                        }
-                    }
+                    UnOp::Negative => {-val},
+                    UnOp::Sin => {val.sin()},
+                    UnOp::Cos => {val.cos()},
+                    UnOp::Tan => {val.tan()},
+                    UnOp::Exp => {val.exp()},
+                    UnOp::Log => {val.ln()}
+                    UnOp::Sqrt => {val.sqrt()}
+                    UnOp::Asinh => {val.asinh()}
+                    UnOp::Acosh => {acosh(val as f64)? as f32}
+                    UnOp::Atanh => {atanh(val as f64)? as f32}
+                    UnOp::Factorial => {factorial(val as f64)? as f32}
+                    UnOp::Percent => {val / 100.0}
                 }
             },
-            Expr::Number(n) => *n,
-            Expr::Eof => panic!("Should not eval Eof expr!")
+            Expr::Number(n) => *n as f32,
+            Expr::Complex(re, im) => {
+                if *im != 0.0 {
+                    return Err(EvalError::NotReal)
+                }
+                *re as f32
+            },
+            Expr::Variable(name) => return Err(EvalError::UnboundVariable(name.clone())),
+            Expr::Let(..) => self.inline_lets().eval_f32()?,
+            Expr::Eof => return Err(EvalError::EmptyExpression)
+        };
+
+        Ok(val)
+    }
+}
+
+// Logarithm of x to the given base, which has no meaning for a base of 1
+// Bases 2 and 10 have dedicated functions that are exact on powers of the base
+pub(crate) fn log_base(base: f64, x: f64) -> Result<f64, EvalError> {
+    let val = match base {
+        1.0 => return Err(EvalError::InvalidArgument("logb is undefined for base 1".to_owned())),
+        2.0 => x.log2(),
+        10.0 => x.log10(),
+        _ => x.log(base)
+    };
+    Ok(val)
+}
+
+// The inverse hyperbolic cosine, which is only real from 1 upwards
+pub(crate) fn acosh(val: f64) -> Result<f64, EvalError> {
+    if val < 1.0 {
+        return Err(EvalError::InvalidArgument(format!("acosh is undefined for {}, which is less than 1", val)))
+    }
+    Ok(val.acosh())
+}
+
+// The inverse hyperbolic tangent, which is only finite strictly between -1 and 1
+pub(crate) fn atanh(val: f64) -> Result<f64, EvalError> {
+    if val.abs() >= 1.0 {
+        return Err(EvalError::InvalidArgument(format!("atanh is undefined for {}, which is not between -1 and 1", val)))
+    }
+    Ok(val.atanh())
+}
+
+// Largest n whose factorial fits in a u64
+pub(crate) const MAX_FACTORIAL: u64 = 20;
+
+// 0! is 1 as the empty product, and -0 is treated as 0
+pub(crate) fn factorial(val: f64) -> Result<f64, EvalError> {
+    if val.fract() == 0.0 {
+        if val < 0.0 {
+            return Err(EvalError::FactorialNegative(val as i64))
         }
+        let int_val = val as u64;
+        (1..=int_val)
+            .try_fold(1u64, |acc, k| acc.checked_mul(k))
+            .map(|n| n as f64)
+            .ok_or(EvalError::FactorialOverflow(int_val))
+    } else {
+        Err(EvalError::NonIntegerFactorial(val))
     }
 }
 
@@ -189,46 +711,156 @@ fn infix_prec(op: &Token) -> Option<(u8, u8)> {
     Some(prec)
 }
 
-fn prefix_prec(op: &Token) -> ((), u8) {
-    match op {
+fn prefix_prec(op: &Token) -> Option<((), u8)> {
+    let prec = match op {
         Token::Minus => ((), 8),
         Token::Sin => ((), 8),
         Token::Cos => ((), 8),
         Token::Tan => ((), 8),
         Token::Exp => ((), 8),
         Token::Log => ((), 8),
-        _ => panic!("Bad op for prefix: {:?}", op)
-    }
+        Token::Sqrt => ((), 8),
+        Token::Asinh => ((), 8),
+        Token::Acosh => ((), 8),
+        Token::Atanh => ((), 8),
+        _ => return None
+    };
+    Some(prec)
 }
 
 fn postfix_prec(op: &Token) -> Option<(u8, ())> {
     let prec = match op {
         Token::Factorial => (9, ()),
+        Token::Percent => (9, ()),
         _ => return None,
     };
     Some(prec)
 }
 
+/// Parse a complete expression, failing if any input is left over
+pub(crate) fn parse_expr(lexer: &mut TokenStream) -> Result<Expr, ParseError> {
+    parse_expr_with(lexer, &HashMap::new())
+}
+
+/// Settings that change how the tokens of an expression are read
+pub(crate) struct ParseOptions<'a> {
+    /// User defined functions whose calls are inlined
+    pub(crate) functions: &'a HashMap<String, Function>,
+    /// Read an operand directly following another, as in 2x or 3(x + 1), as a product
+    pub(crate) implicit_multiply: bool
+}
+
+impl<'a> ParseOptions<'a> {
+    pub(crate) fn new(functions: &'a HashMap<String, Function>) -> Self {
+        Self { functions, implicit_multiply: false }
+    }
+}
+
+// Whether the token can begin an operand, so that it implies a product when it follows another
+fn starts_operand(token: &Token) -> bool {
+    match token {
+        Token::Number(_) | Token::Identifier(_) | Token::Pi | Token::Euler | Token::Tau | Token::Phi
+        | Token::Inf | Token::Imaginary | Token::LParens | Token::LogBase => true,
+        // A minus after an operand is always subtraction
+        Token::Minus => false,
+        t => prefix_prec(t).is_some()
+    }
+}
+
+/// Parse a complete expression, inlining calls to any of the user defined `functions`
+pub(crate) fn parse_expr_with(lexer: &mut TokenStream, functions: &HashMap<String, Function>) -> Result<Expr, ParseError> {
+    parse_expr_with_options(lexer, &ParseOptions::new(functions))
+}
+
+/// Parse a complete expression with the given options
+pub(crate) fn parse_expr_with_options(lexer: &mut TokenStream, options: &ParseOptions) -> Result<Expr, ParseError> {
+    let expr = expr_prec(lexer, 0, options)?;
+    match lexer.next() {
+        None => Ok(expr),
+        Some(Ok(t)) => Err(ParseError::UnexpectedToken(t)),
+        Some(Err(())) => Err(lexer.error())
+    }
+}
+
+// Parse the operand of an operator, which unlike a whole expression cannot be empty
+fn operand(lexer: &mut TokenStream, min_prec: u8, options: &ParseOptions) -> Result<Expr, ParseError> {
+    match expr_prec(lexer, min_prec, options)? {
+        Expr::Eof => Err(ParseError::UnexpectedEof),
+        e => Ok(e)
+    }
+}
+
+// Parse the comma separated arguments of a function call up to the closing parenthesis
+fn call_arguments(lexer: &mut TokenStream, options: &ParseOptions) -> Result<Vec<Expr>, ParseError> {
+    let mut args = Vec::new();
+    loop {
+        args.push(operand(lexer, 0, options)?);
+        match lexer.next() {
+            Some(Ok(Token::Comma)) => continue,
+            Some(Ok(Token::RParens)) => return Ok(args),
+            Some(Ok(t)) => return Err(ParseError::UnexpectedToken(t)),
+            Some(Err(())) => return Err(lexer.error()),
+            None => return Err(ParseError::MissingClosingParen)
+        }
+    }
+}
+
 /// Based off of this blog post: https://matklad.github.io/2020/04/13/simple-but-powerful-pratt-parsing.html
-pub(crate) fn expr_prec(lexer: &mut Peekable<Lexer<Token>>, min_prec: u8) -> Expr {
+pub(crate) fn expr_prec(lexer: &mut TokenStream, min_prec: u8, options: &ParseOptions) -> Result<Expr, ParseError> {
     // Check if lexer reached end of input
-    let lhs_read = if let Some(t) = lexer.next() {
-        t.expect("Could not read token")
-    } else {
-        return Expr::Eof
+    let lhs_read = match lexer.next() {
+        Some(Ok(t)) => t,
+        Some(Err(())) => return Err(lexer.error()),
+        None => return Ok(Expr::Eof)
     };
 
     // Otherwise check the next token type
     let mut lhs = match lhs_read {
         Token::Number(n) => Expr::Number(n),
+        Token::Pi => Expr::Number(std::f64::consts::PI),
+        Token::Euler => Expr::Number(std::f64::consts::E),
+        Token::Tau => Expr::Number(std::f64::consts::TAU),
+        Token::Phi => Expr::Number((1.0 + 5.0_f64.sqrt()) / 2.0),
+        Token::Inf => Expr::Number(f64::INFINITY),
+        Token::Imaginary => Expr::Complex(0.0, 1.0),
+        Token::LogBase => {
+            match lexer.next() {
+                Some(Ok(Token::LParens)) => {},
+                Some(Ok(t)) => return Err(ParseError::UnexpectedToken(t)),
+                Some(Err(())) => return Err(lexer.error()),
+                None => return Err(ParseError::UnexpectedEof)
+            }
+            let mut args = call_arguments(lexer, options)?;
+            if args.len() != 2 {
+                return Err(ParseError::WrongArity { name: "logb".to_owned(), expected: 2, found: args.len() })
+            }
+            let x = args.pop().unwrap();
+            let base = args.pop().unwrap();
+            Expr::BinaryOp(BinOp::LogBase, Box::new(base), Box::new(x))
+        },
+        Token::Identifier(name) => match options.functions.get(&name) {
+            Some(function) if lexer.peek() == Some(&Ok(Token::LParens)) => {
+                lexer.next();
+                let args = call_arguments(lexer, options)?;
+                function.inline(&name, args)?
+            },
+            _ => Expr::Variable(name)
+        },
         Token::LParens => {
-            let lhs = expr_prec(lexer, 0);
-            assert_eq!(lexer.next(), Some(Ok(Token::RParens)));
-            lhs
+            let lhs = operand(lexer, 0, options)?;
+            match lexer.next() {
+                Some(Ok(Token::RParens)) => lhs,
+                Some(Ok(t)) => return Err(ParseError::UnexpectedToken(t)),
+                Some(Err(())) => return Err(lexer.error()),
+                None => return Err(ParseError::MissingClosingParen)
+            }
         },
         t => {
-            let ((), r_prec) = prefix_prec(&t);
-            let rhs = expr_prec(lexer, r_prec);
+            let ((), r_prec) = match prefix_prec(&t) {
+                Some(prec) => prec,
+                None => return Err(ParseError::UnexpectedToken(t))
+            };
+            let rhs = operand(lexer, r_prec, options)?;
             Expr::UnaryOp(UnOp::from(t), Box::new(rhs))
         }
     };
@@ -236,7 +868,10 @@ pub(crate) fn expr_prec(lexer: &mut Peekable<Lexer<Token>>, min_prec: u8) -> Exp
     loop {
         let op = match lexer.peek() {
             Some(Ok(t)) => t,
-            Some(Err(e)) => panic!("Could not parse token with error: {:?}", e),
+            Some(Err(())) => {
+                lexer.next();
+                return Err(lexer.error())
+            },
             None => break,
         };
 
@@ -256,27 +891,38 @@ pub(crate) fn expr_prec(lexer: &mut Peekable<Lexer<Token>>, min_prec: u8) -> Exp
             }
 
             let op = lexer.next().unwrap().unwrap();
-            let rhs = expr_prec(lexer, r_prec);
+            let rhs = operand(lexer, r_prec, options)?;
 
             lhs = Expr::BinaryOp(BinOp::from(op), Box::new(lhs), Box::new(rhs));
             continue;
         }
 
+        if options.implicit_multiply && starts_operand(op) {
+            let (l_prec, r_prec) = infix_prec(&Token::Multiply).expect("Multiply is an infix operator");
+            if l_prec < min_prec {
+                break;
+            }
+
+            let rhs = operand(lexer, r_prec, options)?;
+            lhs = Expr::BinaryOp(BinOp::Multiply, Box::new(lhs), Box::new(rhs));
+            continue;
+        }
+
         break;
     }
 
-    lhs
+    Ok(lhs)
 }
 
 #[cfg(test)]
 mod tests {
-    use logos::Logos;
+    use crate::error::LexError;
     use super::*;
 
     #[test]
     fn parse_expr1() {
-        let lex = Token::lexer("sin(3--1)");
-        let test_e = expr_prec(&mut lex.peekable(), 0);
+        let mut lex = TokenStream::new("sin(3--1)");
+        let test_e = expr_prec(&mut lex, 0, &ParseOptions::new(&HashMap::new())).unwrap();
 
         let neg = Box::new(Expr::UnaryOp(UnOp::Negative, Box::new(Expr::Number(1f64))));
         let diff = Box::new(Expr::BinaryOp(BinOp::Minus, Box::new(Expr::Number(3f64)), neg));
@@ -286,8 +932,8 @@ mod tests {
 
     #[test]
     fn parse_expr2() {
-        let lex = Token::lexer("1+2/3-4/5");
-        let test_e = expr_prec(&mut lex.peekable(), 0);
+        let mut lex = TokenStream::new("1+2/3-4/5");
+        let test_e = expr_prec(&mut lex, 0, &ParseOptions::new(&HashMap::new())).unwrap();
 
         let frac1 = Box::new(Expr::BinaryOp(BinOp::Divide, Box::new(Expr::Number(2f64)), Box::new(Expr::Number(3f64))));
         let frac2 = Box::new(Expr::BinaryOp(BinOp::Divide, Box::new(Expr::Number(4f64)), Box::new(Expr::Number(5f64))));
@@ -296,4 +942,210 @@ mod tests {
 
         assert_eq!(test_e, expect_e);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn binop_properties() {
+        use BinOp::*;
+
+        for op in [Plus, Multiply] {
+            assert!(op.is_commutative());
+            assert!(op.is_associative());
+        }
+        for op in [Minus, Divide, Power] {
+            assert!(!op.is_commutative());
+            assert!(!op.is_associative());
+        }
+
+        assert_eq!(Plus.identity(), Some(0.0));
+        assert_eq!(Multiply.identity(), Some(1.0));
+        assert_eq!(Minus.identity(), None);
+        assert_eq!(Divide.identity(), None);
+        assert_eq!(Power.identity(), None);
+
+        assert_eq!(Multiply.absorbing(), Some(0.0));
+        assert_eq!(Plus.absorbing(), None);
+        assert_eq!(Minus.absorbing(), None);
+        assert_eq!(Divide.absorbing(), None);
+        assert_eq!(Power.absorbing(), None);
+    }
+
+    #[test]
+    fn flatten_chains() {
+        let mut lex = TokenStream::new("((1+2)+3)+4");
+        let e = expr_prec(&mut lex, 0, &ParseOptions::new(&HashMap::new())).unwrap();
+        let terms = e.flatten_additions();
+        assert_eq!(terms.len(), 4);
+        assert_eq!(terms[0], &Expr::Number(1f64));
+        assert_eq!(terms[3], &Expr::Number(4f64));
+        assert_eq!(e.flatten_multiplications(), vec![&e]);
+
+        let mut lex = TokenStream::new("2*(3-4)*x");
+        let e = expr_prec(&mut lex, 0, &ParseOptions::new(&HashMap::new())).unwrap();
+        let factors = e.flatten_multiplications();
+        assert_eq!(factors.len(), 3);
+        assert_eq!(factors[1].flatten_additions().len(), 1);
+        assert_eq!(factors[2], &Expr::Variable("x".to_owned()));
+    }
+
+    #[test]
+    fn destructuring_helpers() {
+        let parse = |s| parse_expr(&mut TokenStream::new(s)).unwrap();
+        let e = parse("sin(x) * 2");
+        let (op, lhs, rhs) = e.as_binop().unwrap();
+        assert_eq!(op, BinOp::Multiply);
+        assert_eq!(rhs.as_number(), Some(2.0));
+        assert_eq!(lhs.as_unop().map(|(op, arg)| (op, arg.as_variable())), Some((UnOp::Sin, Some("x"))));
+
+        assert!(e.is_binop() && !e.is_unop() && !e.is_number());
+        assert!(lhs.is_unop() && rhs.is_number() && parse("y").is_variable());
+        assert!(parse("").is_eof());
+        assert_eq!(e.as_number(), None);
+        assert_eq!(rhs.as_variable(), None);
+    }
+
+    #[test]
+    fn shorthand_constructors() {
+        assert_eq!(Expr::zero(), Expr::Number(0.0));
+        assert_eq!(Expr::one(), Expr::Number(1.0));
+        assert_eq!(Expr::const_(2.5), Expr::Number(2.5));
+        assert_eq!(Expr::var("x"), Expr::Variable("x".to_owned()));
+    }
+
+    #[test]
+    fn numeric_conversions() {
+        assert_eq!(Expr::from(3.0), Expr::Number(3.0));
+        assert_eq!(Expr::from(-4i64), Expr::Number(-4.0));
+        assert_eq!(f64::try_from(Expr::Number(5.0)), Ok(5.0));
+
+        let sum = Expr::BinaryOp(BinOp::Plus, Box::new(Expr::from(1.0)), Box::new(Expr::from(2i64)));
+        assert_eq!(f64::try_from(sum.clone()), Err(sum));
+    }
+
+    #[test]
+    fn default_is_zero() {
+        assert_eq!(Expr::default(), Expr::zero());
+        assert_eq!(Expr::default().eval(), Ok(0.0));
+    }
+
+    #[test]
+    fn factorial_overflow() {
+        assert_eq!(factorial(20.0), Ok(2432902008176640000.0));
+        assert_eq!(factorial(MAX_FACTORIAL as f64 + 1.0), Err(EvalError::FactorialOverflow(21)));
+        assert_eq!(factorial(1e10), Err(EvalError::FactorialOverflow(10000000000)));
+    }
+
+    #[test]
+    fn factorial_edge_cases() {
+        let eval = |s| parse_expr(&mut TokenStream::new(s)).unwrap().eval();
+        assert_eq!(eval("0!"), Ok(1.0));
+        assert_eq!(eval("(-1)!"), Err(EvalError::FactorialNegative(-1)));
+        assert_eq!(eval("(-3)!"), Err(EvalError::FactorialNegative(-3)));
+        assert_eq!(factorial(-0.0), Ok(1.0));
+        let eval_f32 = |s| parse_expr(&mut TokenStream::new(s)).unwrap().eval_f32();
+        assert_eq!(eval_f32("(-3)!"), Err(EvalError::FactorialNegative(-3)));
+        assert_eq!(eval_f32("1.5!"), Err(EvalError::NonIntegerFactorial(1.5)));
+        assert_eq!(eval_f32("20!"), Ok(2432902008176640000.0));
+        assert_eq!(eval_f32("21!"), Err(EvalError::FactorialOverflow(21)));
+    }
+
+    #[test]
+    fn inverse_hyperbolic() {
+        let eval = |s| parse_expr(&mut TokenStream::new(s)).unwrap().eval();
+        let close = |s, expected: f64| assert!((eval(s).unwrap() - expected).abs() < 1e-12, "{}", s);
+        // sinh(2) and cosh(1.5) written out with exp
+        close("asinh((exp(2) - exp(-2)) / 2)", 2.0);
+        close("acosh((exp(1.5) + exp(-1.5)) / 2)", 1.5);
+        close("atanh(0.5)", 0.5f64.atanh());
+        assert_eq!(eval("asinh(0) + acosh(1) + atanh(0)"), Ok(0.0));
+
+        assert_eq!(eval("acosh(0.5)"), Err(EvalError::InvalidArgument("acosh is undefined for 0.5, which is less than 1".to_owned())));
+        assert_eq!(eval("atanh(-1)"), Err(EvalError::InvalidArgument("atanh is undefined for -1, which is not between -1 and 1".to_owned())));
+        assert_eq!(parse_expr(&mut TokenStream::new("atanh(2)")).unwrap().eval_f32().map(|_| ()),
+                   Err(EvalError::InvalidArgument("atanh is undefined for 2, which is not between -1 and 1".to_owned())));
+        // Keywords only match whole words
+        assert_eq!(parse_expr(&mut TokenStream::new("asinhx")), Ok(Expr::Variable("asinhx".to_owned())));
+    }
+
+    #[test]
+    fn log_base() {
+        let eval = |s| parse_expr(&mut TokenStream::new(s)).unwrap().eval();
+        assert_eq!(eval("logb(2, 8)"), Ok(3.0));
+        assert_eq!(eval("logb(10, 1000)"), Ok(3.0));
+        assert_eq!(eval("logb(e, e^5)"), Ok(5.0));
+        assert_eq!(eval("logb(1, 5)"), Err(EvalError::InvalidArgument("logb is undefined for base 1".to_owned())));
+
+        let parse = |s| parse_expr(&mut TokenStream::new(s));
+        assert_eq!(parse("logb(1, x)").unwrap().eval_with(&HashMap::from([("x".to_owned(), 2.0)])),
+                   Err(EvalError::InvalidArgument("logb is undefined for base 1".to_owned())));
+        assert_eq!(parse("logb(2)"), Err(ParseError::WrongArity { name: "logb".to_owned(), expected: 2, found: 1 }));
+        assert_eq!(parse("logb 2"), Err(ParseError::UnexpectedToken(Token::Number(2.0))));
+        assert_eq!(parse("logb(2, 8"), Err(ParseError::MissingClosingParen));
+    }
+
+    #[test]
+    fn percent_postfix() {
+        let eval = |s| parse_expr(&mut TokenStream::new(s)).unwrap().eval();
+        assert_eq!(eval("50%"), Ok(0.5));
+        assert_eq!(eval("200% * 150"), Ok(300.0));
+        assert_eq!(eval("100% - 20%"), Ok(0.8));
+        assert_eq!(eval("(2 + 3)% * 10"), Ok(0.5));
+        assert_eq!(parse_expr(&mut TokenStream::new("% 3")), Err(ParseError::UnexpectedToken(Token::Percent)));
+    }
+
+    #[test]
+    fn named_constants() {
+        let eval = |s| parse_expr(&mut TokenStream::new(s)).unwrap().eval();
+        assert_eq!(eval("tau"), Ok(2.0 * std::f64::consts::PI));
+        assert_eq!(eval("tau / 2 - pi"), Ok(0.0));
+        assert_eq!(eval("phi^2 - phi"), eval("1"));
+        assert_eq!(eval("inf"), Ok(f64::INFINITY));
+        assert_eq!(eval("-inf * 2"), Ok(f64::NEG_INFINITY));
+    }
+
+    #[test]
+    fn display_round_trip() {
+        for s in ["1 + 2 / 3 - 4 / 5", "sin(3 - -1)", "(1 - 2) - (3 - 4)", "2^3^4", "(2^3)^4", "-x^2", "-(x^2)",
+                  "(sin(x))!", "3!!", "ln(exp(-4 / 5))", "-50%", "(1 + x)%!", "logb(2, x + 1)^2", "-2 + 4 * -(5^3 + 7 * 3!)"] {
+            let e = expr_prec(&mut TokenStream::new(s), 0, &ParseOptions::new(&HashMap::new())).unwrap();
+            let printed = e.to_string();
+            assert_eq!(expr_prec(&mut TokenStream::new(&printed), 0, &ParseOptions::new(&HashMap::new())), Ok(e));
+        }
+
+        let e = expr_prec(&mut TokenStream::new("((1+2))*x^(2)"), 0, &ParseOptions::new(&HashMap::new())).unwrap();
+        assert_eq!(e.to_string(), "(1 + 2) * x^2");
+    }
+
+    #[test]
+    fn parse_errors() {
+        let parse = |s| parse_expr(&mut TokenStream::new(s));
+
+        assert_eq!(parse(""), Ok(Expr::Eof));
+        assert_eq!(parse("1 +"), Err(ParseError::UnexpectedEof));
+        assert_eq!(parse("(1 + 2"), Err(ParseError::MissingClosingParen));
+        assert_eq!(parse("1 + 2)"), Err(ParseError::UnexpectedToken(Token::RParens)));
+        assert_eq!(parse("* 3"), Err(ParseError::UnexpectedToken(Token::Multiply)));
+        assert_eq!(parse("3 @ 4"), Err(ParseError::LexError(LexError { position: 2, character: '@', context: "3 @ 4".to_owned() })));
+        assert_eq!(parse("0x1F + 1"), Err(ParseError::LexError(LexError { position: 0, character: '0', context: "0x1F + 1".to_owned() })));
+        assert_eq!(parse("sin()"), Err(ParseError::UnexpectedToken(Token::RParens)));
+    }
+
+    #[test]
+    fn lex_errors() {
+        let lex_error = |s| match parse_expr(&mut TokenStream::new(s)) {
+            Err(ParseError::LexError(e)) => e,
+            r => panic!("{} should not lex but parsed to {:?}", s, r)
+        };
+
+        let e = lex_error("@");
+        assert_eq!((e.position, e.character, e.context.as_str()), (0, '@', "@"));
+        // Found while peeking for an operator
+        let e = lex_error("sin(x) @ 2");
+        assert_eq!((e.position, e.character, e.context.as_str()), (7, '@', "sin(x) @ 2"));
+        // Positions are byte offsets and the context is cut to a few characters either side
+        let e = lex_error("x + y * (z - 1234567) $ π + 1");
+        assert_eq!((e.position, e.character, e.context.as_str()), (22, '$', "234567) $ π + 1"));
+        let e = lex_error("π");
+        assert_eq!((e.position, e.character), (0, 'π'));
+        assert_eq!(e.to_string(), "Unrecognized character 'π' at position 0 in \"π\"");
+    }
+}
@@ -0,0 +1,200 @@
+use crate::ast::Ast;
+use crate::error::PathError;
+use crate::parse::Expr;
+
+/// One entry in the structural diff of two expression trees
+#[derive(Debug, Clone, PartialEq)]
+pub enum TreeDiff {
+    /// The node at `path` differs, with both sides printed in infix notation
+    Changed { path: Vec<usize>, from: String, to: String },
+    /// The two trees are identical
+    Unchanged,
+}
+
+// Paths index the children of a node in order, so 0 is the left operand of a binary
+// operator or the argument of a unary one, and 1 is the right operand
+
+impl Expr {
+    fn child(&self, index: usize) -> Option<&Expr> {
+        match (self, index) {
+            (Expr::BinaryOp(_, e1, _), 0) => Some(e1),
+            (Expr::BinaryOp(_, _, e2), 1) => Some(e2),
+            (Expr::UnaryOp(_, e), 0) => Some(e),
+            _ => None
+        }
+    }
+
+    fn child_mut(&mut self, index: usize) -> Option<&mut Expr> {
+        match (self, index) {
+            (Expr::BinaryOp(_, e1, _), 0) => Some(e1),
+            (Expr::BinaryOp(_, _, e2), 1) => Some(e2),
+            (Expr::UnaryOp(_, e), 0) => Some(e),
+            _ => None
+        }
+    }
+
+    fn subtree(&self, path: &[usize]) -> Result<&Expr, PathError> {
+        path.iter().enumerate().try_fold(self, |node, (depth, index)| {
+            node.child(*index).ok_or(PathError::IndexOutOfBounds { at_depth: depth })
+        })
+    }
+
+    // Same kind of node with the same operator, so the children can be compared pairwise
+    fn same_node(&self, other: &Expr) -> bool {
+        match (self, other) {
+            (Expr::BinaryOp(op1, _, _), Expr::BinaryOp(op2, _, _)) => op1 == op2,
+            (Expr::UnaryOp(op1, _), Expr::UnaryOp(op2, _)) => op1 == op2,
+            _ => false
+        }
+    }
+
+    fn diff_into(&self, other: &Expr, path: &mut Vec<usize>, diffs: &mut Vec<TreeDiff>) {
+        if self == other {
+            return
+        }
+        if !self.same_node(other) {
+            diffs.push(TreeDiff::Changed { path: path.clone(), from: self.to_string(), to: other.to_string() });
+            return
+        }
+        let mut index = 0;
+        while let (Some(c1), Some(c2)) = (self.child(index), other.child(index)) {
+            path.push(index);
+            c1.diff_into(c2, path, diffs);
+            path.pop();
+            index += 1;
+        }
+    }
+
+    // Preorder search, so a node is found before any equal node inside it
+    fn find_path(&self, target: &Expr, path: &mut Vec<usize>) -> bool {
+        if self == target {
+            return true
+        }
+        let mut index = 0;
+        while let Some(child) = self.child(index) {
+            path.push(index);
+            if child.find_path(target, path) {
+                return true
+            }
+            path.pop();
+            index += 1;
+        }
+        false
+    }
+
+    fn subtree_mut(&mut self, path: &[usize]) -> Result<&mut Expr, PathError> {
+        path.iter().enumerate().try_fold(self, |node, (depth, index)| {
+            node.child_mut(*index).ok_or(PathError::IndexOutOfBounds { at_depth: depth })
+        })
+    }
+}
+
+impl Ast {
+    /// Copy out the subtree found by following `path` of child indices from the root
+    pub fn clone_subtree(&self, path: &[usize]) -> Result<Ast, PathError> {
+        Ok(Ast::new(self.expr.subtree(path)?.clone()))
+    }
+
+    /// Build a copy of the tree with the subtree found by following `path` swapped for `new_expr`
+    pub fn replace_subtree(&self, path: &[usize], new_expr: Ast) -> Result<Ast, PathError> {
+        let mut expr = self.expr.clone();
+        *expr.subtree_mut(path)? = new_expr.expr;
+        Ok(Ast::new(expr))
+    }
+
+    /// Number of steps from the root to the node at `path`, or None if there is no node there
+    pub fn depth_at_node(&self, path: &[usize]) -> Option<usize> {
+        self.expr.subtree(path).ok().map(|_| path.len())
+    }
+
+    /// The path to the first node equal to `target`, searching the tree in preorder
+    pub fn path_to_node(&self, target: &Expr) -> Option<Vec<usize>> {
+        let mut path = Vec::new();
+        self.expr.find_path(target, &mut path).then_some(path)
+    }
+
+    /// Compare two trees node by node at matching paths, reporting the outermost nodes that differ
+    /// Nodes with the same operator are descended into, anything else is reported whole
+    /// Identical trees give a single `TreeDiff::Unchanged`
+    pub fn diff_trees(a: &Ast, b: &Ast) -> Vec<TreeDiff> {
+        let mut diffs = Vec::new();
+        a.expr.diff_into(&b.expr, &mut Vec::new(), &mut diffs);
+        if diffs.is_empty() {
+            diffs.push(TreeDiff::Unchanged);
+        }
+        diffs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clone_subtrees() {
+        let ast = Ast::string_to_ast("1 + 2 * 3");
+        assert_eq!(ast.clone_subtree(&[0]), Ok(Ast::string_to_ast("1")));
+        assert_eq!(ast.clone_subtree(&[1]), Ok(Ast::string_to_ast("2 * 3")));
+        assert_eq!(ast.clone_subtree(&[1, 1]), Ok(Ast::string_to_ast("3")));
+        assert_eq!(ast.clone_subtree(&[]), Ok(ast.clone()));
+        assert_eq!(Ast::string_to_ast("sin(x)").clone_subtree(&[0]), Ok(Ast::string_to_ast("x")));
+    }
+
+    #[test]
+    fn invalid_paths() {
+        let ast = Ast::string_to_ast("1 + sin(2)");
+        assert_eq!(ast.clone_subtree(&[2]), Err(PathError::IndexOutOfBounds { at_depth: 0 }));
+        assert_eq!(ast.clone_subtree(&[1, 1]), Err(PathError::IndexOutOfBounds { at_depth: 1 }));
+        assert_eq!(ast.clone_subtree(&[0, 0]), Err(PathError::IndexOutOfBounds { at_depth: 1 }));
+        assert_eq!(ast.replace_subtree(&[1, 1], Ast::string_to_ast("3")), Err(PathError::IndexOutOfBounds { at_depth: 1 }));
+    }
+
+    #[test]
+    fn depths_and_paths() {
+        let ast = Ast::string_to_ast("(x + 1) * -sin(x + 1)");
+        assert_eq!(ast.depth_at_node(&[]), Some(0));
+        assert_eq!(ast.depth_at_node(&[0, 1]), Some(2));
+        assert_eq!(ast.depth_at_node(&[1, 0, 0]), Some(3));
+        assert_eq!(ast.depth_at_node(&[1, 1]), None);
+        assert_eq!(ast.depth_at_node(&[0, 1, 0]), None);
+
+        let node = |s| Ast::string_to_ast(s).expr;
+        // The left occurrence comes first in preorder
+        assert_eq!(ast.path_to_node(&node("x + 1")), Some(vec![0]));
+        assert_eq!(ast.path_to_node(&node("1")), Some(vec![0, 1]));
+        assert_eq!(ast.path_to_node(&node("sin(x + 1)")), Some(vec![1, 0]));
+        assert_eq!(ast.path_to_node(&ast.expr), Some(vec![]));
+        assert_eq!(ast.path_to_node(&node("x + 2")), None);
+        for path in [vec![0, 0], vec![1, 0, 0]] {
+            let subtree = ast.clone_subtree(&path).unwrap();
+            let found = ast.path_to_node(&subtree.expr).unwrap();
+            assert_eq!(ast.clone_subtree(&found), Ok(subtree));
+        }
+    }
+
+    #[test]
+    fn diff_trees() {
+        let diff = Ast::diff_trees(&Ast::string_to_ast("x + 1"), &Ast::string_to_ast("x + 2"));
+        assert_eq!(diff, vec![TreeDiff::Changed { path: vec![1], from: "1".to_string(), to: "2".to_string() }]);
+
+        let diff = Ast::diff_trees(&Ast::string_to_ast("sin(x) * (y + 1)"), &Ast::string_to_ast("cos(x) * (y - 1)"));
+        assert_eq!(diff, vec![
+            TreeDiff::Changed { path: vec![0], from: "sin(x)".to_string(), to: "cos(x)".to_string() },
+            TreeDiff::Changed { path: vec![1], from: "y + 1".to_string(), to: "y - 1".to_string() },
+        ]);
+
+        let diff = Ast::diff_trees(&Ast::string_to_ast("-(x^2)"), &Ast::string_to_ast("-(x^3)"));
+        assert_eq!(diff, vec![TreeDiff::Changed { path: vec![0, 1], from: "2".to_string(), to: "3".to_string() }]);
+
+        assert_eq!(Ast::diff_trees(&Ast::string_to_ast("x + 1"), &Ast::string_to_ast("x + 1")), vec![TreeDiff::Unchanged]);
+    }
+
+    #[test]
+    fn replace_subtrees() {
+        let ast = Ast::string_to_ast("x + y");
+        let replaced = ast.replace_subtree(&[1], Ast::string_to_ast("z^2")).unwrap();
+        assert_eq!(replaced, Ast::string_to_ast("x + z^2"));
+        assert_eq!(replaced.substitute_many(&[("x", 1.0), ("y", 0.0), ("z", 3.0)]), Ok(10.0));
+        assert_eq!(ast.replace_subtree(&[], Ast::string_to_ast("2")), Ok(Ast::string_to_ast("2")));
+    }
+}
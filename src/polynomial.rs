@@ -0,0 +1,340 @@
+use crate::ast::Ast;
+use crate::error::{EvalError, SolveError};
+use crate::parse::{BinOp, Expr, UnOp};
+
+// Largest exponent written out when reading an expression as a polynomial
+const MAX_POLY_POWER: f64 = 64.0;
+
+// Largest constant or leading coefficient whose divisors factor will search for rational roots
+const MAX_FACTOR_COEFFICIENT: f64 = 1e12;
+
+// Coefficients are stored lowest degree first, without trailing zeros
+fn trim(mut p: Vec<f64>) -> Vec<f64> {
+    while p.last() == Some(&0.0) {
+        p.pop();
+    }
+    p
+}
+
+fn add(p: &[f64], q: &[f64], sign: f64) -> Vec<f64> {
+    let len = p.len().max(q.len());
+    let coeff = |v: &[f64], i: usize| v.get(i).copied().unwrap_or(0.0);
+    trim((0..len).map(|i| coeff(p, i) + sign * coeff(q, i)).collect())
+}
+
+fn mul(p: &[f64], q: &[f64]) -> Vec<f64> {
+    if p.is_empty() || q.is_empty() {
+        return Vec::new()
+    }
+    let mut out = vec![0.0; p.len() + q.len() - 1];
+    for (i, a) in p.iter().enumerate() {
+        for (j, b) in q.iter().enumerate() {
+            out[i + j] += a * b;
+        }
+    }
+    trim(out)
+}
+
+// Evaluate the polynomial at x with Horner's method
+fn eval(p: &[f64], x: f64) -> f64 {
+    p.iter().rev().fold(0.0, |acc, c| acc * x + c)
+}
+
+// Divide by (x - r), returning the quotient and dropping the remainder
+fn deflate(p: &[f64], r: f64) -> Vec<f64> {
+    let mut quotient = vec![0.0; p.len() - 1];
+    let mut carry = 0.0;
+    for i in (1..p.len()).rev() {
+        carry = carry * r + p[i];
+        quotient[i - 1] = carry;
+    }
+    quotient
+}
+
+fn divisors(n: f64) -> Vec<f64> {
+    let n = n.abs() as u64;
+    (1..).take_while(|d: &u64| d.checked_mul(*d).is_some_and(|square| square <= n))
+        .filter(|d| n.is_multiple_of(*d))
+        .flat_map(|d| [d, n / d])
+        .map(|d| d as f64)
+        .collect()
+}
+
+fn gcd(a: f64, b: f64) -> f64 {
+    if b == 0.0 { a } else { gcd(b, a % b) }
+}
+
+// Candidate rational roots p/q of an integer polynomial, as (p, q) pairs
+fn rational_root_candidates(p: &[f64]) -> Vec<(f64, f64)> {
+    let constant = p.iter().copied().find(|c| *c != 0.0).unwrap_or(0.0);
+    let leading = *p.last().expect("Polynomial is nonempty");
+    let mut candidates = Vec::new();
+    for num in divisors(constant) {
+        // Only fractions in lowest terms, so each root is tried as one candidate
+        for den in divisors(leading).into_iter().filter(|den| gcd(num, *den) == 1.0) {
+            candidates.push((num, den));
+            candidates.push((-num, den));
+        }
+    }
+    candidates
+}
+
+// Build c_n * x^n + ... + c_0 from the highest degree term down, skipping zero terms
+fn poly_to_expr(p: &[f64], var: &str) -> Expr {
+    let mut terms = p.iter().enumerate().rev().filter(|(_, c)| **c != 0.0).map(|(k, c)| {
+        let monomial = match k {
+            0 => None,
+            1 => Some(Expr::var(var)),
+            k => Some(Expr::BinaryOp(BinOp::Power, Box::new(Expr::var(var)), Box::new(Expr::const_(k as f64))))
+        };
+        let term = match monomial {
+            None => Expr::const_(c.abs()),
+            Some(m) if c.abs() == 1.0 => m,
+            Some(m) => Expr::BinaryOp(BinOp::Multiply, Box::new(Expr::const_(c.abs())), Box::new(m))
+        };
+        (*c < 0.0, term)
+    });
+
+    let first = match terms.next() {
+        Some((true, term)) => Expr::UnaryOp(UnOp::Negative, Box::new(term)),
+        Some((false, term)) => term,
+        None => return Expr::zero()
+    };
+    terms.fold(first, |acc, (negative, term)| {
+        let op = if negative { BinOp::Minus } else { BinOp::Plus };
+        Expr::BinaryOp(op, Box::new(acc), Box::new(term))
+    })
+}
+
+// The linear factor q * x - p with root p / q
+fn linear_factor(num: f64, den: f64, var: &str) -> Expr {
+    let x = if den == 1.0 {
+        Expr::var(var)
+    } else {
+        Expr::BinaryOp(BinOp::Multiply, Box::new(Expr::const_(den)), Box::new(Expr::var(var)))
+    };
+    if num == 0.0 {
+        x
+    } else if num < 0.0 {
+        Expr::BinaryOp(BinOp::Plus, Box::new(x), Box::new(Expr::const_(-num)))
+    } else {
+        Expr::BinaryOp(BinOp::Minus, Box::new(x), Box::new(Expr::const_(num)))
+    }
+}
+
+impl Expr {
+    /// Read the expression as a polynomial in `var`, returning its coefficients lowest degree first
+    /// Returns None if it uses other variables or is not a polynomial, such as sin(x) or 1 / x
+    pub(crate) fn polynomial_coefficients(&self, var: &str) -> Option<Vec<f64>> {
+        if self.free_variables().is_empty() {
+            return self.eval().ok().map(|c| trim(vec![c]))
+        }
+
+        let coeffs = match self {
+            Expr::BinaryOp(op, e1, e2) => {
+                let (p, q) = (e1.polynomial_coefficients(var)?, e2.polynomial_coefficients(var)?);
+                match op {
+                    BinOp::Plus => add(&p, &q, 1.0),
+                    BinOp::Minus => add(&p, &q, -1.0),
+                    BinOp::Multiply => mul(&p, &q),
+                    BinOp::Divide => match q.as_slice() {
+                        [c] => p.iter().map(|a| a / c).collect(),
+                        _ => return None
+                    },
+                    BinOp::Power => match q.as_slice() {
+                        [] => vec![1.0],
+                        [n] if n.fract() == 0.0 && (1.0..=MAX_POLY_POWER).contains(n) => {
+                            (0..*n as usize).fold(vec![1.0], |acc, _| mul(&acc, &p))
+                        },
+                        _ => return None
+                    },
+                    BinOp::LogBase => return None
+                }
+            },
+            Expr::UnaryOp(UnOp::Negative, e) => e.polynomial_coefficients(var)?.iter().map(|c| -c).collect(),
+            Expr::Variable(name) if name == var => vec![0.0, 1.0],
+            _ => return None
+        };
+
+        Some(coeffs)
+    }
+}
+
+impl Ast {
+    /// Write a polynomial in one variable with integer coefficients as a product of factors,
+    /// pulling out each of its rational roots p / q as a factor q * x - p
+    /// Anything else, including polynomials without rational roots or with a constant or leading
+    /// coefficient above 10^12, is returned unchanged
+    pub fn factor(&self) -> Ast {
+        let vars = self.expr.free_variables();
+        let Some(var) = vars.iter().next().filter(|_| vars.len() == 1) else {
+            return self.clone()
+        };
+        let Some(mut p) = self.expr.polynomial_coefficients(var) else {
+            return self.clone()
+        };
+        if p.len() < 3 || p.iter().any(|c| c.fract() != 0.0) {
+            return self.clone()
+        }
+
+        let mut roots = Vec::new();
+        while p[0] == 0.0 {
+            p.remove(0);
+            roots.push((0.0, 1.0));
+        }
+        // Finding the divisors of larger coefficients takes too long
+        let leading = *p.last().expect("Polynomial has at least three coefficients");
+        if p[0].abs() > MAX_FACTOR_COEFFICIENT || leading.abs() > MAX_FACTOR_COEFFICIENT {
+            return self.clone()
+        }
+        for (num, den) in rational_root_candidates(&p) {
+            // Each root may be repeated, so keep dividing it out while it remains one
+            while p.len() > 1 && eval(&p, num / den).abs() < 1e-9 {
+                // By Gauss's lemma the quotient by den * x - num has integer coefficients
+                p = deflate(&p, num / den).iter().map(|c| (c / den).round()).collect();
+                roots.push((num, den));
+            }
+        }
+        if roots.is_empty() {
+            return self.clone()
+        }
+
+        let mut factors = Vec::new();
+        match p.as_slice() {
+            [c] if *c == 1.0 => {},
+            [c] => factors.push(Expr::const_(*c)),
+            p => factors.push(poly_to_expr(p, var))
+        }
+
+        // Group repeated roots into powers, in increasing order of root
+        roots.sort_by(|a, b| (a.0 / a.1).total_cmp(&(b.0 / b.1)));
+        let mut i = 0;
+        while i < roots.len() {
+            let multiplicity = roots[i..].iter().take_while(|r| **r == roots[i]).count();
+            let (num, den) = roots[i];
+            let factor = linear_factor(num, den, var);
+            factors.push(match multiplicity {
+                1 => factor,
+                m => Expr::BinaryOp(BinOp::Power, Box::new(factor), Box::new(Expr::const_(m as f64)))
+            });
+            i += multiplicity;
+        }
+
+        let expr = factors.into_iter()
+            .reduce(|acc, e| Expr::BinaryOp(BinOp::Multiply, Box::new(acc), Box::new(e)))
+            .expect("At least one root was found");
+        Ast::new(expr)
+    }
+
+    /// Evaluate a polynomial in `var` at `x` with Horner's method, which takes one multiplication
+    /// and one addition per coefficient and agrees with tree evaluation up to rounding
+    /// The coefficients are read from the tree on every call, which costs more than evaluating
+    /// the tree directly, so `horner_benchmark` shows this is slower for repeated evaluation
+    pub fn evaluate_polynomial_horner(&self, var: &str, x: f64) -> Result<f64, EvalError> {
+        let p = self.expr.polynomial_coefficients(var)
+            .ok_or_else(|| EvalError::InvalidArgument(format!("{} is not a polynomial in {}", self.expr, var)))?;
+        Ok(eval(&p, x))
+    }
+
+    /// Find the real roots of a quadratic polynomial in `var` with the quadratic formula,
+    /// smallest first and repeated when there is only one
+    pub fn solve_quadratic(&self, var: &str) -> Result<(f64, f64), SolveError> {
+        let [c, b, a] = self.expr.polynomial_coefficients(var)
+            .and_then(|p| <[f64; 3]>::try_from(p).ok())
+            .ok_or(SolveError::NotQuadratic)?;
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 {
+            return Err(SolveError::ComplexRoots { discriminant })
+        }
+        // Avoid cancellation between -b and the square root by computing the larger root
+        // first and getting the other from their product c / a
+        let q = -(b + b.signum() * discriminant.sqrt()) / 2.0;
+        let (r1, r2) = if q == 0.0 { (0.0, 0.0) } else { (q / a, c / q) };
+        Ok((r1.min(r2), r1.max(r2)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn polynomial_coefficients() {
+        let coeffs = |s| Ast::string_to_ast(s).expr.polynomial_coefficients("x");
+        assert_eq!(coeffs("(x + 1)^2"), Some(vec![1.0, 2.0, 1.0]));
+        assert_eq!(coeffs("3 * x^3 - x / 2 + 2^2"), Some(vec![4.0, -0.5, 0.0, 3.0]));
+        assert_eq!(coeffs("x - x"), Some(vec![]));
+        assert_eq!(coeffs("sin(x)"), None);
+        assert_eq!(coeffs("1 / x"), None);
+        assert_eq!(coeffs("x * y"), None);
+    }
+
+    #[test]
+    fn horner_matches_tree_evaluation() {
+        let ast = Ast::string_to_ast("3 * x^3 - x / 2 + 2^2 - (x + 1)^2");
+        for x in [-2.5, -1.0, 0.0, 0.5, 4.0] {
+            let vars = std::collections::HashMap::from([("x".to_owned(), x)]);
+            let expected = ast.eval_multivar(&vars).unwrap();
+            assert!((ast.evaluate_polynomial_horner("x", x).unwrap() - expected).abs() < 1e-9);
+        }
+        assert_eq!(Ast::string_to_ast("7").evaluate_polynomial_horner("x", 3.0), Ok(7.0));
+        assert!(matches!(Ast::string_to_ast("sin(x)").evaluate_polynomial_horner("x", 1.0), Err(EvalError::InvalidArgument(_))));
+        assert!(Ast::string_to_ast("x * y").evaluate_polynomial_horner("x", 1.0).is_err());
+    }
+
+    // Compare the speed of both methods on a high degree polynomial with cargo test -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn horner_benchmark() {
+        use std::time::Instant;
+        let poly = (0..=30).map(|k| format!("{} * x^{}", k + 1, k)).collect::<Vec<_>>().join(" + ");
+        let ast = Ast::string_to_ast(&poly);
+        let points: Vec<f64> = (0..10_000).map(|i| i as f64 / 10_000.0).collect();
+
+        let start = Instant::now();
+        let tree: Vec<f64> = points.iter()
+            .map(|x| ast.eval_multivar(&std::collections::HashMap::from([("x".to_owned(), *x)])).unwrap())
+            .collect();
+        let tree_time = start.elapsed();
+        let start = Instant::now();
+        let horner: Vec<f64> = points.iter().map(|x| ast.evaluate_polynomial_horner("x", *x).unwrap()).collect();
+        let horner_time = start.elapsed();
+
+        println!("tree: {:?}, horner: {:?}", tree_time, horner_time);
+        for (a, b) in tree.iter().zip(&horner) {
+            assert!((a - b).abs() <= 1e-9 * a.abs().max(1.0));
+        }
+    }
+
+    #[test]
+    fn solve_quadratic() {
+        let solve = |s| Ast::string_to_ast(s).solve_quadratic("x");
+        assert_eq!(solve("x^2 - 5*x + 6"), Ok((2.0, 3.0)));
+        assert_eq!(solve("(x - 1)^2"), Ok((1.0, 1.0)));
+        assert_eq!(solve("2*x^2"), Ok((0.0, 0.0)));
+        assert_eq!(solve("4 - x^2"), Ok((-2.0, 2.0)));
+        let (small, large) = solve("x^2 + 1e8*x + 1").unwrap();
+        assert!((small + 1e8).abs() < 1e-6 && (large + 1e-8).abs() < 1e-20);
+        assert_eq!(solve("x^2 + 1"), Err(SolveError::ComplexRoots { discriminant: -4.0 }));
+        assert_eq!(solve("x^3 - x"), Err(SolveError::NotQuadratic));
+        assert_eq!(solve("2*x + 1"), Err(SolveError::NotQuadratic));
+        assert_eq!(solve("sin(x)^2"), Err(SolveError::NotQuadratic));
+        assert_eq!(Ast::string_to_ast("y^2 - 4").solve_quadratic("x"), Err(SolveError::NotQuadratic));
+    }
+
+    #[test]
+    fn factor_rational_roots() {
+        assert_eq!(Ast::string_to_ast("x^2 - 1").factor(), Ast::string_to_ast("(x + 1) * (x - 1)"));
+        assert_eq!(Ast::string_to_ast("x^3 - x^2").factor(), Ast::string_to_ast("x^2 * (x - 1)"));
+        assert_eq!(Ast::string_to_ast("2*t^2 + t - 1").factor(), Ast::string_to_ast("(t + 1) * (2 * t - 1)"));
+        assert_eq!(Ast::string_to_ast("3*x^2 - 3").factor(), Ast::string_to_ast("3 * (x + 1) * (x - 1)"));
+        assert_eq!(Ast::string_to_ast("(x^2 + 1) * (x - 2)").factor(), Ast::string_to_ast("(x^2 + 1) * (x - 2)"));
+    }
+
+    #[test]
+    fn factor_leaves_others_unchanged() {
+        for s in ["x^2 + 1", "x + 1", "x^2 - 2", "0.5 * x^2 - 2", "sin(x)^2 - 1", "x * y - y", "x^2 - 1e17", "1e19 * x^2 - x"] {
+            assert_eq!(Ast::string_to_ast(s).factor(), Ast::string_to_ast(s));
+        }
+    }
+}
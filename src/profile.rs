@@ -0,0 +1,92 @@
+use std::time::Instant;
+use crate::ast::Ast;
+use crate::error::EvalError;
+use crate::parse::Expr;
+
+/// Timing breakdown of a single evaluation
+/// Each entry pairs a subexpression with the nanoseconds spent evaluating it, including
+/// the time spent on its children, listed in preorder so the root comes first
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvalProfile {
+    pub total_ns: u64,
+    pub nodes: Vec<(String, u64)>
+}
+
+impl Ast {
+    /// Evaluate the expression, timing how long each node takes
+    pub fn profile_eval(&self) -> Result<EvalProfile, EvalError> {
+        let mut times = Vec::new();
+        self.expr.profile_eval(&mut times)?;
+        let mut names = Vec::new();
+        self.expr.preorder_names(&mut names);
+        let nodes: Vec<(String, u64)> = names.into_iter().zip(times).collect();
+        let total_ns = nodes.first().map(|(_, ns)| *ns).unwrap_or(0);
+        Ok(EvalProfile {
+            total_ns,
+            nodes
+        })
+    }
+}
+
+impl Expr {
+    // Nodes are named after evaluating so that printing them is not counted in the times
+    fn profile_eval(&self, times: &mut Vec<u64>) -> Result<f64, EvalError> {
+        let index = times.len();
+        times.push(0);
+
+        let start = Instant::now();
+        let val = match self {
+            Expr::BinaryOp(op, e1, e2) => {
+                let v1 = e1.profile_eval(times)?;
+                let v2 = e2.profile_eval(times)?;
+                op.apply(v1, v2)?
+            },
+            Expr::UnaryOp(op, e) => {
+                op.apply(e.profile_eval(times)?)?
+            },
+            e => e.eval()?
+        };
+        times[index] = start.elapsed().as_nanos() as u64;
+
+        Ok(val)
+    }
+
+    fn preorder_names(&self, names: &mut Vec<String>) {
+        names.push(self.to_string());
+        match self {
+            Expr::BinaryOp(_, e1, e2) => {
+                e1.preorder_names(names);
+                e2.preorder_names(names);
+            },
+            Expr::UnaryOp(_, e) => e.preorder_names(names),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profile_covers_every_node() {
+        let profile = Ast::string_to_ast("sin(1) + 2 * 3").profile_eval().unwrap();
+        let names: Vec<&str> = profile.nodes.iter().map(|(s, _)| s.as_str()).collect();
+        assert_eq!(names, vec!["sin(1) + 2 * 3", "sin(1)", "1", "2 * 3", "2", "3"]);
+        assert_eq!(profile.total_ns, profile.nodes[0].1);
+    }
+
+    #[test]
+    fn profile_times_include_children() {
+        let profile = Ast::string_to_ast("exp(sin(2) * cos(3))").profile_eval().unwrap();
+        for (_, ns) in &profile.nodes[1..] {
+            assert!(*ns <= profile.total_ns);
+        }
+    }
+
+    #[test]
+    fn profile_reports_errors() {
+        let result = Ast::string_to_ast("1 + x").profile_eval();
+        assert_eq!(result, Err(EvalError::UnboundVariable("x".to_owned())));
+    }
+}
@@ -0,0 +1,257 @@
+use std::fmt::{Display, Formatter};
+use crate::ast::Ast;
+use crate::error::EvalError;
+use crate::parse::{BinOp, Expr, UnOp};
+
+/// An exact fraction numer / denom, always kept in lowest terms with a positive denominator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rational {
+    numer: i64,
+    denom: i64
+}
+
+fn gcd(a: i64, b: i64) -> u64 {
+    let (mut a, mut b) = (a.unsigned_abs(), b.unsigned_abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+impl Rational {
+    /// Build the fraction n / d in lowest terms, panicking if d is zero
+    pub fn new(n: i64, d: i64) -> Self {
+        Self::checked_new(n, d).expect("Denominator of a rational must be nonzero")
+    }
+
+    fn checked_new(n: i64, d: i64) -> Option<Self> {
+        // i64::MIN has no positive counterpart, so it could not be negated later on
+        if d == 0 || n == i64::MIN || d == i64::MIN {
+            return None
+        }
+        let g = gcd(n, d).max(1) as i64;
+        let sign = if d < 0 { -1 } else { 1 };
+        Some(Self {
+            numer: (n / g).checked_mul(sign)?,
+            denom: (d / g).checked_mul(sign)?
+        })
+    }
+
+    pub fn numer(&self) -> i64 {
+        self.numer
+    }
+
+    pub fn denom(&self) -> i64 {
+        self.denom
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.numer as f64 / self.denom as f64
+    }
+
+    // Recover the fraction a literal was written as from its shortest decimal representation
+    fn from_f64(n: f64) -> Option<Self> {
+        if !n.is_finite() {
+            return None
+        }
+        let s = format!("{}", n);
+        let (int_part, frac_part) = s.split_once('.').unwrap_or((&s, ""));
+        let denom = 10i64.checked_pow(frac_part.len() as u32)?;
+        let numer = format!("{}{}", int_part, frac_part).parse::<i64>().ok()?;
+        Self::checked_new(numer, denom)
+    }
+
+    fn checked_add(self, rhs: Self) -> Option<Self> {
+        let n = self.numer.checked_mul(rhs.denom)?.checked_add(rhs.numer.checked_mul(self.denom)?)?;
+        Self::checked_new(n, self.denom.checked_mul(rhs.denom)?)
+    }
+
+    fn checked_neg(self) -> Option<Self> {
+        Some(Self {
+            numer: self.numer.checked_neg()?,
+            denom: self.denom
+        })
+    }
+
+    fn checked_mul(self, rhs: Self) -> Option<Self> {
+        Self::checked_new(self.numer.checked_mul(rhs.numer)?, self.denom.checked_mul(rhs.denom)?)
+    }
+
+    fn checked_recip(self) -> Option<Self> {
+        Self::checked_new(self.denom, self.numer)
+    }
+
+    fn checked_pow(self, exp: i64) -> Option<Self> {
+        let base = if exp < 0 { self.checked_recip()? } else { self };
+        let exp = u32::try_from(exp.unsigned_abs()).ok()?;
+        Self::checked_new(base.numer.checked_pow(exp)?, base.denom.checked_pow(exp)?)
+    }
+}
+
+// Largest denominator accepted when approximating a value by a fraction
+const MAX_DENOMINATOR: i64 = 1_000_000;
+
+// The fraction with the smallest denominator in [lo, hi] for 0 < lo <= hi, from the continued
+// fraction expansion: take an integer if there is one in the interval, otherwise take the common
+// integer part and recurse on the reciprocals of what is left
+// Denominators grow at least as fast as the Fibonacci numbers, so deep recursion would overflow anyway
+fn simplest_between(lo: f64, hi: f64, depth: u32) -> Option<(i64, i64)> {
+    if depth > 64 || hi > i64::MAX as f64 {
+        return None
+    }
+    let ceil = lo.ceil();
+    if ceil <= hi {
+        return Some((ceil as i64, 1))
+    }
+    let floor = lo.floor();
+    let (p, q) = simplest_between(1.0 / (hi - floor), 1.0 / (lo - floor), depth + 1)?;
+    Some(((floor as i64).checked_mul(p)?.checked_add(q)?, p))
+}
+
+impl Rational {
+    /// The fraction with the smallest denominator within `tolerance` of x, if it has a
+    /// denominator of at most a million
+    pub fn approximate(x: f64, tolerance: f64) -> Option<Self> {
+        if !x.is_finite() || tolerance.is_nan() || tolerance < 0.0 {
+            return None
+        }
+        let (lo, hi) = (x - tolerance, x + tolerance);
+        let (numer, denom) = if lo <= 0.0 && hi >= 0.0 {
+            (0, 1)
+        } else if hi < 0.0 {
+            let (numer, denom) = simplest_between(-hi, -lo, 0)?;
+            (-numer, denom)
+        } else {
+            simplest_between(lo, hi, 0)?
+        };
+        if denom > MAX_DENOMINATOR {
+            return None
+        }
+        Self::checked_new(numer, denom)
+    }
+}
+
+impl Display for Rational {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.denom == 1 {
+            write!(f, "{}", self.numer)
+        } else {
+            write!(f, "{}/{}", self.numer, self.denom)
+        }
+    }
+}
+
+impl Expr {
+    fn eval_rational(&self) -> Result<Rational, EvalError> {
+        let val = match self {
+            Expr::BinaryOp(op, e1, e2) => {
+                let (v1, v2) = (e1.eval_rational()?, e2.eval_rational()?);
+                match op {
+                    BinOp::Plus => v1.checked_add(v2),
+                    BinOp::Minus => v2.checked_neg().and_then(|v2| v1.checked_add(v2)),
+                    BinOp::Multiply => v1.checked_mul(v2),
+                    BinOp::Divide => {
+                        if v2.numer == 0 {
+                            return Err(EvalError::NotRational)
+                        }
+                        v2.checked_recip().and_then(|v2| v1.checked_mul(v2))
+                    },
+                    BinOp::Power => {
+                        // Only integer powers of rationals are guaranteed to be rational
+                        if v2.denom != 1 || (v1.numer == 0 && v2.numer < 0) {
+                            return Err(EvalError::NotRational)
+                        }
+                        v1.checked_pow(v2.numer)
+                    },
+                    BinOp::LogBase => return Err(EvalError::NotRational)
+                }
+            },
+            Expr::UnaryOp(op, e) => {
+                let val = e.eval_rational()?;
+                match op {
+                    UnOp::Negative => val.checked_neg(),
+                    UnOp::Factorial => {
+                        if val.denom != 1 {
+                            return Err(EvalError::NonIntegerFactorial(val.to_f64()))
+                        }
+                        if val.numer < 0 {
+                            return Err(EvalError::FactorialNegative(val.numer))
+                        }
+                        (1..=val.numer).try_fold(1i64, |acc, k| acc.checked_mul(k)).map(|n| Rational::new(n, 1))
+                    },
+                    UnOp::Percent => val.checked_mul(Rational::new(1, 100)),
+                    _ => return Err(EvalError::NotRational)
+                }
+            },
+            Expr::Number(n) => Rational::from_f64(*n),
+            Expr::Complex(_, _) => return Err(EvalError::NotReal),
+            Expr::Variable(name) => return Err(EvalError::UnboundVariable(name.clone())),
+            Expr::Let(..) => return self.inline_lets().eval_rational(),
+            Expr::Eof => return Err(EvalError::EmptyExpression)
+        };
+
+        val.ok_or(EvalError::Overflow)
+    }
+}
+
+impl Ast {
+    /// Evaluate the expression with exact fractions instead of floating point
+    /// Functions like sin that can leave the rationals return `EvalError::NotRational`
+    pub fn eval_rational(&self) -> Result<Rational, EvalError> {
+        self.expr.eval_rational()
+    }
+    /// Evaluate the expression and find the simplest fraction p / q within `tolerance` of the result,
+    /// as with `Rational::approximate`, so 0.3333333333333333 gives (1, 3)
+    /// Returns None if the expression cannot be evaluated or no fraction is close enough
+    pub fn approximate_as_rational(&self, tolerance: f64) -> Option<(i64, i64)> {
+        let approx = Rational::approximate(self.try_eval().ok()?, tolerance)?;
+        Some((approx.numer, approx.denom))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rational_exact_sums() {
+        assert_eq!(Ast::string_to_ast("1/3 + 1/3 + 1/3").eval_rational(), Ok(Rational::new(1, 1)));
+        assert_eq!(Ast::string_to_ast("(1 + 2) * 4 / 6").eval_rational(), Ok(Rational::new(2, 1)));
+        assert_eq!(Ast::string_to_ast("0.1 + 0.2").eval_rational(), Ok(Rational::new(3, 10)));
+        assert_eq!(Ast::string_to_ast("(2/3)^-2 - 3!").eval_rational(), Ok(Rational::new(-15, 4)));
+    }
+
+    #[test]
+    fn rational_errors() {
+        assert_eq!(Ast::string_to_ast("sin(1)").eval_rational(), Err(EvalError::NotRational));
+        assert_eq!(Ast::string_to_ast("2^(1/2)").eval_rational(), Err(EvalError::NotRational));
+        assert_eq!(Ast::string_to_ast("1/0").eval_rational(), Err(EvalError::NotRational));
+        assert_eq!(Ast::string_to_ast("10^30").eval_rational(), Err(EvalError::Overflow));
+        assert_eq!(Ast::string_to_ast("(-2)^63").eval_rational(), Err(EvalError::Overflow));
+        assert_eq!(Ast::string_to_ast("(-2)!").eval_rational(), Err(EvalError::FactorialNegative(-2)));
+    }
+
+    #[test]
+    fn rational_approximations() {
+        let approx = |s, tolerance| Ast::string_to_ast(s).approximate_as_rational(tolerance);
+        assert_eq!(approx("1/3", 1e-9), Some((1, 3)));
+        assert_eq!(approx("0.1 + 0.2", 1e-12), Some((3, 10)));
+        assert_eq!(approx("-22/14", 1e-9), Some((-11, 7)));
+        assert_eq!(approx("5", 0.0), Some((5, 1)));
+        assert_eq!(approx("0.01", 0.1), Some((0, 1)));
+        assert_eq!(approx("pi", 1e-2), Some((22, 7)));
+        assert_eq!(approx("pi", 1e-6), Some((355, 113)));
+        // Every fraction this close to pi has a denominator over a million
+        assert_eq!(approx("pi", 1e-13), None);
+        assert_eq!(approx("sqrt(2)", 1e-4), Some((99, 70)));
+        assert_eq!(approx("1/0", 1.0), None);
+        assert_eq!(approx("x", 1.0), None);
+        assert_eq!(approx("1/3", -1.0), None);
+    }
+
+    #[test]
+    fn rational_display() {
+        assert_eq!(Rational::new(6, -4).to_string(), "-3/2");
+        assert_eq!(Rational::new(8, 4).to_string(), "2");
+    }
+}
@@ -0,0 +1,267 @@
+use std::collections::HashMap;
+use crate::ast::Ast;
+use crate::parse::{BinOp, Expr, UnOp};
+
+/// A pattern matched against the nodes of an expression tree
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprPattern {
+    /// Matches any subtree, binding it to the given name
+    /// Repeated names must bind structurally equal subtrees
+    Wildcard(String),
+    Number(f64),
+    Complex(f64, f64),
+    Variable(String),
+    BinaryOp(BinOp, Box<ExprPattern>, Box<ExprPattern>),
+    UnaryOp(UnOp, Box<ExprPattern>)
+}
+
+impl ExprPattern {
+    /// Build a pattern from an expression, treating each of its variables as a wildcard
+    pub fn from_expr(expr: &Expr) -> Self {
+        match expr {
+            Expr::BinaryOp(op, e1, e2) => {
+                ExprPattern::BinaryOp(*op, Box::new(Self::from_expr(e1)), Box::new(Self::from_expr(e2)))
+            },
+            Expr::UnaryOp(op, e) => ExprPattern::UnaryOp(*op, Box::new(Self::from_expr(e))),
+            Expr::Number(n) => ExprPattern::Number(*n),
+            Expr::Complex(re, im) => ExprPattern::Complex(*re, *im),
+            Expr::Variable(name) => ExprPattern::Wildcard(name.clone()),
+            Expr::Let(..) => Self::from_expr(&expr.inline_lets()),
+            Expr::Eof => panic!("Cannot build a pattern from an empty expression")
+        }
+    }
+
+    fn matches(&self, expr: &Expr, bindings: &mut HashMap<String, Expr>) -> bool {
+        match (self, expr) {
+            (ExprPattern::Wildcard(name), e) => {
+                match bindings.get(name) {
+                    Some(bound) => bound == e,
+                    None => {
+                        bindings.insert(name.clone(), e.clone());
+                        true
+                    }
+                }
+            },
+            (ExprPattern::Number(m), Expr::Number(n)) => m == n,
+            (ExprPattern::Complex(a, b), Expr::Complex(c, d)) => a == c && b == d,
+            (ExprPattern::Variable(m), Expr::Variable(n)) => m == n,
+            (ExprPattern::BinaryOp(pop, p1, p2), Expr::BinaryOp(op, e1, e2)) => {
+                pop == op && p1.matches(e1, bindings) && p2.matches(e2, bindings)
+            },
+            (ExprPattern::UnaryOp(pop, p), Expr::UnaryOp(op, e)) => {
+                pop == op && p.matches(e, bindings)
+            },
+            _ => false
+        }
+    }
+}
+
+/// Replace any subtree matching `lhs` with `rhs`
+/// Variables in `rhs` named after a wildcard of `lhs` are replaced by the captured subtree
+#[derive(Debug, Clone, PartialEq)]
+pub struct RewriteRule {
+    pub lhs: ExprPattern,
+    pub rhs: Expr
+}
+
+impl RewriteRule {
+    pub fn new(lhs: ExprPattern, rhs: Expr) -> Self {
+        Self {
+            lhs,
+            rhs
+        }
+    }
+
+    /// Build a rule from two expression strings, treating variables on the left as wildcards
+    /// For example `RewriteRule::parse("sin(x)^2 + cos(x)^2", "1")`
+    pub fn parse(lhs: &str, rhs: &str) -> Self {
+        Self::new(ExprPattern::from_expr(&Ast::string_to_ast(lhs).expr), Ast::string_to_ast(rhs).expr)
+    }
+
+    fn apply(&self, expr: &Expr) -> Option<Expr> {
+        let mut bindings = HashMap::new();
+        if self.lhs.matches(expr, &mut bindings) {
+            Some(self.rhs.substitute(&bindings))
+        } else {
+            None
+        }
+    }
+}
+
+// Trigonometric identities, each written so that applying it never sets up another
+// The double angle formula for sine is applied in reverse, collecting the product into sin(2x)
+fn trig_rules() -> Vec<RewriteRule> {
+    vec![
+        RewriteRule::parse("sin(x)^2 + cos(x)^2", "1"),
+        RewriteRule::parse("cos(x)^2 + sin(x)^2", "1"),
+        // Sums are left associative, so a longer sum ending in the identity holds it only as its last two terms
+        RewriteRule::parse("y + sin(x)^2 + cos(x)^2", "y + 1"),
+        RewriteRule::parse("y + cos(x)^2 + sin(x)^2", "y + 1"),
+        RewriteRule::parse("2 * sin(x) * cos(x)", "sin(2 * x)"),
+        RewriteRule::parse("2 * cos(x) * sin(x)", "sin(2 * x)"),
+        RewriteRule::parse("cos(2 * x)", "cos(x)^2 - sin(x)^2"),
+        RewriteRule::parse("sin(-x)", "-sin(x)"),
+        RewriteRule::parse("cos(-x)", "cos(x)"),
+    ]
+}
+
+impl Expr {
+    // Structural equality where each variable of self must correspond to the variable of other
+    // it is mapped to, or to itself if it is not in the mapping
+    fn alpha_equivalent_with(&self, other: &Expr, mapping: &HashMap<String, String>) -> bool {
+        match (self, other) {
+            (Expr::BinaryOp(op1, l1, r1), Expr::BinaryOp(op2, l2, r2)) => {
+                op1 == op2 && l1.alpha_equivalent_with(l2, mapping) && r1.alpha_equivalent_with(r2, mapping)
+            },
+            (Expr::UnaryOp(op1, e1), Expr::UnaryOp(op2, e2)) => op1 == op2 && e1.alpha_equivalent_with(e2, mapping),
+            (Expr::Variable(m), Expr::Variable(n)) => mapping.get(m).unwrap_or(m) == n,
+            (e1, e2) => e1 == e2
+        }
+    }
+
+    // Structural equality that builds the renaming as it goes, recording each pair of names
+    // in both directions so that no two variables of one side share a name on the other
+    fn find_renaming<'a>(&'a self, other: &'a Expr, forward: &mut HashMap<&'a str, &'a str>,
+                         backward: &mut HashMap<&'a str, &'a str>) -> bool {
+        match (self, other) {
+            (Expr::BinaryOp(op1, l1, r1), Expr::BinaryOp(op2, l2, r2)) => {
+                op1 == op2 && l1.find_renaming(l2, forward, backward) && r1.find_renaming(r2, forward, backward)
+            },
+            (Expr::UnaryOp(op1, e1), Expr::UnaryOp(op2, e2)) => op1 == op2 && e1.find_renaming(e2, forward, backward),
+            (Expr::Variable(m), Expr::Variable(n)) => {
+                *forward.entry(m).or_insert(n) == n && *backward.entry(n).or_insert(m) == m
+            },
+            (e1, e2) => e1 == e2
+        }
+    }
+
+    pub(crate) fn rewrite(&self, rules: &[RewriteRule]) -> Expr {
+        let expr = match self {
+            Expr::BinaryOp(op, e1, e2) => {
+                Expr::BinaryOp(*op, Box::new(e1.rewrite(rules)), Box::new(e2.rewrite(rules)))
+            },
+            Expr::UnaryOp(op, e) => Expr::UnaryOp(*op, Box::new(e.rewrite(rules))),
+            e => e.clone()
+        };
+
+        // A successful rewrite may expose new matches, so keep going on the result
+        match rules.iter().find_map(|rule| rule.apply(&expr)) {
+            Some(rewritten) => rewritten.rewrite(rules),
+            None => expr
+        }
+    }
+}
+
+impl Ast {
+    /// Replace every occurrence of the variable `old` with `new`
+    pub fn rename_variable(&self, old: &str, new: &str) -> Ast {
+        let bindings = HashMap::from([(old.to_owned(), Expr::Variable(new.to_owned()))]);
+        Ast::new(self.expr.substitute(&bindings))
+    }
+
+    /// Check whether some one to one renaming of the variables of this expression gives `other`,
+    /// so x^2 + x matches t^2 + t but not x^2 + y
+    pub fn alpha_equivalent(&self, other: &Ast) -> bool {
+        self.expr.find_renaming(&other.expr, &mut HashMap::new(), &mut HashMap::new())
+    }
+
+    /// Check whether renaming the variables of this expression according to `mapping` gives `other`
+    /// Variables missing from the mapping must appear unchanged in `other`
+    pub fn alpha_equivalent_with(&self, other: &Ast, mapping: &HashMap<String, String>) -> bool {
+        self.expr.alpha_equivalent_with(&other.expr, mapping)
+    }
+
+    /// Apply the identities sin(x)^2 + cos(x)^2 = 1, 2 sin(x) cos(x) = sin(2x),
+    /// cos(2x) = cos(x)^2 - sin(x)^2, sin(-x) = -sin(x) and cos(-x) = cos(x) wherever they match
+    pub fn symbolic_simplify_trig(&self) -> Ast {
+        self.rewrite(&trig_rules())
+    }
+
+    /// Apply the rules bottom-up until none of them match
+    /// The rules must not undo each other, or this will never terminate
+    pub fn rewrite(&self, rules: &[RewriteRule]) -> Ast {
+        Ast::new(self.expr.rewrite(rules))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rewrite_pythagorean() {
+        let rules = [RewriteRule::parse("sin(x)^2 + cos(x)^2", "1")];
+        let ast = Ast::string_to_ast("sin(2*y)^2 + cos(2*y)^2");
+        assert_eq!(ast.rewrite(&rules), Ast::string_to_ast("1"));
+
+        // Wildcards must bind the same subtree everywhere
+        let ast = Ast::string_to_ast("sin(y)^2 + cos(z)^2");
+        assert_eq!(ast.rewrite(&rules), ast);
+    }
+
+    #[test]
+    fn simplify_trig() {
+        assert_eq!(Ast::string_to_ast("sin(x)^2 + cos(x)^2").symbolic_simplify_trig().expr, Expr::Number(1.0));
+        for (s, expected) in [
+            ("cos(y + 1)^2 + sin(y + 1)^2", "1"),
+            ("x + sin(t)^2 + cos(t)^2", "x + 1"),
+            ("2 * sin(x) * cos(x)", "sin(2 * x)"),
+            ("2 * cos(x) * sin(x) + 1", "sin(2 * x) + 1"),
+            ("cos(2 * x)", "cos(x)^2 - sin(x)^2"),
+            ("sin(-x) * cos(-x)", "-sin(x) * cos(x)"),
+            ("sin(-(2 * t))", "-sin(2 * t)"),
+            // Bottom up, so the inner identity is used before the outer one is tried
+            ("cos(-(sin(x)^2 + cos(x)^2))", "cos(1)"),
+            ("sin(x)^2 + cos(y)^2", "sin(x)^2 + cos(y)^2"),
+        ] {
+            assert_eq!(Ast::string_to_ast(s).symbolic_simplify_trig(), Ast::string_to_ast(expected), "{}", s);
+        }
+    }
+
+    #[test]
+    fn rewrite_bottom_up() {
+        let rules = [
+            RewriteRule::parse("x * 1", "x"),
+            RewriteRule::parse("x + 0", "x"),
+        ];
+        let ast = Ast::string_to_ast("sin((y + 0) * 1) * 1 + 0");
+        assert_eq!(ast.rewrite(&rules), Ast::string_to_ast("sin(y)"));
+    }
+
+    #[test]
+    fn rewrite_literal_patterns() {
+        let rule = RewriteRule::new(
+            ExprPattern::UnaryOp(UnOp::Exp, Box::new(ExprPattern::Variable("t".to_owned()))),
+            Expr::Number(1.0)
+        );
+        assert_eq!(Ast::string_to_ast("exp(t) + exp(s)").rewrite(&[rule]), Ast::string_to_ast("1 + exp(s)"));
+    }
+
+    #[test]
+    fn rename_variable() {
+        let ast = Ast::string_to_ast("x^2 + x * y");
+        assert_eq!(ast.rename_variable("x", "t"), Ast::string_to_ast("t^2 + t * y"));
+        assert_eq!(ast.rename_variable("z", "t"), ast);
+    }
+
+    #[test]
+    fn alpha_equivalence_with_mapping() {
+        let mapping = HashMap::from([("x".to_owned(), "t".to_owned())]);
+        let ast = Ast::string_to_ast("x^2 + x * y");
+        assert!(ast.alpha_equivalent_with(&Ast::string_to_ast("t^2 + t * y"), &mapping));
+        assert!(!ast.alpha_equivalent_with(&Ast::string_to_ast("t^2 + x * y"), &mapping));
+        assert!(!ast.alpha_equivalent_with(&Ast::string_to_ast("t^2 + t * z"), &mapping));
+        assert!(ast.alpha_equivalent_with(&ast, &HashMap::new()));
+    }
+
+    #[test]
+    fn alpha_equivalence() {
+        let ast = Ast::string_to_ast("x^2 + x");
+        assert!(ast.alpha_equivalent(&Ast::string_to_ast("t^2 + t")));
+        assert!(!ast.alpha_equivalent(&Ast::string_to_ast("x^2 + y")));
+        assert!(!ast.alpha_equivalent(&Ast::string_to_ast("x^3 + x")));
+        assert!(Ast::string_to_ast("x - y").alpha_equivalent(&Ast::string_to_ast("y - x")));
+        assert!(!Ast::string_to_ast("x - y").alpha_equivalent(&Ast::string_to_ast("z - z")));
+        assert!(!Ast::string_to_ast("z - z").alpha_equivalent(&Ast::string_to_ast("x - y")));
+    }
+}
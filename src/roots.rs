@@ -0,0 +1,186 @@
+use crate::ast::Ast;
+use crate::error::SolveError;
+
+impl Ast {
+    // Evaluate at both ends of [a, b], checking that the function changes sign between them
+    fn bracket(&self, a: f64, b: f64) -> Result<(f64, f64), SolveError> {
+        let (fa, fb) = (self.eval_point(a)?, self.eval_point(b)?);
+        if fa.is_nan() || fb.is_nan() || ((fa < 0.0) == (fb < 0.0) && fa != 0.0 && fb != 0.0) {
+            return Err(SolveError::NoSignChange)
+        }
+        Ok((fa, fb))
+    }
+
+    // Bisection returning the root along with the number of iterations it took
+    fn bisection(&self, mut a: f64, mut b: f64, tol: f64, max_iter: usize) -> Result<(f64, usize), SolveError> {
+        let (mut fa, fb) = self.bracket(a, b)?;
+        if fa == 0.0 {
+            return Ok((a, 0))
+        }
+        if fb == 0.0 {
+            return Ok((b, 0))
+        }
+        for iter in 1..=max_iter {
+            let mid = (a + b) / 2.0;
+            let fm = self.eval_point(mid)?;
+            if fm == 0.0 || (b - a).abs() / 2.0 < tol {
+                return Ok((mid, iter))
+            }
+            if (fm < 0.0) == (fa < 0.0) {
+                a = mid;
+                fa = fm;
+            } else {
+                b = mid;
+            }
+        }
+        Err(SolveError::NoConvergence { iterations: max_iter })
+    }
+
+    // Illinois variant of regula falsi returning the root along with the number of iterations it took
+    // Plain regula falsi can keep moving the same end of the interval, so whenever one end is kept
+    // twice in a row its value is halved, pulling the next secant step towards it
+    fn illinois(&self, mut a: f64, mut b: f64, tol: f64, max_iter: usize) -> Result<(f64, usize), SolveError> {
+        let (mut fa, mut fb) = self.bracket(a, b)?;
+        if fa == 0.0 {
+            return Ok((a, 0))
+        }
+        if fb == 0.0 {
+            return Ok((b, 0))
+        }
+        // Which end was kept last time, -1 for a and 1 for b
+        let mut kept = 0;
+        let mut prev = a;
+        for iter in 1..=max_iter {
+            let c = (a * fb - b * fa) / (fb - fa);
+            let fc = self.eval_point(c)?;
+            if fc == 0.0 || (c - prev).abs() < tol {
+                return Ok((c, iter))
+            }
+            prev = c;
+            if (fc < 0.0) == (fb < 0.0) {
+                b = c;
+                fb = fc;
+                if kept == -1 {
+                    fa /= 2.0;
+                }
+                kept = -1;
+            } else {
+                a = c;
+                fa = fc;
+                if kept == 1 {
+                    fb /= 2.0;
+                }
+                kept = 1;
+            }
+        }
+        Err(SolveError::NoConvergence { iterations: max_iter })
+    }
+
+    // Newton's method returning the root along with the number of iterations it took
+    fn newton(&self, x0: f64, tol: f64, max_iter: usize) -> Result<(f64, usize), SolveError> {
+        let vars = self.expr.free_variables();
+        let var = vars.iter().next().map_or("x", |v| v.as_str());
+        // Only use the symbolic derivative if it depends on nothing but the one variable
+        let derivative = self.expr.diff(var)
+            .filter(|d| d.free_variables().iter().all(|v| v == var))
+            .map(Ast::new);
+
+        let mut x = x0;
+        for iter in 1..=max_iter {
+            let fx = self.eval_point(x)?;
+            if fx == 0.0 {
+                return Ok((x, iter))
+            }
+            let dfx = match &derivative {
+                Some(d) => d.eval_point(x)?,
+                None => self.derivative_at(x)?
+            };
+            if dfx == 0.0 {
+                return Err(SolveError::ZeroDerivative { x })
+            }
+            let step = fx / dfx;
+            x -= step;
+            if step.abs() < tol {
+                return Ok((x, iter))
+            }
+        }
+        Err(SolveError::NoConvergence { iterations: max_iter })
+    }
+
+    /// Find a root of a function of at most one variable near x0 with Newton's method, stopping
+    /// once a step is smaller than tol
+    /// The derivative is taken symbolically where possible, falling back to a numerical estimate
+    /// for operations like factorial that have no derivative rule
+    pub fn newton_raphson(&self, x0: f64, tol: f64, max_iter: usize) -> Result<f64, SolveError> {
+        self.newton(x0, tol, max_iter).map(|(root, _)| root)
+    }
+
+    /// Find a root of a function of at most one variable on [a, b] by repeatedly halving the
+    /// interval, keeping the half where the function changes sign, until it is narrower than 2 * tol
+    /// The function must have opposite signs at a and b
+    pub fn bisect_interval(&self, a: f64, b: f64, tol: f64, max_iter: usize) -> Result<f64, SolveError> {
+        self.bisection(a, b, tol, max_iter).map(|(root, _)| root)
+    }
+
+    /// Find a root of a function of at most one variable on [a, b] with the Illinois variant of
+    /// regula falsi, which needs no derivative and usually converges much faster than bisection
+    /// It stops once successive estimates are within tol, and the function must have opposite
+    /// signs at a and b
+    pub fn illinois_method(&self, a: f64, b: f64, tol: f64, max_iter: usize) -> Result<f64, SolveError> {
+        self.illinois(a, b, tol, max_iter).map(|(root, _)| root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::EvalError;
+
+    #[test]
+    fn illinois_converges_faster_than_bisection() {
+        let f = Ast::string_to_ast("x^5 - x - 1");
+        let (bisect_root, bisect_iters) = f.bisection(1.0, 2.0, 1e-12, 100).unwrap();
+        let (illinois_root, illinois_iters) = f.illinois(1.0, 2.0, 1e-12, 100).unwrap();
+        for root in [bisect_root, illinois_root] {
+            assert!((root - 1.1673039782614187).abs() < 1e-11);
+        }
+        assert!(illinois_iters * 3 < bisect_iters, "{} vs {}", illinois_iters, bisect_iters);
+        assert_eq!(f.bisect_interval(1.0, 2.0, 1e-12, 100), Ok(bisect_root));
+        assert_eq!(f.illinois_method(1.0, 2.0, 1e-12, 100), Ok(illinois_root));
+    }
+
+    #[test]
+    fn newton_raphson_converges_quickly() {
+        let (root, iters) = Ast::string_to_ast("x^2 - 2").newton(1.5, 1e-10, 50).unwrap();
+        assert!((root - std::f64::consts::SQRT_2).abs() < 1e-15);
+        assert!(iters <= 5);
+        assert_eq!(Ast::string_to_ast("x^2 - 2").newton_raphson(1.5, 1e-10, 50), Ok(root));
+
+        let root = Ast::string_to_ast("cos(t) - t").newton_raphson(1.0, 1e-12, 50).unwrap();
+        assert!((root.cos() - root).abs() < 1e-12);
+        // Factorial has no derivative rule, so the numerical derivative is tried instead, though it
+        // needs factorials of non-integers
+        assert_eq!(Ast::string_to_ast("x! - 6").newton_raphson(3.0, 1e-9, 50), Ok(3.0));
+        assert!(matches!(
+            Ast::string_to_ast("x! - 5").newton_raphson(3.0, 1e-9, 50),
+            Err(SolveError::Eval(EvalError::NonIntegerFactorial(_)))
+        ));
+
+        assert_eq!(Ast::string_to_ast("x^2 + 1").newton_raphson(0.0, 1e-9, 50), Err(SolveError::ZeroDerivative { x: 0.0 }));
+        assert_eq!(Ast::string_to_ast("x^2 + 1").newton_raphson(0.5, 1e-9, 10), Err(SolveError::NoConvergence { iterations: 10 }));
+    }
+
+    #[test]
+    fn root_finding_errors() {
+        let f = Ast::string_to_ast("x^2 + 1");
+        assert_eq!(f.bisect_interval(-1.0, 1.0, 1e-9, 100), Err(SolveError::NoSignChange));
+        assert_eq!(f.illinois_method(-1.0, 1.0, 1e-9, 100), Err(SolveError::NoSignChange));
+
+        let f = Ast::string_to_ast("x - 0.3");
+        assert_eq!(f.bisect_interval(0.0, 1.0, 1e-12, 5), Err(SolveError::NoConvergence { iterations: 5 }));
+        assert_eq!(f.illinois_method(0.3, 1.0, 1e-12, 5), Ok(0.3));
+
+        let f = Ast::string_to_ast("x * y");
+        assert_eq!(f.illinois_method(0.0, 1.0, 1e-9, 10), Err(SolveError::Eval(EvalError::WrongArity { expected: 1, found: 2 })));
+    }
+}
@@ -0,0 +1,600 @@
+use std::fs;
+use std::io::Write;
+use std::iter::Peekable;
+use std::path::Path;
+use std::str::Chars;
+use crate::ast::Ast;
+use crate::config::AstConfig;
+use crate::error::{CalcError, ParseError};
+use crate::lex::{Token, TokenStream};
+use crate::parse::{BinOp, Expr, UnOp};
+
+/// The formats an AST can be written out in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Infix,
+    Json,
+    Postfix,
+    Prefix,
+    SExpression
+}
+
+impl Ast {
+    /// Print the expression in the usual infix notation, e.g. `1 + 2 * 3`
+    /// Let bindings are inlined, since the parser cannot read them back
+    pub fn to_infix_string(&self) -> String {
+        self.expr.inline_lets().to_string()
+    }
+
+    /// Print the expression in reverse Polish notation, e.g. `1 2 3 * +`
+    /// Negation is written as `neg` to tell it apart from subtraction
+    pub fn to_postfix_string(&self) -> String {
+        let mut tokens = Vec::new();
+        self.expr.postfix_tokens(&mut tokens);
+        tokens.join(" ")
+    }
+
+    /// Print the expression in Polish notation, e.g. `+ 1 * 2 3`
+    /// Negation is written as `neg` to tell it apart from subtraction
+    pub fn to_prefix_string(&self) -> String {
+        let mut tokens = Vec::new();
+        self.expr.prefix_tokens(&mut tokens);
+        tokens.join(" ")
+    }
+
+    /// Serialize the tree as nested JSON objects, one per node
+    pub fn to_json(&self) -> String {
+        self.expr.to_json()
+    }
+
+    /// Parse the nested JSON objects written by `to_json`
+    /// Numbers may also be the bare words inf, -inf and NaN, which is how `to_json` writes them
+    /// Objects may be nested at most as deep as the default `AstConfig::max_depth`
+    pub fn from_json(s: &str) -> Result<Ast, ParseError> {
+        let mut reader = JsonReader {
+            chars: s.chars().peekable(),
+            depth: 0,
+            max_depth: AstConfig::default().max_depth
+        };
+        let json = reader.value()?;
+        reader.skip_whitespace();
+        if let Some(c) = reader.chars.next() {
+            return Err(ParseError::InvalidJson(format!("unexpected {:?} after the end of the tree", c)))
+        }
+        Ok(Ast::new(Expr::from_json(&json)?))
+    }
+
+    /// Read a tree written by `to_json_file`
+    pub fn from_json_file(path: &Path) -> Result<Ast, CalcError> {
+        let contents = fs::read_to_string(path).map_err(|e| ParseError::Io(e.to_string()))?;
+        Ok(Self::from_json(&contents)?)
+    }
+
+    /// Write the tree to the file at `path` as JSON, replacing anything already there
+    pub fn to_json_file(&self, path: &Path) -> Result<(), CalcError> {
+        fs::write(path, self.to_json()).map_err(|e| CalcError::Io(e.to_string()))
+    }
+
+    /// Print the expression as a Lisp style S-expression, e.g. `(+ 1 (* 2 3))`
+    /// Operators and functions are written as in infix, so negation is `(- x)` and the
+    /// natural log is `(ln x)`, while complex constants other than `i` are `(complex re im)`
+    pub fn to_s_expression(&self) -> String {
+        self.expr.to_s_expression()
+    }
+
+    /// Parse an S-expression in the form written by `to_s_expression`
+    pub fn from_s_expression(s: &str) -> Result<Ast, ParseError> {
+        let mut lexer = TokenStream::new(s);
+        if lexer.peek().is_none() {
+            return Ok(Ast::new(Expr::Eof))
+        }
+        let expr = parse_s_expression(&mut lexer)?;
+        match lexer.next() {
+            None => Ok(Ast::new(expr)),
+            Some(Ok(t)) => Err(ParseError::UnexpectedToken(t)),
+            Some(Err(())) => Err(lexer.error())
+        }
+    }
+
+    pub fn to_writer(&self, mut w: impl Write, format: OutputFormat) -> std::io::Result<()> {
+        let s = match format {
+            OutputFormat::Infix => self.to_infix_string(),
+            OutputFormat::Json => self.to_json(),
+            OutputFormat::Postfix => self.to_postfix_string(),
+            OutputFormat::Prefix => self.to_prefix_string(),
+            OutputFormat::SExpression => self.to_s_expression()
+        };
+        w.write_all(s.as_bytes())
+    }
+}
+
+// The subset of JSON that trees are written in, objects of strings and numbers
+enum Json {
+    Number(f64),
+    String(String),
+    Object(Vec<(String, Json)>)
+}
+
+// Nesting is limited so that deep input fails rather than overflowing the stack
+struct JsonReader<'a> {
+    chars: Peekable<Chars<'a>>,
+    depth: usize,
+    max_depth: usize
+}
+
+impl JsonReader<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ParseError> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(ParseError::InvalidJson(format!("expected {:?} but found {:?}", expected, c))),
+            None => Err(ParseError::InvalidJson(format!("expected {:?} but the input ended", expected)))
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, ParseError> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some('{') => {
+                self.depth += 1;
+                if self.depth > self.max_depth {
+                    return Err(ParseError::TooDeep { max_depth: self.max_depth })
+                }
+                let object = self.object();
+                self.depth -= 1;
+                object
+            },
+            Some('"') => Ok(Json::String(self.string()?)),
+            Some(_) => self.number(),
+            None => Err(ParseError::InvalidJson("expected a value but the input ended".to_owned()))
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, ParseError> {
+        self.expect('{')?;
+        let mut fields = Vec::new();
+        self.skip_whitespace();
+        if self.chars.next_if_eq(&'}').is_some() {
+            return Ok(Json::Object(fields))
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.expect(':')?;
+            fields.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some(',') => continue,
+                Some('}') => return Ok(Json::Object(fields)),
+                Some(c) => return Err(ParseError::InvalidJson(format!("expected ',' or '}}' but found {:?}", c))),
+                None => return Err(ParseError::InvalidJson("unclosed object".to_owned()))
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, ParseError> {
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(s),
+                Some('\\') => {
+                    let escaped = match self.chars.next() {
+                        Some(c @ ('"' | '\\' | '/')) => c,
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('u') => {
+                            let hex: String = (0..4).filter_map(|_| self.chars.next()).collect();
+                            u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32)
+                                .ok_or_else(|| ParseError::InvalidJson(format!("invalid escape \\u{}", hex)))?
+                        },
+                        c => return Err(ParseError::InvalidJson(format!("invalid escape {:?}", c)))
+                    };
+                    s.push(escaped);
+                },
+                Some(c) => s.push(c),
+                None => return Err(ParseError::InvalidJson("unclosed string".to_owned()))
+            }
+        }
+    }
+
+    // Letters are read too so that inf and NaN come through
+    fn number(&mut self) -> Result<Json, ParseError> {
+        let mut s = String::new();
+        while let Some(c) = self.chars.next_if(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.')) {
+            s.push(c);
+        }
+        s.parse::<f64>()
+            .map(Json::Number)
+            .map_err(|_| ParseError::InvalidJson(format!("expected a value but found {:?}", s)))
+    }
+}
+
+impl Json {
+    fn field(&self, key: &str) -> Result<&Json, ParseError> {
+        let missing = || ParseError::InvalidJson(format!("missing field {:?}", key));
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v).ok_or_else(missing),
+            _ => Err(ParseError::InvalidJson(format!("expected an object with field {:?}", key)))
+        }
+    }
+
+    fn number_field(&self, key: &str) -> Result<f64, ParseError> {
+        match self.field(key)? {
+            Json::Number(n) => Ok(*n),
+            _ => Err(ParseError::InvalidJson(format!("field {:?} should be a number", key)))
+        }
+    }
+
+    fn string_field(&self, key: &str) -> Result<&str, ParseError> {
+        match self.field(key)? {
+            Json::String(s) => Ok(s),
+            _ => Err(ParseError::InvalidJson(format!("field {:?} should be a string", key)))
+        }
+    }
+}
+
+// The kind of a node read from JSON, with its children still to be converted
+// Kept apart from the recursion in `Expr::from_json` so that each level uses little stack
+enum JsonNode {
+    Binary(BinOp),
+    Unary(UnOp),
+    Let(String),
+    Leaf(Expr)
+}
+
+impl JsonNode {
+    fn read(json: &Json) -> Result<JsonNode, ParseError> {
+        let node = match json.string_field("type")? {
+            "binary" => {
+                let op = json.string_field("op")?;
+                let op = BinOp::ALL.into_iter().chain([BinOp::LogBase]).find(|o| o.to_string() == op)
+                    .ok_or_else(|| ParseError::InvalidJson(format!("unknown binary operator {:?}", op)))?;
+                JsonNode::Binary(op)
+            },
+            "unary" => {
+                let op = json.string_field("op")?;
+                let op = UnOp::ALL.into_iter().find(|o| o.to_string() == op)
+                    .ok_or_else(|| ParseError::InvalidJson(format!("unknown unary operator {:?}", op)))?;
+                JsonNode::Unary(op)
+            },
+            "number" => JsonNode::Leaf(Expr::Number(json.number_field("value")?)),
+            "complex" => JsonNode::Leaf(Expr::Complex(json.number_field("re")?, json.number_field("im")?)),
+            "variable" => JsonNode::Leaf(Expr::Variable(json.string_field("name")?.to_owned())),
+            "let" => JsonNode::Let(json.string_field("name")?.to_owned()),
+            "empty" => JsonNode::Leaf(Expr::Eof),
+            t => return Err(ParseError::InvalidJson(format!("unknown node type {:?}", t)))
+        };
+        Ok(node)
+    }
+}
+
+impl Expr {
+    fn from_json(json: &Json) -> Result<Expr, ParseError> {
+        let child = |key| Ok::<_, ParseError>(Box::new(Expr::from_json(json.field(key)?)?));
+        let expr = match JsonNode::read(json)? {
+            JsonNode::Binary(op) => Expr::BinaryOp(op, child("lhs")?, child("rhs")?),
+            JsonNode::Unary(op) => Expr::UnaryOp(op, child("arg")?),
+            JsonNode::Let(name) => Expr::Let(name, child("value")?, child("body")?),
+            JsonNode::Leaf(expr) => expr
+        };
+        Ok(expr)
+    }
+
+    fn postfix_tokens(&self, tokens: &mut Vec<String>) {
+        match self {
+            Expr::BinaryOp(op, e1, e2) => {
+                e1.postfix_tokens(tokens);
+                e2.postfix_tokens(tokens);
+                tokens.push(op.to_string());
+            },
+            Expr::UnaryOp(op, e) => {
+                e.postfix_tokens(tokens);
+                match op {
+                    UnOp::Negative => tokens.push("neg".to_owned()),
+                    _ => tokens.push(op.to_string())
+                }
+            },
+            Expr::Number(n) => tokens.push(n.to_string()),
+            Expr::Complex(_, _) => tokens.push(format!("({})", self)),
+            Expr::Variable(name) => tokens.push(name.clone()),
+            Expr::Let(..) => self.inline_lets().postfix_tokens(tokens),
+            Expr::Eof => {}
+        }
+    }
+
+    fn prefix_tokens(&self, tokens: &mut Vec<String>) {
+        match self {
+            Expr::BinaryOp(op, e1, e2) => {
+                tokens.push(op.to_string());
+                e1.prefix_tokens(tokens);
+                e2.prefix_tokens(tokens);
+            },
+            Expr::UnaryOp(op, e) => {
+                match op {
+                    UnOp::Negative => tokens.push("neg".to_owned()),
+                    _ => tokens.push(op.to_string())
+                }
+                e.prefix_tokens(tokens);
+            },
+            Expr::Number(n) => tokens.push(n.to_string()),
+            Expr::Complex(_, _) => tokens.push(format!("({})", self)),
+            Expr::Variable(name) => tokens.push(name.clone()),
+            Expr::Let(..) => self.inline_lets().prefix_tokens(tokens),
+            Expr::Eof => {}
+        }
+    }
+
+    fn to_s_expression(&self) -> String {
+        let symbol = |token: Token| token.symbol().expect("Operators have a symbol");
+        match self {
+            Expr::BinaryOp(op, e1, e2) => {
+                format!("({} {} {})", symbol(Token::from(*op)), e1.to_s_expression(), e2.to_s_expression())
+            },
+            Expr::UnaryOp(op, e) => format!("({} {})", symbol(Token::from(*op)), e.to_s_expression()),
+            Expr::Number(n) => n.to_string(),
+            Expr::Complex(re, im) if *re == 0.0 && *im == 1.0 => "i".to_owned(),
+            Expr::Complex(re, im) => format!("(complex {} {})", re, im),
+            Expr::Variable(name) => name.clone(),
+            Expr::Let(..) => self.inline_lets().to_s_expression(),
+            Expr::Eof => String::new()
+        }
+    }
+
+    fn to_json(&self) -> String {
+        match self {
+            Expr::BinaryOp(op, e1, e2) => {
+                format!(r#"{{"type":"binary","op":"{}","lhs":{},"rhs":{}}}"#, op, e1.to_json(), e2.to_json())
+            },
+            Expr::UnaryOp(op, e) => format!(r#"{{"type":"unary","op":"{}","arg":{}}}"#, op, e.to_json()),
+            Expr::Number(n) => format!(r#"{{"type":"number","value":{}}}"#, n),
+            Expr::Complex(re, im) => format!(r#"{{"type":"complex","re":{},"im":{}}}"#, re, im),
+            Expr::Variable(name) => format!(r#"{{"type":"variable","name":"{}"}}"#, name),
+            Expr::Let(name, value, body) => {
+                format!(r#"{{"type":"let","name":"{}","value":{},"body":{}}}"#, name, value.to_json(), body.to_json())
+            },
+            Expr::Eof => r#"{"type":"empty"}"#.to_owned()
+        }
+    }
+}
+
+// Number of arguments taken by the operator or function at the head of a list
+// Minus is left out since it takes one argument for negation or two for subtraction
+fn s_expression_arity(head: &Token) -> Option<usize> {
+    match head {
+        Token::Plus | Token::Multiply | Token::Divide | Token::Power | Token::LogBase => Some(2),
+        Token::Sin | Token::Cos | Token::Tan | Token::Exp | Token::Log | Token::Sqrt
+            | Token::Asinh | Token::Acosh | Token::Atanh | Token::Factorial | Token::Percent => Some(1),
+        Token::Identifier(name) if name == "complex" => Some(2),
+        _ => None
+    }
+}
+
+// Recursive descent over a single atom or parenthesized list
+fn parse_s_expression(lexer: &mut TokenStream) -> Result<Expr, ParseError> {
+    let token = match lexer.next() {
+        Some(Ok(t)) => t,
+        Some(Err(())) => return Err(lexer.error()),
+        None => return Err(ParseError::UnexpectedEof)
+    };
+    let expr = match token {
+        Token::Number(n) => Expr::Number(n),
+        // A minus outside the head of a list can only be the sign of a negative number
+        Token::Minus => match lexer.next() {
+            Some(Ok(Token::Number(n))) => Expr::Number(-n),
+            Some(Ok(t)) => return Err(ParseError::UnexpectedToken(t)),
+            Some(Err(())) => return Err(lexer.error()),
+            None => return Err(ParseError::UnexpectedEof)
+        },
+        Token::Pi => Expr::Number(std::f64::consts::PI),
+        Token::Euler => Expr::Number(std::f64::consts::E),
+        Token::Tau => Expr::Number(std::f64::consts::TAU),
+        Token::Phi => Expr::Number((1.0 + 5.0_f64.sqrt()) / 2.0),
+        Token::Inf => Expr::Number(f64::INFINITY),
+        Token::Imaginary => Expr::Complex(0.0, 1.0),
+        Token::Identifier(name) => Expr::Variable(name),
+        Token::LParens => {
+            let head = match lexer.next() {
+                Some(Ok(Token::RParens)) => return Err(ParseError::UnexpectedToken(Token::RParens)),
+                Some(Ok(t)) => t,
+                Some(Err(())) => return Err(lexer.error()),
+                None => return Err(ParseError::MissingClosingParen)
+            };
+            let mut args = Vec::new();
+            loop {
+                match lexer.peek() {
+                    Some(Ok(Token::RParens)) => {
+                        lexer.next();
+                        break
+                    },
+                    None => return Err(ParseError::MissingClosingParen),
+                    _ => args.push(parse_s_expression(lexer)?)
+                }
+            }
+            s_expression_list(head, args)?
+        },
+        t => return Err(ParseError::UnexpectedToken(t))
+    };
+    Ok(expr)
+}
+
+// Build the node for a list from its head and already parsed arguments
+fn s_expression_list(head: Token, mut args: Vec<Expr>) -> Result<Expr, ParseError> {
+    let expected = match (&head, args.len()) {
+        (Token::Minus, 1 | 2) => args.len(),
+        (Token::Minus, _) => 2,
+        (t, _) => s_expression_arity(t).ok_or_else(|| ParseError::UnexpectedToken(head.clone()))?
+    };
+    if args.len() != expected {
+        let name = match &head {
+            Token::Identifier(name) => name.clone(),
+            t => t.symbol().unwrap_or_default().to_owned()
+        };
+        return Err(ParseError::WrongArity { name, expected, found: args.len() })
+    }
+
+    let expr = match (head, args.len()) {
+        (Token::Identifier(_), _) => match (&args[0], &args[1]) {
+            (Expr::Number(re), Expr::Number(im)) => Expr::Complex(*re, *im),
+            _ => return Err(ParseError::UnexpectedToken(Token::Identifier("complex".to_owned())))
+        },
+        (Token::Minus, 1) => Expr::UnaryOp(UnOp::Negative, Box::new(args.remove(0))),
+        (t, 1) => Expr::UnaryOp(UnOp::from(t), Box::new(args.remove(0))),
+        (t, _) => {
+            let rhs = args.pop().expect("Binary lists have two arguments");
+            let lhs = args.pop().expect("Binary lists have two arguments");
+            Expr::BinaryOp(BinOp::from(t), Box::new(lhs), Box::new(rhs))
+        }
+    };
+    Ok(expr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_to_string(ast: &Ast, format: OutputFormat) -> String {
+        let mut buf: Vec<u8> = Vec::new();
+        ast.to_writer(&mut buf, format).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn write_infix() {
+        let ast = Ast::string_to_ast("1+2*(3-x)");
+        assert_eq!(ast.to_infix_string(), "1 + 2 * (3 - x)");
+        assert_eq!(write_to_string(&ast, OutputFormat::Infix), ast.to_infix_string());
+        let shared = Ast::string_to_ast("(x + 1)^2 + (x + 1)^2").eliminate_common_subexpressions();
+        assert_eq!(shared.to_infix_string(), "(x + 1)^2 + (x + 1)^2");
+    }
+
+    #[test]
+    fn write_postfix() {
+        let ast = Ast::string_to_ast("-sin(1 + 2 * 3)!");
+        assert_eq!(ast.to_postfix_string(), "1 2 3 * + ! sin neg");
+        assert_eq!(write_to_string(&ast, OutputFormat::Postfix), ast.to_postfix_string());
+    }
+
+    #[test]
+    fn write_prefix() {
+        let ast = Ast::string_to_ast("-sin(1 + 2 * 3)!");
+        assert_eq!(ast.to_prefix_string(), "neg sin ! + 1 * 2 3");
+        assert_eq!(Ast::string_to_ast("(a - b) / c^2").to_prefix_string(), "/ - a b ^ c 2");
+        assert_eq!(write_to_string(&ast, OutputFormat::Prefix), ast.to_prefix_string());
+    }
+
+    #[test]
+    fn write_s_expression() {
+        assert_eq!(Ast::string_to_ast("1 + 2*3").to_s_expression(), "(+ 1 (* 2 3))");
+        assert_eq!(Ast::string_to_ast("-x^2 - ln(y)!").to_s_expression(), "(- (^ (- x) 2) (ln (! y)))");
+        assert_eq!(Ast::string_to_ast("logb(2, 50%)").to_s_expression(), "(logb 2 (% 50))");
+        assert_eq!(Ast::new(Expr::Complex(1.0, -2.0)).to_s_expression(), "(complex 1 -2)");
+        assert_eq!(write_to_string(&Ast::string_to_ast("i * x"), OutputFormat::SExpression), "(* i x)");
+    }
+
+    #[test]
+    fn s_expression_round_trip() {
+        for s in ["sin(4) + exp(3 - 1)^3", "-2 + 4 * -(5^3 + 7 * 3!)", "sin(3.14159) + cos(3.14159) + exp(0)^2 - ln(1)/2",
+                  "tan(-4--4) / ln(4)", "ln(exp(-4/5))", "", "i"] {
+            let ast = Ast::string_to_ast(s);
+            assert_eq!(Ast::from_s_expression(&ast.to_s_expression()), Ok(ast));
+        }
+        for expr in [Expr::Number(-2.5), Expr::Complex(1.0, -2.0), Expr::Number(1e-7)] {
+            let ast = Ast::new(expr);
+            assert_eq!(Ast::from_s_expression(&ast.to_s_expression()), Ok(ast));
+        }
+        assert_eq!(Ast::from_s_expression("(*  pi\n (sqrt x))"), Ok(Ast::string_to_ast("pi * sqrt(x)")));
+    }
+
+    #[test]
+    fn s_expression_errors() {
+        assert_eq!(Ast::from_s_expression("(+ 1 2"), Err(ParseError::MissingClosingParen));
+        assert_eq!(Ast::from_s_expression("(+ 1 2))"), Err(ParseError::UnexpectedToken(Token::RParens)));
+        assert_eq!(Ast::from_s_expression("(sin 1 2)"), Err(ParseError::WrongArity { name: "sin".to_owned(), expected: 1, found: 2 }));
+        assert_eq!(Ast::from_s_expression("(- 1 2 3)"), Err(ParseError::WrongArity { name: "-".to_owned(), expected: 2, found: 3 }));
+        assert_eq!(Ast::from_s_expression("()"), Err(ParseError::UnexpectedToken(Token::RParens)));
+        assert_eq!(Ast::from_s_expression("(f x)"), Err(ParseError::UnexpectedToken(Token::Identifier("f".to_owned()))));
+        assert_eq!(Ast::from_s_expression("+"), Err(ParseError::UnexpectedToken(Token::Plus)));
+    }
+
+    #[test]
+    fn write_json() {
+        let ast = Ast::string_to_ast("-x + 2.5");
+        let expected = concat!(
+            r#"{"type":"binary","op":"+","lhs":"#,
+            r#"{"type":"unary","op":"-","arg":{"type":"variable","name":"x"}},"#,
+            r#""rhs":{"type":"number","value":2.5}}"#
+        );
+        assert_eq!(ast.to_json(), expected);
+        assert_eq!(write_to_string(&ast, OutputFormat::Json), ast.to_json());
+    }
+
+    #[test]
+    fn json_round_trip() {
+        for s in ["-x + 2.5", "logb(2, y)! * sqrt(3%)", "atanh(ln(x) / 1e-7) ^ -tau", "i", ""] {
+            let ast = Ast::string_to_ast(s);
+            assert_eq!(Ast::from_json(&ast.to_json()), Ok(ast));
+        }
+        for expr in [Expr::Number(f64::INFINITY), Expr::Number(-f64::INFINITY), Expr::Complex(1.0, -2.0),
+                     Ast::string_to_ast("sin(x) * sin(x)").eliminate_common_subexpressions().expr] {
+            let ast = Ast::new(expr);
+            assert_eq!(Ast::from_json(&ast.to_json()), Ok(ast));
+        }
+        let spaced = "{ \"type\" : \"unary\",\n  \"arg\": {\"name\": \"\\u0078\", \"type\": \"variable\"}, \"op\": \"sin\" }";
+        assert_eq!(Ast::from_json(spaced), Ok(Ast::string_to_ast("sin(x)")));
+    }
+
+    #[test]
+    fn json_errors() {
+        let error = |s| match Ast::from_json(s) {
+            Err(ParseError::InvalidJson(e)) => e,
+            r => panic!("{} should not parse but gave {:?}", s, r)
+        };
+        assert_eq!(error(r#"{"type":"number"}"#), r#"missing field "value""#);
+        assert_eq!(error(r#"{"type":"unary","op":"cosh","arg":{"type":"empty"}}"#), r#"unknown unary operator "cosh""#);
+        assert_eq!(error(r#"{"type":"number","value":"1"}"#), r#"field "value" should be a number"#);
+        assert_eq!(error(r#"{"type":"tree"}"#), r#"unknown node type "tree""#);
+        assert_eq!(error(r#"{"type":"empty"} {}"#), "unexpected '{' after the end of the tree");
+        assert_eq!(error(r#"{"type":"empty""#), "unclosed object");
+        assert_eq!(error("[1]"), r#"expected a value but found """#);
+    }
+
+    #[test]
+    fn json_too_deep() {
+        let nested = |depth| {
+            let mut json = r#"{"type":"variable","name":"x"}"#.to_owned();
+            for _ in 1..depth {
+                json = format!(r#"{{"type":"unary","op":"-","arg":{}}}"#, json);
+            }
+            json
+        };
+        assert!(Ast::from_json(&nested(512)).is_ok());
+        assert_eq!(Ast::from_json(&nested(513)), Err(ParseError::TooDeep { max_depth: 512 }));
+        // Far deeper input is rejected without overflowing the stack
+        assert_eq!(Ast::from_json(&"{\"arg\":".repeat(200_000)), Err(ParseError::TooDeep { max_depth: 512 }));
+    }
+
+    #[test]
+    fn json_files() {
+        let path = std::env::temp_dir().join(format!("ast-calc-tree-{}.json", std::process::id()));
+        let ast = Ast::string_to_ast("x^2 + sin(y) / 3");
+        ast.to_json_file(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), ast.to_json());
+        let loaded = Ast::from_json_file(&path);
+        fs::write(&path, "{").unwrap();
+        let truncated = Ast::from_json_file(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, Ok(ast.clone()));
+        assert!(matches!(truncated, Err(CalcError::Parse(ParseError::InvalidJson(_)))));
+
+        assert!(matches!(Ast::from_json_file(&path), Err(CalcError::Parse(ParseError::Io(_)))));
+        assert!(matches!(ast.to_json_file(&path.join("nested")), Err(CalcError::Io(_))));
+    }
+}
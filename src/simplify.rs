@@ -0,0 +1,521 @@
+use crate::ast::Ast;
+use crate::parse::{BinOp, Expr, UnOp};
+
+// Largest power of a sum that expand will write out with the binomial theorem
+const MAX_BINOMIAL_POWER: u32 = 10;
+
+impl Ast {
+    /// Put the operands of every commutative operator in a canonical order
+    /// Chains like c * a * b are flattened, sorted by their printed form and rebuilt, so
+    /// that expressions differing only in the order of such operands become structurally equal
+    pub fn sort_commutative(&self) -> Ast {
+        Ast::new(self.expr.sort_commutative())
+    }
+
+    /// Distribute multiplication over addition and subtraction, so a * (b + c) becomes a * b + a * c
+    /// Integer powers of sums up to the 10th are also written out with the binomial theorem
+    pub fn expand(&self) -> Ast {
+        Ast::new(self.expr.expand())
+    }
+
+    /// Rewrite the expression into a canonical form so that equivalent expressions are likelier to be
+    /// structurally equal. Subtraction becomes addition of a negation, division becomes multiplication
+    /// by a -1 power, double negations are removed, constants are folded and commutative operands sorted
+    pub fn normalize(&self) -> Ast {
+        let mut expr = self.expr.clone();
+        loop {
+            let next = expr.desugar().fold_constants().sort_commutative();
+            if next == expr {
+                return Ast::new(expr)
+            }
+            expr = next;
+        }
+    }
+
+    /// Whether the expression folds to the constant 0, without any symbolic reasoning,
+    /// so x - x is not recognised as zero
+    pub fn is_zero(&self) -> bool {
+        self.expr.fold_constants() == Expr::Number(0.0)
+    }
+
+    /// Whether the expression folds to the constant 1, without any symbolic reasoning
+    pub fn is_one(&self) -> bool {
+        self.expr.fold_constants() == Expr::Number(1.0)
+    }
+
+    /// Remove operations that leave their operand unchanged, like x + 0, x * 1, x / 1 and x^1,
+    /// and collapse x * 0 to 0, x^0 to 1 and --x to x
+    pub fn simplify(&self) -> Ast {
+        Ast::new(self.expr.simplify_identities())
+    }
+
+    /// Set the variable `var` to `value` and fold constants in the same bottom-up pass, so every
+    /// operator whose operands have all become numbers is replaced by its value
+    /// Operations that fail or give a non-finite value are left in place, as with folding
+    pub fn substitute_and_fold(&self, var: &str, value: f64) -> Ast {
+        Ast::new(self.expr.substitute_and_fold(var, value))
+    }
+
+    /// Print a simplified form of the expression meant for reading rather than parsing back exactly
+    /// The expression is normalized, like terms such as 2 * x + 4 * x are collected into 6 * x,
+    /// identities are removed and constants folded, then subtraction and division are restored
+    pub fn to_symbolic_string(&self) -> String {
+        let mut expr = self.normalize().expr;
+        loop {
+            let next = expr.simplify_identities().fold_constants().collect_like_terms();
+            if next == expr {
+                return expr.resugar().to_string()
+            }
+            expr = next;
+        }
+    }
+}
+
+// Product of two terms, dropping factors of one and folding numbers together
+fn product(lhs: Expr, rhs: Expr) -> Expr {
+    match (lhs, rhs) {
+        (Expr::Number(a), Expr::Number(b)) => Expr::Number(a * b),
+        (Expr::Number(a), e) | (e, Expr::Number(a)) if a == 1.0 => e,
+        (lhs, rhs) => Expr::BinaryOp(BinOp::Multiply, Box::new(lhs), Box::new(rhs))
+    }
+}
+
+// Integer power of a term, dropping exponents of zero and one
+fn power(base: Expr, n: u32) -> Expr {
+    match (base, n) {
+        (_, 0) => Expr::one(),
+        (base, 1) => base,
+        (Expr::Number(a), n) => Expr::Number(a.powi(n as i32)),
+        (base, n) => Expr::BinaryOp(BinOp::Power, Box::new(base), Box::new(Expr::Number(n as f64)))
+    }
+}
+
+// Rebuild a chain of operands joined by op, or None if there are none
+fn chain(op: BinOp, operands: impl IntoIterator<Item = Expr>) -> Option<Expr> {
+    operands.into_iter().reduce(|acc, e| Expr::BinaryOp(op, Box::new(acc), Box::new(e)))
+}
+
+// Split a term of a sum into its numeric coefficient and the rest of the term,
+// which is None for a term that is just a number
+fn split_coefficient(term: &Expr) -> (f64, Option<Expr>) {
+    match term {
+        Expr::Number(n) => (*n, None),
+        Expr::UnaryOp(UnOp::Negative, e) => {
+            let (c, rest) = split_coefficient(e);
+            (-c, rest)
+        },
+        Expr::BinaryOp(BinOp::Multiply, _, _) => {
+            let (numbers, rest): (Vec<&Expr>, Vec<&Expr>) = term.flatten_op(BinOp::Multiply).into_iter().partition(|e| e.is_number());
+            let c = numbers.iter().filter_map(|e| e.as_number()).product();
+            (c, chain(BinOp::Multiply, rest.into_iter().cloned()))
+        },
+        e => (1.0, Some(e.clone()))
+    }
+}
+
+// The term c * rest, written without a coefficient of 1 or -1
+fn scaled(c: f64, rest: Option<Expr>) -> Expr {
+    match rest {
+        None => Expr::Number(c),
+        Some(rest) if c == 1.0 => rest,
+        Some(rest) if c == -1.0 => Expr::UnaryOp(UnOp::Negative, Box::new(rest)),
+        Some(rest) => {
+            let factors = rest.flatten_op(BinOp::Multiply).into_iter().cloned();
+            chain(BinOp::Multiply, std::iter::once(Expr::Number(c)).chain(factors)).expect("There is at least the coefficient")
+        }
+    }
+}
+
+// The positive form of a term that reads as negative, so that a + -b can be written a - b
+fn negated_term(term: &Expr) -> Option<Expr> {
+    match term {
+        Expr::UnaryOp(UnOp::Negative, e) => Some(e.as_ref().clone()),
+        Expr::Number(n) if *n < 0.0 => Some(Expr::Number(-n)),
+        Expr::BinaryOp(BinOp::Multiply, _, _) => {
+            let (c, rest) = split_coefficient(term);
+            (c < 0.0).then(|| scaled(-c, rest))
+        },
+        // A quotient reads as negative when its numerator does, as in -2 / x
+        Expr::BinaryOp(BinOp::Divide, n, d) => {
+            negated_term(n).map(|n| Expr::BinaryOp(BinOp::Divide, Box::new(n), d.clone()))
+        },
+        _ => None
+    }
+}
+
+fn binomial(n: u32, k: u32) -> f64 {
+    (0..k).fold(1.0, |acc, i| acc * (n - i) as f64 / (i + 1) as f64)
+}
+
+// Multiply out lhs * rhs where both sides are already expanded
+fn distribute(lhs: Expr, rhs: Expr) -> Expr {
+    match (lhs, rhs) {
+        (Expr::BinaryOp(op @ (BinOp::Plus | BinOp::Minus), a, b), rhs) => {
+            Expr::BinaryOp(op, Box::new(distribute(*a, rhs.clone())), Box::new(distribute(*b, rhs)))
+        },
+        (lhs, Expr::BinaryOp(op @ (BinOp::Plus | BinOp::Minus), a, b)) => {
+            Expr::BinaryOp(op, Box::new(distribute(lhs.clone(), *a)), Box::new(distribute(lhs, *b)))
+        },
+        (lhs, rhs) => product(lhs, rhs)
+    }
+}
+
+impl Expr {
+    pub(crate) fn expand(&self) -> Expr {
+        match self {
+            Expr::BinaryOp(BinOp::Multiply, e1, e2) => distribute(e1.expand(), e2.expand()),
+            Expr::BinaryOp(BinOp::Power, base, exp) => {
+                let base = base.expand();
+                match (base, exp.expand()) {
+                    (Expr::BinaryOp(op @ (BinOp::Plus | BinOp::Minus), a, b), Expr::Number(n))
+                        if n.fract() == 0.0 && (2.0..=MAX_BINOMIAL_POWER as f64).contains(&n) => {
+                        let n = n as u32;
+                        (1..=n)
+                            .map(|k| {
+                                let term = product(Expr::Number(binomial(n, k)), power(*a.clone(), n - k));
+                                let sign = if op == BinOp::Minus && k % 2 == 1 { BinOp::Minus } else { BinOp::Plus };
+                                (sign, distribute(term, power(*b.clone(), k)).expand())
+                            })
+                            .fold(power(*a.clone(), n).expand(), |acc, (sign, term)| {
+                                Expr::BinaryOp(sign, Box::new(acc), Box::new(term))
+                            })
+                    },
+                    (base, exp) => Expr::BinaryOp(BinOp::Power, Box::new(base), Box::new(exp))
+                }
+            },
+            Expr::BinaryOp(op, e1, e2) => Expr::BinaryOp(*op, Box::new(e1.expand()), Box::new(e2.expand())),
+            Expr::UnaryOp(op, e) => Expr::UnaryOp(*op, Box::new(e.expand())),
+            e => e.clone()
+        }
+    }
+
+    // Replace subtraction, division and double negation with the operators normalize keeps
+    fn desugar(&self) -> Expr {
+        match self {
+            Expr::BinaryOp(BinOp::Minus, e1, e2) => Expr::BinaryOp(
+                BinOp::Plus,
+                Box::new(e1.desugar()),
+                Box::new(Expr::UnaryOp(UnOp::Negative, Box::new(e2.desugar())))
+            ),
+            Expr::BinaryOp(BinOp::Divide, e1, e2) => Expr::BinaryOp(
+                BinOp::Multiply,
+                Box::new(e1.desugar()),
+                Box::new(Expr::BinaryOp(BinOp::Power, Box::new(e2.desugar()), Box::new(Expr::const_(-1.0))))
+            ),
+            Expr::UnaryOp(UnOp::Negative, e) => match e.as_ref() {
+                Expr::UnaryOp(UnOp::Negative, inner) => inner.desugar(),
+                e => Expr::UnaryOp(UnOp::Negative, Box::new(e.desugar()))
+            },
+            Expr::BinaryOp(op, e1, e2) => Expr::BinaryOp(*op, Box::new(e1.desugar()), Box::new(e2.desugar())),
+            Expr::UnaryOp(op, e) => Expr::UnaryOp(*op, Box::new(e.desugar())),
+            e => e.clone()
+        }
+    }
+
+    /// Evaluate every subtree without variables down to a number, merging the numeric
+    /// operands of commutative chains like 2 * x * 3 too and dropping ones that are the identity
+    /// Subtrees that fail to evaluate or give a non-finite result are left in place
+    pub(crate) fn fold_constants(&self) -> Expr {
+        if !matches!(self, Expr::Number(_) | Expr::Eof) && self.free_variables().is_empty() {
+            if let Ok(val) = self.eval() {
+                if val.is_finite() {
+                    return Expr::Number(val)
+                }
+            }
+        }
+
+        match self {
+            Expr::BinaryOp(op, _, _) if op.is_commutative() => {
+                let (numbers, mut rest): (Vec<Expr>, Vec<Expr>) = self.flatten_op(*op)
+                    .into_iter()
+                    .map(|e| e.fold_constants())
+                    .partition(|e| e.is_number());
+                // Numbers that do not combine to a finite value stay as a chain, which is already folded
+                let values: Vec<f64> = numbers.iter().filter_map(Expr::as_number).collect();
+                let folded = values.split_first().and_then(|(first, others)| {
+                    others.iter().try_fold(*first, |acc, val| op.apply(acc, *val)).ok().filter(|val| val.is_finite())
+                });
+                let folded = folded.map(Expr::Number).or_else(|| chain(*op, numbers));
+                match folded {
+                    // Drop an identity like + 0 unless it is all that is left
+                    Some(Expr::Number(n)) if Some(n) == op.identity() && !rest.is_empty() => {},
+                    Some(folded) => rest.insert(0, folded),
+                    None => {}
+                }
+                rest.into_iter()
+                    .reduce(|acc, e| Expr::BinaryOp(*op, Box::new(acc), Box::new(e)))
+                    .expect("Binary op has at least two operands")
+            },
+            Expr::BinaryOp(op, e1, e2) => Expr::BinaryOp(*op, Box::new(e1.fold_constants()), Box::new(e2.fold_constants())),
+            Expr::UnaryOp(op, e) => Expr::UnaryOp(*op, Box::new(e.fold_constants())),
+            e => e.clone()
+        }
+    }
+
+    fn substitute_and_fold(&self, var: &str, value: f64) -> Expr {
+        match self {
+            Expr::BinaryOp(op, e1, e2) => {
+                let (e1, e2) = (e1.substitute_and_fold(var, value), e2.substitute_and_fold(var, value));
+                if let (Expr::Number(a), Expr::Number(b)) = (&e1, &e2) {
+                    if let Some(val) = op.apply(*a, *b).ok().filter(|val| val.is_finite()) {
+                        return Expr::Number(val)
+                    }
+                }
+                Expr::BinaryOp(*op, Box::new(e1), Box::new(e2))
+            },
+            Expr::UnaryOp(op, e) => {
+                let e = e.substitute_and_fold(var, value);
+                if let Expr::Number(a) = e {
+                    if let Some(val) = op.apply(a).ok().filter(|val| val.is_finite()) {
+                        return Expr::Number(val)
+                    }
+                }
+                Expr::UnaryOp(*op, Box::new(e))
+            },
+            Expr::Variable(name) if name == var => Expr::Number(value),
+            Expr::Let(..) => self.inline_lets().substitute_and_fold(var, value),
+            e => e.clone()
+        }
+    }
+
+    // Simplify bottom-up so that removing one identity can expose another above it
+    pub(crate) fn simplify_identities(&self) -> Expr {
+        match self {
+            Expr::BinaryOp(op, e1, e2) => {
+                match (op, e1.simplify_identities(), e2.simplify_identities()) {
+                    (BinOp::Plus, Expr::Number(0.0), e) | (BinOp::Plus | BinOp::Minus, e, Expr::Number(0.0)) => e,
+                    (BinOp::Minus, Expr::Number(0.0), e) => Expr::UnaryOp(UnOp::Negative, Box::new(e)),
+                    (BinOp::Multiply, Expr::Number(0.0), _) | (BinOp::Multiply, _, Expr::Number(0.0)) => Expr::zero(),
+                    (BinOp::Multiply, Expr::Number(1.0), e) | (BinOp::Multiply | BinOp::Divide | BinOp::Power, e, Expr::Number(1.0)) => e,
+                    (BinOp::Power, _, Expr::Number(0.0)) => Expr::one(),
+                    (op, e1, e2) => Expr::BinaryOp(*op, Box::new(e1), Box::new(e2))
+                }
+            },
+            Expr::UnaryOp(op, e) => match (op, e.simplify_identities()) {
+                (UnOp::Negative, Expr::UnaryOp(UnOp::Negative, inner)) => *inner,
+                (op, e) => Expr::UnaryOp(*op, Box::new(e))
+            },
+            e => e.clone()
+        }
+    }
+
+    // Add up the coefficients of terms in a sum that differ only by a numeric factor,
+    // keeping terms in the order they first appear with the constant last
+    fn collect_like_terms(&self) -> Expr {
+        match self {
+            Expr::BinaryOp(BinOp::Plus, _, _) => {
+                let mut groups: Vec<(Option<Expr>, f64)> = Vec::new();
+                for term in self.flatten_op(BinOp::Plus) {
+                    let (c, rest) = split_coefficient(&term.collect_like_terms());
+                    match groups.iter_mut().find(|(r, _)| *r == rest) {
+                        Some((_, total)) => *total += c,
+                        None => groups.push((rest, c))
+                    }
+                }
+                groups.sort_by_key(|(rest, _)| rest.is_none());
+                let terms = groups.into_iter().filter(|(_, c)| *c != 0.0).map(|(rest, c)| scaled(c, rest));
+                chain(BinOp::Plus, terms).unwrap_or_else(Expr::zero)
+            },
+            Expr::BinaryOp(op, e1, e2) => Expr::BinaryOp(*op, Box::new(e1.collect_like_terms()), Box::new(e2.collect_like_terms())),
+            Expr::UnaryOp(op, e) => Expr::UnaryOp(*op, Box::new(e.collect_like_terms())),
+            e => e.clone()
+        }
+    }
+
+    // Undo desugar where it reads better, writing a + -b as a - b and a * b^-1 as a / b
+    fn resugar(&self) -> Expr {
+        match self {
+            Expr::BinaryOp(BinOp::Plus, _, _) => {
+                let mut terms: Vec<Expr> = self.flatten_op(BinOp::Plus).into_iter().map(|e| e.resugar()).collect();
+                // Lead with a positive term where there is one, so -b + a reads a - b
+                if let Some(i) = terms.iter().position(|e| negated_term(e).is_none()) {
+                    let first = terms.remove(i);
+                    terms.insert(0, first);
+                }
+                let mut terms = terms.into_iter();
+                let first = terms.next().expect("Binary op has at least two operands");
+                terms.fold(first, |acc, e| match negated_term(&e) {
+                    Some(e) => Expr::BinaryOp(BinOp::Minus, Box::new(acc), Box::new(e)),
+                    None => Expr::BinaryOp(BinOp::Plus, Box::new(acc), Box::new(e))
+                })
+            },
+            Expr::BinaryOp(BinOp::Multiply, _, _) | Expr::BinaryOp(BinOp::Power, _, _) => {
+                let mut numerator = Vec::new();
+                let mut denominator = Vec::new();
+                for factor in self.flatten_op(BinOp::Multiply) {
+                    match factor {
+                        Expr::BinaryOp(BinOp::Power, base, exponent) if exponent.as_number().is_some_and(|n| n < 0.0) => {
+                            let n = -exponent.as_number().expect("Checked to be a number");
+                            let base = base.resugar();
+                            denominator.push(if n == 1.0 { base } else { Expr::BinaryOp(BinOp::Power, Box::new(base), Box::new(Expr::Number(n))) });
+                        },
+                        Expr::BinaryOp(BinOp::Power, base, exponent) => {
+                            numerator.push(Expr::BinaryOp(BinOp::Power, Box::new(base.resugar()), Box::new(exponent.resugar())));
+                        },
+                        e => numerator.push(e.resugar())
+                    }
+                }
+                let numerator = chain(BinOp::Multiply, numerator).unwrap_or_else(Expr::one);
+                match chain(BinOp::Multiply, denominator) {
+                    Some(denominator) => Expr::BinaryOp(BinOp::Divide, Box::new(numerator), Box::new(denominator)),
+                    None => numerator
+                }
+            },
+            Expr::BinaryOp(op, e1, e2) => Expr::BinaryOp(*op, Box::new(e1.resugar()), Box::new(e2.resugar())),
+            Expr::UnaryOp(op, e) => Expr::UnaryOp(*op, Box::new(e.resugar())),
+            e => e.clone()
+        }
+    }
+
+    pub(crate) fn sort_commutative(&self) -> Expr {
+        match self {
+            Expr::BinaryOp(op, _, _) if op.is_commutative() => {
+                let mut terms: Vec<Expr> = self.flatten_op(*op).into_iter().map(|e| e.sort_commutative()).collect();
+                terms.sort_by_cached_key(|e| e.to_string());
+                terms.into_iter()
+                    .reduce(|acc, e| Expr::BinaryOp(*op, Box::new(acc), Box::new(e)))
+                    .expect("Binary op has at least two operands")
+            },
+            Expr::BinaryOp(op, e1, e2) => {
+                Expr::BinaryOp(*op, Box::new(e1.sort_commutative()), Box::new(e2.sort_commutative()))
+            },
+            Expr::UnaryOp(op, e) => Expr::UnaryOp(*op, Box::new(e.sort_commutative())),
+            e => e.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sort_sums() {
+        let sorted = Ast::string_to_ast("b + a").sort_commutative();
+        assert_eq!(sorted, Ast::string_to_ast("a + b"));
+    }
+
+    #[test]
+    fn sort_products() {
+        let sorted = Ast::string_to_ast("c * a * b").sort_commutative();
+        assert_eq!(sorted, Ast::string_to_ast("a * b * c"));
+        assert_eq!(Ast::string_to_ast("b * (c * a)").sort_commutative(), sorted);
+    }
+
+    #[test]
+    fn sort_nested() {
+        let sorted = Ast::string_to_ast("sin(y + x) - (z * 2)").sort_commutative();
+        assert_eq!(sorted, Ast::string_to_ast("sin(x + y) - 2 * z"));
+        // Non-commutative operators keep their order
+        assert_eq!(Ast::string_to_ast("b - a").sort_commutative(), Ast::string_to_ast("b - a"));
+    }
+
+    #[test]
+    fn expand_products() {
+        assert_eq!(Ast::string_to_ast("2 * (x + y)").expand(), Ast::string_to_ast("2 * x + 2 * y"));
+        assert_eq!(Ast::string_to_ast("(a - b) * c").expand(), Ast::string_to_ast("a * c - b * c"));
+        assert_eq!(Ast::string_to_ast("(a + b) * (c + d)").expand(), Ast::string_to_ast("a * c + a * d + (b * c + b * d)"));
+        assert_eq!(Ast::string_to_ast("sin(x * (y + 1))").expand(), Ast::string_to_ast("sin(x * y + x)"));
+    }
+
+    #[test]
+    fn expand_binomials() {
+        assert_eq!(Ast::string_to_ast("(x + 1)^2").expand(), Ast::string_to_ast("x^2 + 2 * x + 1"));
+        assert_eq!(Ast::string_to_ast("(x - y)^3").expand(), Ast::string_to_ast("x^3 - 3 * x^2 * y + 3 * x * y^2 - y^3"));
+        // Large or fractional powers are left alone
+        assert_eq!(Ast::string_to_ast("(x + 1)^0.5").expand(), Ast::string_to_ast("(x + 1)^0.5"));
+        assert_eq!(Ast::string_to_ast("(x + 1)^20").expand(), Ast::string_to_ast("(x + 1)^20"));
+    }
+
+    #[test]
+    fn expand_preserves_value() {
+        let ast = Ast::string_to_ast("(x + 2)^4 * (x - 3) - 2 * (x + 1)^3");
+        let expanded = ast.expand();
+        for x in [-2.0, -0.5, 0.0, 1.5, 3.0] {
+            let vars = std::collections::HashMap::from([("x".to_owned(), x)]);
+            assert!((ast.eval_multivar(&vars).unwrap() - expanded.eval_multivar(&vars).unwrap()).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn normalize_equivalent_forms() {
+        let normalize = |s| Ast::string_to_ast(s).normalize();
+        assert_eq!(normalize("a - b"), normalize("a + (-b)"));
+        assert_eq!(normalize("x * 2"), normalize("2 * x"));
+        assert_eq!(normalize("x / y"), normalize("y^-1 * x"));
+        assert_eq!(normalize("--x + 0"), normalize("x"));
+        assert_eq!(normalize("2 * x * 3 - 1 + 4"), normalize("3 + 6 * x"));
+    }
+
+    #[test]
+    fn simplify_identities() {
+        let simplify = |s| Ast::string_to_ast(s).simplify();
+        assert_eq!(simplify("x + 0 - 0"), Ast::string_to_ast("x"));
+        assert_eq!(simplify("1 * sin(x / 1)^1"), Ast::string_to_ast("sin(x)"));
+        assert_eq!(simplify("(y + 0) * 0 + x^0"), Ast::string_to_ast("1"));
+        assert_eq!(simplify("0 - --x"), Ast::string_to_ast("-x"));
+        assert_eq!(simplify("2 * x + 1"), Ast::string_to_ast("2 * x + 1"));
+    }
+
+    #[test]
+    fn symbolic_strings() {
+        let symbolic = |s| Ast::string_to_ast(s).to_symbolic_string();
+        assert_eq!(symbolic("x * 1 + 0 + 0 * y"), "x");
+        assert_eq!(symbolic("2 * x + 4 * x"), "6 * x");
+        assert_eq!(symbolic("x + x - 3 * x"), "-x");
+        assert_eq!(symbolic("3 + y * 2 - 1 + y"), "3 * y + 2");
+        assert_eq!(symbolic("a - b"), "a - b");
+        assert_eq!(symbolic("x - 2 * y + x"), "2 * x - 2 * y");
+        assert_eq!(symbolic("x / y * 2"), "2 * x / y");
+        assert_eq!(symbolic("sin(x)^2 * 1 + 1 / x^2"), "1 / x^2 + sin(x)^2");
+        assert_eq!(symbolic("x - x"), "0");
+        assert_eq!(symbolic("x - 2 / x"), "x - 2 / x");
+        assert_eq!(symbolic("y - 3 * x / y^2"), "y - 3 * x / y^2");
+        // The plain infix form keeps everything as written
+        assert_eq!(Ast::string_to_ast("x * 1 + 0").to_infix_string(), "x * 1 + 0");
+    }
+
+    #[test]
+    fn zero_and_one_after_folding() {
+        assert!(Ast::string_to_ast("1 - 1").is_zero());
+        assert!(Ast::string_to_ast("sin(0)").is_zero());
+        // Folding leaves variables alone, so multiplying by zero needs simplify first
+        assert!(!Ast::string_to_ast("0 * x").is_zero());
+        assert!(Ast::string_to_ast("0 * x").simplify().is_zero());
+        assert!(!Ast::string_to_ast("x - x").is_zero());
+        assert!(!Ast::string_to_ast("1").is_zero());
+        assert!(Ast::string_to_ast("2 / 2").is_one());
+        assert!(Ast::string_to_ast("exp(0) * cos(2 * pi)").is_one());
+        assert!(!Ast::string_to_ast("x / x").is_one());
+    }
+
+    #[test]
+    fn substitute_and_fold() {
+        assert_eq!(Ast::string_to_ast("x^2 + 2*x + 1").substitute_and_fold("x", 3.0).expr, Expr::Number(16.0));
+        for (s, expected) in [
+            ("x * y + sin(x - 3)", "3 * y + 0"),
+            ("y^2 + (1 + 2)", "y^2 + 3"),
+            // Failed operations stay, with their operands substituted
+            ("1 / (x - 3) + x", "1 / 0 + 3"),
+            ("(x - 4)! * 2", "(-1)! * 2"),
+        ] {
+            assert_eq!(Ast::string_to_ast(s).substitute_and_fold("x", 3.0).to_infix_string(), expected, "{}", s);
+        }
+    }
+
+    #[test]
+    fn normalize_folds_constants() {
+        assert_eq!(Ast::string_to_ast("(1 + 2) * sin(x^(4 / 2))").normalize(), Ast::string_to_ast("3 * sin(x^2)"));
+        // Values that cannot be represented as a number are left alone
+        assert_eq!(Ast::string_to_ast("1 / 0").normalize().to_infix_string(), "0^-1");
+        // Chains of numbers that overflow are kept rather than folded again and again
+        let overflow = Ast::string_to_ast("2 * 1e308");
+        assert_eq!(overflow.normalize().expr, Ast::string_to_ast("1e308 * 2").expr);
+        assert_eq!(overflow.optimize().eval(), f64::INFINITY);
+        assert!(!overflow.is_zero());
+        assert!(!Ast::string_to_ast("1e300 * 1e300").is_one());
+        let nan = Ast::string_to_ast("x + inf * 0");
+        assert_eq!(nan.normalize().expr, Ast::string_to_ast("0 * inf + x").expr);
+        assert_eq!(nan.to_symbolic_string(), "x");
+    }
+}
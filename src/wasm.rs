@@ -0,0 +1,42 @@
+use std::fmt::Display;
+use wasm_bindgen::prelude::*;
+use crate::ast::Ast;
+use crate::error::CalcError;
+
+// Errors cross into JavaScript as their message, which is thrown from the calling function
+fn to_js(e: impl Display) -> JsValue {
+    JsValue::from_str(&e.to_string())
+}
+
+/// Parse and evaluate the expression
+#[wasm_bindgen]
+pub fn evaluate(expr: &str) -> Result<f64, JsValue> {
+    let val: Result<f64, CalcError> = Ast::try_from_str(expr).map_err(CalcError::from)
+        .and_then(|ast| ast.try_eval().map_err(CalcError::from));
+    val.map_err(to_js)
+}
+
+/// Parse the expression and print it back in infix notation with only the parentheses it needs
+#[wasm_bindgen]
+pub fn to_infix(expr: &str) -> Result<String, JsValue> {
+    Ast::try_from_str(expr).map(|ast| ast.to_infix_string()).map_err(to_js)
+}
+
+#[cfg(all(test, target_arch = "wasm32"))]
+mod tests {
+    use wasm_bindgen_test::*;
+    use super::*;
+
+    #[wasm_bindgen_test]
+    fn evaluate_expressions() {
+        assert_eq!(evaluate("-2 + 4 * -(5^3 + 7 * 3!)"), Ok(-670.0));
+        assert_eq!(evaluate("1 +").map_err(|e| e.as_string()), Err(Some("Unexpected end of input".to_owned())));
+        assert_eq!(evaluate("x").map_err(|e| e.as_string()), Err(Some("Variable x has no value".to_owned())));
+    }
+
+    #[wasm_bindgen_test]
+    fn infix_round_trip() {
+        assert_eq!(to_infix("((1+2))*x^(2)"), Ok("(1 + 2) * x^2".to_owned()));
+        assert!(to_infix("(1 + 2").is_err());
+    }
+}